@@ -0,0 +1,21 @@
+// Runs a WebAssembly module compiled from Instant with Node.js: `node run_wasm.js file.wasm`.
+// Traps are reported like the runtime errors of the other backends, with exit code 1.
+const fs = require("fs");
+
+const lines = [];
+const imports = { env: { printInt: (value) => lines.push(value + "\n") } };
+
+WebAssembly.instantiate(fs.readFileSync(process.argv[2]), imports).then(({ instance }) => {
+    try {
+        instance.exports.main();
+    } catch (e) {
+        if (!(e instanceof WebAssembly.RuntimeError)) {
+            throw e;
+        }
+        process.stdout.write(lines.join(""));
+        process.stderr.write(`ERROR: ${e.message}\n`);
+        process.exitCode = 1;
+        return;
+    }
+    process.stdout.write(lines.join(""));
+});
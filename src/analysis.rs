@@ -0,0 +1,300 @@
+//! Static analysis of Instant programs for editors: diagnostics, definitions and references of
+//! variables, their values and formatting.
+//! A variable is identified by its name, as every assignment overwrites the same variable.
+
+use crate::{
+    ast::{Exp, Stmt},
+    interpreter::Interpreter,
+    parser::{self, SyntaxError},
+    Arithmetic,
+};
+use num_bigint::BigInt;
+use std::{collections::HashMap, io};
+
+/// Error found in the program, with the byte range it refers to.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub start: usize,
+    pub end: usize,
+    pub message: String,
+}
+
+/// Assignment to a variable or a use of it.
+#[derive(Debug)]
+struct Occurrence<'a> {
+    name: &'a str,
+    start: usize,
+    /// Index of the assignment defining the value, the occurrence itself for assignments.
+    /// [None] for uses of undeclared variables.
+    definition: Option<usize>,
+    /// Value assigned or read, [None] if the program fails before.
+    value: Option<BigInt>,
+}
+
+impl Occurrence<'_> {
+    fn range(&self) -> (usize, usize) {
+        (self.start, self.start + self.name.len())
+    }
+}
+
+/// Results of the analysis of a program.
+pub struct Analysis<'a> {
+    syntax_error: Option<SyntaxError<'a>>,
+    stmts: Vec<Stmt<'a>>,
+    /// Occurrences of variables in the order of the source.
+    occurrences: Vec<Occurrence<'a>>,
+}
+
+impl<'a> Analysis<'a> {
+    /// Analyzes the program, executing it with the reference interpreter to find the values of
+    /// the variables.
+    pub fn new(source: &'a str, arithmetic: Arithmetic) -> Self {
+        let stmts = match parser::parse_spanned(source, arithmetic) {
+            Ok(stmts) => stmts,
+            Err(e) => {
+                return Self {
+                    syntax_error: Some(e),
+                    stmts: Vec::new(),
+                    occurrences: Vec::new(),
+                }
+            }
+        };
+
+        let mut occurrences = Vec::new();
+        let mut definitions = HashMap::new();
+        let mut interpreter = Some(Interpreter::new(arithmetic));
+        for (start, stmt, _) in &stmts {
+            // Assignments start with the name of the variable, before the uses in the expression.
+            let (assignment, exp) = match stmt {
+                Stmt::Ass { var, exp } => {
+                    occurrences.push(Occurrence {
+                        name: var,
+                        start: *start,
+                        definition: Some(occurrences.len()),
+                        value: None,
+                    });
+                    (Some((*var, occurrences.len() - 1)), exp)
+                }
+                Stmt::Exp(exp) => (None, exp),
+            };
+
+            uses(exp, &mut |name, start| {
+                let value = interpreter
+                    .as_ref()
+                    .and_then(|interpreter| interpreter.variables().get(name))
+                    .cloned();
+                occurrences.push(Occurrence {
+                    name,
+                    start,
+                    definition: definitions.get(name).copied(),
+                    value,
+                });
+            });
+
+            // Values are unknown after a runtime error.
+            if let Some(executing) = &mut interpreter {
+                if executing.execute(stmt, &mut io::sink()).is_err() {
+                    interpreter = None;
+                }
+            }
+
+            if let Some((var, idx)) = assignment {
+                definitions.insert(var, idx);
+                occurrences[idx].value = interpreter
+                    .as_ref()
+                    .map(|interpreter| interpreter.variables()[var].clone());
+            }
+        }
+
+        Self {
+            syntax_error: None,
+            stmts: stmts.into_iter().map(|(_, stmt, _)| stmt).collect(),
+            occurrences,
+        }
+    }
+
+    /// Returns the syntax error, or the uses of undeclared variables.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        if let Some(e) = &self.syntax_error {
+            let (start, end) = e.range();
+            return vec![Diagnostic {
+                start,
+                end,
+                message: e.to_string(),
+            }];
+        }
+
+        self.occurrences
+            .iter()
+            .filter(|occurrence| occurrence.definition.is_none())
+            .map(|occurrence| {
+                let (start, end) = occurrence.range();
+                Diagnostic {
+                    start,
+                    end,
+                    message: format!("undeclared variable {}", occurrence.name),
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the occurrence of a variable containing the byte offset or ending at it.
+    fn occurrence(&self, offset: usize) -> Option<&Occurrence<'a>> {
+        let after = self
+            .occurrences
+            .partition_point(|occurrence| occurrence.start <= offset);
+        let occurrence = self.occurrences.get(after.checked_sub(1)?)?;
+        (offset <= occurrence.range().1).then_some(occurrence)
+    }
+
+    /// Returns the byte range of the assignment defining the value of the variable at the offset.
+    pub fn definition(&self, offset: usize) -> Option<(usize, usize)> {
+        let definition = self.occurrence(offset)?.definition?;
+        Some(self.occurrences[definition].range())
+    }
+
+    /// Returns the byte ranges of all occurrences of the variable at the offset, in order.
+    pub fn references(&self, offset: usize, include_assignments: bool) -> Vec<(usize, usize)> {
+        let Some(target) = self.occurrence(offset) else {
+            return Vec::new();
+        };
+
+        self.occurrences
+            .iter()
+            .enumerate()
+            .filter(|(idx, occurrence)| {
+                occurrence.name == target.name
+                    && (include_assignments || occurrence.definition != Some(*idx))
+            })
+            .map(|(_, occurrence)| occurrence.range())
+            .collect()
+    }
+
+    /// Returns the byte range of the variable at the offset with a description of its value.
+    pub fn hover(&self, offset: usize) -> Option<((usize, usize), String)> {
+        let occurrence = self.occurrence(offset)?;
+        let description = match (&occurrence.value, occurrence.definition) {
+            (Some(val), _) => format!("{} = {}", occurrence.name, val),
+            (None, Some(_)) => format!("{} is unknown, the program fails first", occurrence.name),
+            (None, None) => format!("undeclared variable {}", occurrence.name),
+        };
+
+        Some((occurrence.range(), description))
+    }
+
+    /// Returns the program with one statement per line and the minimal parentheses,
+    /// [None] if it does not parse.
+    pub fn format(&self) -> Option<String> {
+        if self.syntax_error.is_some() {
+            return None;
+        }
+
+        Some(
+            self.stmts
+                .iter()
+                .map(|stmt| format!("{}", stmt))
+                .collect::<Vec<_>>()
+                .join(";\n")
+                + "\n",
+        )
+    }
+}
+
+/// Calls `visit` with the name and the byte offset of every variable used in the expression,
+/// in the order of the source.
+fn uses<'a>(exp: &Exp<'a>, visit: &mut impl FnMut(&'a str, usize)) {
+    match exp {
+        Exp::Lit(_) | Exp::BigLit(_) => {}
+        Exp::Var { name, position } => visit(name, *position),
+        Exp::Bi { lhs, rhs, .. } => {
+            uses(lhs, visit);
+            uses(rhs, visit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SOURCE: &str = "a = 2;\nb = a * 3;\na = a + b\n;c + a;\nd = 1 / (b - 6); d";
+
+    fn text(range: (usize, usize)) -> &'static str {
+        &SOURCE[range.0..range.1]
+    }
+
+    #[test]
+    fn variables() {
+        let analysis = Analysis::new(SOURCE, Arithmetic::Wrapping);
+        assert_eq!(
+            analysis.diagnostics(),
+            [Diagnostic {
+                start: 29,
+                end: 30,
+                message: "undeclared variable c".into()
+            }]
+        );
+
+        // The use of a in the reassignment of a refers to the first assignment.
+        assert_eq!(analysis.definition(22), Some((0, 1)));
+        assert_eq!(analysis.definition(33), Some((18, 19)));
+        assert_eq!(analysis.definition(19), Some((18, 19)));
+        assert_eq!(analysis.definition(29), None);
+        assert_eq!(analysis.definition(3), None);
+
+        assert_eq!(
+            analysis.references(12, true),
+            [(0, 1), (11, 12), (18, 19), (22, 23), (33, 34)]
+        );
+        assert_eq!(analysis.references(0, false).len(), 3);
+        assert_eq!(text(analysis.references(7, true)[2]), "b");
+
+        assert_eq!(analysis.hover(23), Some(((22, 23), "a = 2".into())));
+        assert_eq!(analysis.hover(18), Some(((18, 19), "a = 8".into())));
+        assert_eq!(analysis.hover(33), Some(((33, 34), "a = 8".into())));
+        assert_eq!(
+            analysis.hover(30),
+            Some(((29, 30), "undeclared variable c".into()))
+        );
+        // The undeclared variable stops the execution.
+        assert_eq!(
+            analysis.hover(53),
+            Some(((53, 54), "d is unknown, the program fails first".into()))
+        );
+    }
+
+    #[test]
+    fn format() {
+        let analysis = Analysis::new(SOURCE, Arithmetic::Wrapping);
+        let formatted = analysis.format().unwrap();
+        assert_eq!(
+            formatted,
+            "a = 2;\nb = a * 3;\na = a + b;\nc + a;\nd = 1 / (b - 6);\nd\n"
+        );
+        assert_eq!(
+            Analysis::new(&formatted, Arithmetic::Wrapping).format(),
+            Some(formatted.clone())
+        );
+
+        let analysis = Analysis::new(
+            "((1 + 2) + 3) - (4 - (5 * 6)) / (7 / 8)",
+            Arithmetic::Wrapping,
+        );
+        assert_eq!(
+            analysis.format().unwrap(),
+            "((1 + 2) + 3) - (4 - 5 * 6) / (7 / 8)\n"
+        );
+
+        let analysis = Analysis::new("a = 1;\n2147483648", Arithmetic::Checked);
+        assert_eq!(analysis.format(), None);
+        assert_eq!(
+            analysis.diagnostics(),
+            [Diagnostic {
+                start: 7,
+                end: 17,
+                message: "literal 2147483648 out of bounds".into()
+            }]
+        );
+        assert_eq!(analysis.hover(0), None);
+    }
+}
@@ -1,10 +1,14 @@
+use std::fmt::{self, Display, Formatter};
+
+/// Literal out of the i32 range in a bounded arithmetic mode.
+#[derive(Debug)]
 pub struct LiteralOutOfBoundsError<'a> {
     pub literal: &'a str,
     pub position: usize,
 }
 
 /// Binary operation on i32 values.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Op {
     Add,
     Sub,
@@ -12,11 +16,31 @@ pub enum Op {
     Div,
 }
 
+impl Op {
+    /// Returns whether the order of the operands does not matter.
+    pub fn commutative(self) -> bool {
+        matches!(self, Self::Add | Self::Mul)
+    }
+
+    /// Returns the symbol of the operation in Instant source.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+        }
+    }
+}
+
 /// i32 expression.
 #[derive(Debug)]
 pub enum Exp<'a> {
     /// i32 literal.
     Lit(i32),
+    /// Decimal literal out of the i32 range.
+    /// Produced by the parser only in the [Arithmetic::Unbounded](crate::Arithmetic::Unbounded) mode.
+    BigLit(&'a str),
     /// Variable access.
     Var {
         /// Name of the variable.
@@ -32,6 +56,49 @@ pub enum Exp<'a> {
     },
 }
 
+impl Exp<'_> {
+    /// Returns the precedence level of the expression in the grammar, from 1 for additions
+    /// to 4 for expressions that never need parentheses.
+    fn level(&self) -> u8 {
+        match self {
+            Self::Bi { op: Op::Add, .. } => 1,
+            Self::Bi { op: Op::Sub, .. } => 2,
+            Self::Bi { .. } => 3,
+            Self::Lit(_) | Self::BigLit(_) | Self::Var { .. } => 4,
+        }
+    }
+}
+
+/// Writes the expression with the minimal parentheses, so that it parses back to the same tree.
+impl Display for Exp<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lit(val) => write!(f, "{}", val),
+            Self::BigLit(digits) => f.write_str(digits),
+            Self::Var { name, .. } => f.write_str(name),
+            Self::Bi { lhs, op, rhs } => {
+                // Addition is right-associative, the other operations are left-associative.
+                let (lhs_level, rhs_level) = match op {
+                    Op::Add => (2, 1),
+                    Op::Sub => (2, 3),
+                    Op::Mul | Op::Div => (3, 4),
+                };
+                let operand = |f: &mut Formatter<'_>, exp: &Exp, level| {
+                    if exp.level() < level {
+                        write!(f, "({})", exp)
+                    } else {
+                        write!(f, "{}", exp)
+                    }
+                };
+
+                operand(f, lhs, lhs_level)?;
+                write!(f, " {} ", op.symbol())?;
+                operand(f, rhs, rhs_level)
+            }
+        }
+    }
+}
+
 /// Statement.
 #[derive(Debug)]
 pub enum Stmt<'a> {
@@ -40,3 +107,19 @@ pub enum Stmt<'a> {
     /// Expression.
     Exp(Exp<'a>),
 }
+
+impl Display for Stmt<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ass { var, exp } => write!(f, "{} = {}", var, exp),
+            Self::Exp(exp) => write!(f, "{}", exp),
+        }
+    }
+}
+
+/// Returns the value of the given decimal literal, wrapped around to the i32 range.
+pub fn wrapped_literal(digits: &str) -> i32 {
+    digits.bytes().fold(0i32, |acc, digit| {
+        acc.wrapping_mul(10).wrapping_add((digit - b'0') as i32)
+    })
+}
@@ -0,0 +1,486 @@
+; Arbitrary-precision integer runtime for the LLVM backend.
+;
+; A number is stored as its sign (-1, 0 or 1), number of limbs and the limbs themselves.
+; Limbs are little-endian digits in base 10^9, the most significant limb is never zero.
+; Zero has no limbs. Values are immutable and never freed.
+
+%big = type { i32, i32, [0 x i32] }
+
+@big.fmt.first = internal constant [3 x i8] c"%d\00"
+@big.fmt.next = internal constant [5 x i8] c"%09d\00"
+@big.msg.div = internal constant [18 x i8] c"division by zero\0A\00"
+
+declare i8* @calloc(i64, i64)
+declare i32 @putchar(i32)
+declare i64 @write(i32, i8*, i64)
+declare void @exit(i32)
+
+; Allocates a positive number with the given number of zeroed limbs.
+define internal %big* @big.alloc(i32 %len) {
+	%limbs = zext i32 %len to i64
+	%size = add i64 %limbs, 2
+	%raw = call i8* @calloc(i64 %size, i64 4)
+	%r = bitcast i8* %raw to %big*
+	%sign = getelementptr %big, %big* %r, i32 0, i32 0
+	store i32 1, i32* %sign
+	%lenp = getelementptr %big, %big* %r, i32 0, i32 1
+	store i32 %len, i32* %lenp
+	ret %big* %r
+}
+
+define internal i32 @big.sign(%big* %a) {
+	%p = getelementptr %big, %big* %a, i32 0, i32 0
+	%sign = load i32, i32* %p
+	ret i32 %sign
+}
+
+define internal void @big.setsign(%big* %a, i32 %sign) {
+	%p = getelementptr %big, %big* %a, i32 0, i32 0
+	store i32 %sign, i32* %p
+	ret void
+}
+
+define internal i32 @big.len(%big* %a) {
+	%p = getelementptr %big, %big* %a, i32 0, i32 1
+	%len = load i32, i32* %p
+	ret i32 %len
+}
+
+define internal i32* @big.limb(%big* %a, i32 %i) {
+	%p = getelementptr %big, %big* %a, i32 0, i32 2, i32 %i
+	ret i32* %p
+}
+
+; Returns the limb at the given index or 0 if the index is out of bounds.
+define internal i32 @big.get(%big* %a, i32 %i) {
+entry:
+	%len = call i32 @big.len(%big* %a)
+	%inside = icmp ult i32 %i, %len
+	br i1 %inside, label %load, label %outside
+load:
+	%p = call i32* @big.limb(%big* %a, i32 %i)
+	%limb = load i32, i32* %p
+	ret i32 %limb
+outside:
+	ret i32 0
+}
+
+; Strips leading zero limbs, zero gets sign 0.
+define internal %big* @big.normalize(%big* %r) {
+entry:
+	%lenp = getelementptr %big, %big* %r, i32 0, i32 1
+	br label %loop
+loop:
+	%len = load i32, i32* %lenp
+	%empty = icmp eq i32 %len, 0
+	br i1 %empty, label %zero, label %check
+check:
+	%top = sub i32 %len, 1
+	%p = call i32* @big.limb(%big* %r, i32 %top)
+	%limb = load i32, i32* %p
+	%nonzero = icmp ne i32 %limb, 0
+	br i1 %nonzero, label %done, label %shrink
+shrink:
+	store i32 %top, i32* %lenp
+	br label %loop
+zero:
+	call void @big.setsign(%big* %r, i32 0)
+	br label %done
+done:
+	ret %big* %r
+}
+
+; Compares magnitudes, returns -1, 0 or 1.
+define internal i32 @big.cmp(%big* %a, %big* %b) {
+entry:
+	%la = call i32 @big.len(%big* %a)
+	%lb = call i32 @big.len(%big* %b)
+	%i = alloca i32
+	store i32 %la, i32* %i
+	%shorter = icmp ult i32 %la, %lb
+	br i1 %shorter, label %less, label %entry.2
+entry.2:
+	%longer = icmp ugt i32 %la, %lb
+	br i1 %longer, label %greater, label %loop
+loop:
+	%iv = load i32, i32* %i
+	%end = icmp eq i32 %iv, 0
+	br i1 %end, label %equal, label %body
+body:
+	%idx = sub i32 %iv, 1
+	store i32 %idx, i32* %i
+	%x = call i32 @big.get(%big* %a, i32 %idx)
+	%y = call i32 @big.get(%big* %b, i32 %idx)
+	%lt = icmp ult i32 %x, %y
+	br i1 %lt, label %less, label %body.2
+body.2:
+	%gt = icmp ugt i32 %x, %y
+	br i1 %gt, label %greater, label %loop
+less:
+	ret i32 -1
+greater:
+	ret i32 1
+equal:
+	ret i32 0
+}
+
+; Adds magnitudes.
+define internal %big* @big.addmag(%big* %a, %big* %b) {
+entry:
+	%la = call i32 @big.len(%big* %a)
+	%lb = call i32 @big.len(%big* %b)
+	%a.longer = icmp ugt i32 %la, %lb
+	%max = select i1 %a.longer, i32 %la, i32 %lb
+	%n = add i32 %max, 1
+	%r = call %big* @big.alloc(i32 %n)
+	%i = alloca i32
+	store i32 0, i32* %i
+	%carry = alloca i32
+	store i32 0, i32* %carry
+	br label %loop
+loop:
+	%iv = load i32, i32* %i
+	%end = icmp eq i32 %iv, %n
+	br i1 %end, label %done, label %body
+body:
+	%x = call i32 @big.get(%big* %a, i32 %iv)
+	%y = call i32 @big.get(%big* %b, i32 %iv)
+	%c = load i32, i32* %carry
+	%xy = add i32 %x, %y
+	%sum = add i32 %xy, %c
+	%over = icmp uge i32 %sum, 1000000000
+	%wrapped = sub i32 %sum, 1000000000
+	%limb = select i1 %over, i32 %wrapped, i32 %sum
+	%next.carry = zext i1 %over to i32
+	store i32 %next.carry, i32* %carry
+	%p = call i32* @big.limb(%big* %r, i32 %iv)
+	store i32 %limb, i32* %p
+	%next = add i32 %iv, 1
+	store i32 %next, i32* %i
+	br label %loop
+done:
+	%res = call %big* @big.normalize(%big* %r)
+	ret %big* %res
+}
+
+; Subtracts magnitudes, the magnitude of the first argument must not be smaller.
+define internal %big* @big.submag(%big* %a, %big* %b) {
+entry:
+	%n = call i32 @big.len(%big* %a)
+	%r = call %big* @big.alloc(i32 %n)
+	%i = alloca i32
+	store i32 0, i32* %i
+	%borrow = alloca i32
+	store i32 0, i32* %borrow
+	br label %loop
+loop:
+	%iv = load i32, i32* %i
+	%end = icmp eq i32 %iv, %n
+	br i1 %end, label %done, label %body
+body:
+	%x = call i32 @big.get(%big* %a, i32 %iv)
+	%y = call i32 @big.get(%big* %b, i32 %iv)
+	%c = load i32, i32* %borrow
+	%xy = sub i32 %x, %y
+	%diff = sub i32 %xy, %c
+	%under = icmp slt i32 %diff, 0
+	%wrapped = add i32 %diff, 1000000000
+	%limb = select i1 %under, i32 %wrapped, i32 %diff
+	%next.borrow = zext i1 %under to i32
+	store i32 %next.borrow, i32* %borrow
+	%p = call i32* @big.limb(%big* %r, i32 %iv)
+	store i32 %limb, i32* %p
+	%next = add i32 %iv, 1
+	store i32 %next, i32* %i
+	br label %loop
+done:
+	%res = call %big* @big.normalize(%big* %r)
+	ret %big* %res
+}
+
+; Adds the first argument and the magnitude of the second argument with the given sign.
+define internal %big* @big.addsigned(%big* %a, %big* %b, i32 %sb) {
+entry:
+	%sa = call i32 @big.sign(%big* %a)
+	%b.zero = icmp eq i32 %sb, 0
+	br i1 %b.zero, label %lhs, label %entry.2
+entry.2:
+	%a.zero = icmp eq i32 %sa, 0
+	%same = icmp eq i32 %sa, %sb
+	%add = or i1 %a.zero, %same
+	br i1 %add, label %sum, label %difference
+lhs:
+	ret %big* %a
+sum:
+	%r.sum = call %big* @big.addmag(%big* %a, %big* %b)
+	call void @big.setsign(%big* %r.sum, i32 %sb)
+	ret %big* %r.sum
+difference:
+	%cmp = call i32 @big.cmp(%big* %a, %big* %b)
+	%a.greater = icmp sgt i32 %cmp, 0
+	br i1 %a.greater, label %a.minus.b, label %difference.2
+difference.2:
+	%a.less = icmp slt i32 %cmp, 0
+	br i1 %a.less, label %b.minus.a, label %zero
+a.minus.b:
+	%r.ab = call %big* @big.submag(%big* %a, %big* %b)
+	call void @big.setsign(%big* %r.ab, i32 %sa)
+	ret %big* %r.ab
+b.minus.a:
+	%r.ba = call %big* @big.submag(%big* %b, %big* %a)
+	call void @big.setsign(%big* %r.ba, i32 %sb)
+	ret %big* %r.ba
+zero:
+	%r.zero = call %big* @big.alloc(i32 0)
+	%res = call %big* @big.normalize(%big* %r.zero)
+	ret %big* %res
+}
+
+define internal %big* @big.add(%big* %a, %big* %b) {
+	%sb = call i32 @big.sign(%big* %b)
+	%r = call %big* @big.addsigned(%big* %a, %big* %b, i32 %sb)
+	ret %big* %r
+}
+
+define internal %big* @big.sub(%big* %a, %big* %b) {
+	%sb = call i32 @big.sign(%big* %b)
+	%neg = sub i32 0, %sb
+	%r = call %big* @big.addsigned(%big* %a, %big* %b, i32 %neg)
+	ret %big* %r
+}
+
+; Multiplies the magnitude by a single limb.
+define internal %big* @big.mulsmall(%big* %a, i32 %d) {
+entry:
+	%la = call i32 @big.len(%big* %a)
+	%n = add i32 %la, 1
+	%r = call %big* @big.alloc(i32 %n)
+	%dw = zext i32 %d to i64
+	%i = alloca i32
+	store i32 0, i32* %i
+	%carry = alloca i64
+	store i64 0, i64* %carry
+	br label %loop
+loop:
+	%iv = load i32, i32* %i
+	%end = icmp eq i32 %iv, %n
+	br i1 %end, label %done, label %body
+body:
+	%x = call i32 @big.get(%big* %a, i32 %iv)
+	%xw = zext i32 %x to i64
+	%c = load i64, i64* %carry
+	%prod = mul i64 %xw, %dw
+	%t = add i64 %prod, %c
+	%limbw = urem i64 %t, 1000000000
+	%next.carry = udiv i64 %t, 1000000000
+	store i64 %next.carry, i64* %carry
+	%limb = trunc i64 %limbw to i32
+	%p = call i32* @big.limb(%big* %r, i32 %iv)
+	store i32 %limb, i32* %p
+	%next = add i32 %iv, 1
+	store i32 %next, i32* %i
+	br label %loop
+done:
+	%res = call %big* @big.normalize(%big* %r)
+	ret %big* %res
+}
+
+; Returns the magnitude multiplied by 10^9 with the given limb added.
+define internal %big* @big.shift(%big* %a, i32 %limb) {
+entry:
+	%la = call i32 @big.len(%big* %a)
+	%n = add i32 %la, 1
+	%r = call %big* @big.alloc(i32 %n)
+	%first = call i32* @big.limb(%big* %r, i32 0)
+	store i32 %limb, i32* %first
+	%i = alloca i32
+	store i32 0, i32* %i
+	br label %loop
+loop:
+	%iv = load i32, i32* %i
+	%end = icmp eq i32 %iv, %la
+	br i1 %end, label %done, label %body
+body:
+	%x = call i32 @big.get(%big* %a, i32 %iv)
+	%next = add i32 %iv, 1
+	%p = call i32* @big.limb(%big* %r, i32 %next)
+	store i32 %x, i32* %p
+	store i32 %next, i32* %i
+	br label %loop
+done:
+	%res = call %big* @big.normalize(%big* %r)
+	ret %big* %res
+}
+
+define internal %big* @big.mul(%big* %a, %big* %b) {
+entry:
+	%sa = call i32 @big.sign(%big* %a)
+	%sb = call i32 @big.sign(%big* %b)
+	%sign = mul i32 %sa, %sb
+	%la = call i32 @big.len(%big* %a)
+	%lb = call i32 @big.len(%big* %b)
+	%n = add i32 %la, %lb
+	%r = call %big* @big.alloc(i32 %n)
+	call void @big.setsign(%big* %r, i32 %sign)
+	%i = alloca i32
+	store i32 0, i32* %i
+	%j = alloca i32
+	%carry = alloca i64
+	br label %outer
+outer:
+	%iv = load i32, i32* %i
+	%outer.end = icmp eq i32 %iv, %la
+	br i1 %outer.end, label %done, label %outer.body
+outer.body:
+	%x = call i32 @big.get(%big* %a, i32 %iv)
+	%xw = zext i32 %x to i64
+	store i32 0, i32* %j
+	store i64 0, i64* %carry
+	br label %inner
+inner:
+	%jv = load i32, i32* %j
+	%inner.end = icmp eq i32 %jv, %lb
+	br i1 %inner.end, label %outer.next, label %inner.body
+inner.body:
+	%y = call i32 @big.get(%big* %b, i32 %jv)
+	%yw = zext i32 %y to i64
+	%k = add i32 %iv, %jv
+	%p = call i32* @big.limb(%big* %r, i32 %k)
+	%old = load i32, i32* %p
+	%oldw = zext i32 %old to i64
+	%c = load i64, i64* %carry
+	%prod = mul i64 %xw, %yw
+	%t.1 = add i64 %prod, %oldw
+	%t = add i64 %t.1, %c
+	%limbw = urem i64 %t, 1000000000
+	%next.carry = udiv i64 %t, 1000000000
+	store i64 %next.carry, i64* %carry
+	%limb = trunc i64 %limbw to i32
+	store i32 %limb, i32* %p
+	%jn = add i32 %jv, 1
+	store i32 %jn, i32* %j
+	br label %inner
+outer.next:
+	%top = add i32 %iv, %lb
+	%top.p = call i32* @big.limb(%big* %r, i32 %top)
+	%top.c = load i64, i64* %carry
+	%top.limb = trunc i64 %top.c to i32
+	store i32 %top.limb, i32* %top.p
+	%in = add i32 %iv, 1
+	store i32 %in, i32* %i
+	br label %outer
+done:
+	%res = call %big* @big.normalize(%big* %r)
+	ret %big* %res
+}
+
+; Divides with truncation towards zero, exits the program on division by zero.
+define internal %big* @big.div(%big* %a, %big* %b) {
+entry:
+	%sa = call i32 @big.sign(%big* %a)
+	%sb = call i32 @big.sign(%big* %b)
+	%b.zero = icmp eq i32 %sb, 0
+	br i1 %b.zero, label %error, label %entry.2
+error:
+	%msg = getelementptr [18 x i8], [18 x i8]* @big.msg.div, i32 0, i32 0
+	call i64 @write(i32 2, i8* %msg, i64 17)
+	call void @exit(i32 1)
+	unreachable
+entry.2:
+	%la = call i32 @big.len(%big* %a)
+	%q = call %big* @big.alloc(i32 %la)
+	%sign = mul i32 %sa, %sb
+	call void @big.setsign(%big* %q, i32 %sign)
+	%zero = call %big* @big.alloc(i32 0)
+	%rem.init = call %big* @big.normalize(%big* %zero)
+	%rem = alloca %big*
+	store %big* %rem.init, %big** %rem
+	%i = alloca i32
+	store i32 %la, i32* %i
+	%lo = alloca i32
+	%hi = alloca i32
+	br label %loop
+loop:
+	%iv = load i32, i32* %i
+	%end = icmp eq i32 %iv, 0
+	br i1 %end, label %done, label %body
+body:
+	%idx = sub i32 %iv, 1
+	store i32 %idx, i32* %i
+	%x = call i32 @big.get(%big* %a, i32 %idx)
+	%remv = load %big*, %big** %rem
+	%cur = call %big* @big.shift(%big* %remv, i32 %x)
+	store i32 0, i32* %lo
+	store i32 999999999, i32* %hi
+	br label %search
+search:
+	%lov = load i32, i32* %lo
+	%hiv = load i32, i32* %hi
+	%found = icmp eq i32 %lov, %hiv
+	br i1 %found, label %digit, label %search.body
+search.body:
+	%span = sub i32 %hiv, %lov
+	%half = add i32 %span, 1
+	%offset = udiv i32 %half, 2
+	%mid = add i32 %lov, %offset
+	%prod = call %big* @big.mulsmall(%big* %b, i32 %mid)
+	%cmp = call i32 @big.cmp(%big* %prod, %big* %cur)
+	%fits = icmp sle i32 %cmp, 0
+	br i1 %fits, label %search.up, label %search.down
+search.up:
+	store i32 %mid, i32* %lo
+	br label %search
+search.down:
+	%below = sub i32 %mid, 1
+	store i32 %below, i32* %hi
+	br label %search
+digit:
+	%p = call i32* @big.limb(%big* %q, i32 %idx)
+	store i32 %lov, i32* %p
+	%sub = call %big* @big.mulsmall(%big* %b, i32 %lov)
+	%next.rem = call %big* @big.submag(%big* %cur, %big* %sub)
+	store %big* %next.rem, %big** %rem
+	br label %loop
+done:
+	%res = call %big* @big.normalize(%big* %q)
+	ret %big* %res
+}
+
+define internal void @big.print(%big* %a) {
+entry:
+	%sign = call i32 @big.sign(%big* %a)
+	%len = call i32 @big.len(%big* %a)
+	%first = getelementptr [3 x i8], [3 x i8]* @big.fmt.first, i32 0, i32 0
+	%next = getelementptr [5 x i8], [5 x i8]* @big.fmt.next, i32 0, i32 0
+	%i = alloca i32
+	%zero = icmp eq i32 %sign, 0
+	br i1 %zero, label %print.zero, label %entry.2
+print.zero:
+	call i32 (i8*, ...) @printf(i8* %first, i32 0)
+	br label %done
+entry.2:
+	%negative = icmp slt i32 %sign, 0
+	br i1 %negative, label %minus, label %top
+minus:
+	call i32 @putchar(i32 45)
+	br label %top
+top:
+	%top.idx = sub i32 %len, 1
+	store i32 %top.idx, i32* %i
+	%top.limb = call i32 @big.get(%big* %a, i32 %top.idx)
+	call i32 (i8*, ...) @printf(i8* %first, i32 %top.limb)
+	br label %loop
+loop:
+	%iv = load i32, i32* %i
+	%end = icmp eq i32 %iv, 0
+	br i1 %end, label %done, label %body
+body:
+	%idx = sub i32 %iv, 1
+	store i32 %idx, i32* %i
+	%limb = call i32 @big.get(%big* %a, i32 %idx)
+	call i32 (i8*, ...) @printf(i8* %next, i32 %limb)
+	br label %loop
+done:
+	call i32 @putchar(i32 10)
+	ret void
+}
@@ -0,0 +1,723 @@
+use super::{
+    stack::{Layout, Storage},
+    Backend,
+};
+use crate::{
+    ast::{self, Op},
+    ir::{Instruction as IrInstruction, MachineOp, Operand, Program},
+    Arithmetic,
+};
+use num_bigint::BigInt;
+use std::{
+    cmp::{self, Ordering},
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+};
+
+/// First bytes of every serialized [Bytecode].
+pub const MAGIC: [u8; 4] = *b"INSB";
+
+/// Version of the serialized [Bytecode] format.
+pub const VERSION: u16 = 1;
+
+/// Backend for generating [Bytecode] of the stack virtual machine in [crate::vm].
+/// Operands are computed in the Sethi–Ullman order and values used many times are kept in local
+/// variables, in the same way as in the [JasminBackend](super::jasmin::JasminBackend).
+#[derive(Default)]
+pub struct BytecodeBackend;
+
+impl Backend for BytecodeBackend {
+    type Representation = Bytecode;
+
+    fn process(&self, program: &Program) -> Bytecode {
+        let mut builder = BytecodeBuilder {
+            program,
+            layout: Layout::new(program),
+            constants: Default::default(),
+            constant_indices: Default::default(),
+            stack_depth: 0,
+            instructions: Default::default(),
+        };
+
+        for instruction in &program.instructions {
+            builder.add_instruction(instruction);
+        }
+
+        builder.build()
+    }
+}
+
+/// Instruction of the stack virtual machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// Pushes the constant with the given index in the constant pool.
+    Const(usize),
+    /// Pushes the value of the local variable with the given index.
+    Load(usize),
+    /// Pops a value into the local variable with the given index.
+    Store(usize),
+    /// Pops the right and the left operand and pushes the result of the operation.
+    Bin(Op),
+    /// Pops the right and the left operand and pushes the result of the operation.
+    /// Appears only in bounded arithmetic modes.
+    Machine(MachineOp),
+    Dup,
+    Swap,
+    Pop,
+    /// Pops a value and prints it in a separate line.
+    Print,
+}
+
+impl Instruction {
+    fn opcode(self) -> u8 {
+        match self {
+            Self::Const(_) => 0x01,
+            Self::Load(_) => 0x02,
+            Self::Store(_) => 0x03,
+            Self::Bin(Op::Add) => 0x10,
+            Self::Bin(Op::Sub) => 0x11,
+            Self::Bin(Op::Mul) => 0x12,
+            Self::Bin(Op::Div) => 0x13,
+            Self::Machine(MachineOp::Shl) => 0x20,
+            Self::Machine(MachineOp::Sar) => 0x21,
+            Self::Machine(MachineOp::Shr) => 0x22,
+            Self::Machine(MachineOp::MulHigh) => 0x23,
+            Self::Dup => 0x30,
+            Self::Swap => 0x31,
+            Self::Pop => 0x32,
+            Self::Print => 0x40,
+        }
+    }
+
+    /// Returns the change of the stack depth caused by this instruction.
+    fn stack_effect(self) -> isize {
+        match self {
+            Self::Const(_) | Self::Load(_) | Self::Dup => 1,
+            Self::Swap => 0,
+            Self::Store(_) | Self::Bin(_) | Self::Machine(_) | Self::Pop | Self::Print => -1,
+        }
+    }
+
+    /// Returns the number of values this instruction pops.
+    fn inputs(self) -> usize {
+        match self {
+            Self::Const(_) | Self::Load(_) => 0,
+            Self::Store(_) | Self::Dup | Self::Pop | Self::Print => 1,
+            Self::Bin(_) | Self::Machine(_) | Self::Swap => 2,
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Const(idx) => write!(f, "const #{}", idx),
+            Self::Load(slot) => write!(f, "load {}", slot),
+            Self::Store(slot) => write!(f, "store {}", slot),
+            Self::Bin(op) => write!(f, "{}", op),
+            Self::Machine(op) => write!(f, "{}", op),
+            Self::Dup => f.write_str("dup"),
+            Self::Swap => f.write_str("swap"),
+            Self::Pop => f.write_str("pop"),
+            Self::Print => f.write_str("print"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ProcessedExp {
+    instructions: Vec<Instruction>,
+    depth: usize,
+}
+
+struct BytecodeBuilder<'p> {
+    program: &'p Program,
+    layout: Layout,
+    constants: Vec<BigInt>,
+    constant_indices: HashMap<BigInt, usize>,
+    stack_depth: usize,
+    instructions: Vec<Instruction>,
+}
+
+impl<'p> BytecodeBuilder<'p> {
+    /// Returns the instruction pushing the constant, adding it to the pool if needed.
+    fn constant(&mut self, val: BigInt) -> Instruction {
+        let next = self.constants.len();
+        let idx = *self.constant_indices.entry(val.clone()).or_insert(next);
+        if idx == next {
+            self.constants.push(val);
+        }
+
+        Instruction::Const(idx)
+    }
+
+    fn process_operand(&mut self, operand: Operand) -> ProcessedExp {
+        let instruction = match operand {
+            Operand::Const(val) => self.constant(val.into()),
+            Operand::BigConst(idx) if self.program.arithmetic == Arithmetic::Unbounded => {
+                let val = self.program.big_constants[idx]
+                    .parse()
+                    .expect("big constants are valid integers");
+                self.constant(val)
+            }
+            Operand::BigConst(idx) => {
+                let val = ast::wrapped_literal(&self.program.big_constants[idx]);
+                self.constant(val.into())
+            }
+            Operand::Value(value) => match self.layout.storage[value.0] {
+                Storage::Local(slot) => Instruction::Load(slot),
+                Storage::Inline | Storage::Dup | Storage::Discard => {
+                    let definition = self.layout.definitions[value.0];
+                    return self.process_definition(&self.program.instructions[definition]);
+                }
+            },
+        };
+
+        ProcessedExp {
+            instructions: vec![instruction],
+            depth: 1,
+        }
+    }
+
+    fn process_definition(&mut self, instruction: &IrInstruction) -> ProcessedExp {
+        match instruction {
+            IrInstruction::Bin { lhs, op, rhs, .. } => {
+                self.process_bin(*lhs, *rhs, Instruction::Bin(*op), op.commutative())
+            }
+            IrInstruction::Machine { lhs, op, rhs, .. } => {
+                assert!(
+                    self.program.arithmetic != Arithmetic::Unbounded,
+                    "machine operations are not supported in unbounded arithmetic"
+                );
+                let commutative = *op == MachineOp::MulHigh;
+                self.process_bin(*lhs, *rhs, Instruction::Machine(*op), commutative)
+            }
+            IrInstruction::Print(_) => unreachable!("values are never defined by prints"),
+        }
+    }
+
+    /// Computes both operands in the Sethi–Ullman order and applies the given instruction.
+    fn process_bin(
+        &mut self,
+        lhs: Operand,
+        rhs: Operand,
+        instruction: Instruction,
+        commutative: bool,
+    ) -> ProcessedExp {
+        let duplicated = match (lhs, lhs == rhs) {
+            (Operand::Value(value), true) => self.layout.storage[value.0] == Storage::Dup,
+            _ => false,
+        };
+        if duplicated {
+            let mut exp = self.process_operand(lhs);
+            exp.instructions.push(Instruction::Dup);
+            exp.instructions.push(instruction);
+
+            return ProcessedExp {
+                instructions: exp.instructions,
+                depth: cmp::max(exp.depth, 2),
+            };
+        }
+
+        let mut lhs = self.process_operand(lhs);
+        let mut rhs = self.process_operand(rhs);
+
+        let (instructions, depth) = match rhs.depth.cmp(&lhs.depth) {
+            Ordering::Equal => {
+                lhs.instructions.extend(rhs.instructions);
+                lhs.instructions.push(instruction);
+
+                (lhs.instructions, rhs.depth + 1)
+            }
+            Ordering::Greater => {
+                rhs.instructions.extend(lhs.instructions);
+                if !commutative {
+                    rhs.instructions.push(Instruction::Swap);
+                }
+                rhs.instructions.push(instruction);
+
+                (rhs.instructions, rhs.depth)
+            }
+            Ordering::Less => {
+                lhs.instructions.extend(rhs.instructions);
+                lhs.instructions.push(instruction);
+
+                (lhs.instructions, lhs.depth)
+            }
+        };
+
+        ProcessedExp {
+            instructions,
+            depth,
+        }
+    }
+
+    fn add_instruction(&mut self, instruction: &IrInstruction) {
+        let (exp, last) = match instruction {
+            IrInstruction::Print(operand) => (self.process_operand(*operand), Instruction::Print),
+            IrInstruction::Bin { dst, .. } | IrInstruction::Machine { dst, .. } => {
+                let last = match self.layout.storage[dst.0] {
+                    Storage::Inline | Storage::Dup => return,
+                    Storage::Local(slot) => Instruction::Store(slot),
+                    Storage::Discard => Instruction::Pop,
+                };
+
+                (self.process_definition(instruction), last)
+            }
+        };
+
+        self.instructions.extend(exp.instructions);
+        self.instructions.push(last);
+        self.stack_depth = cmp::max(self.stack_depth, exp.depth);
+    }
+
+    fn build(self) -> Bytecode {
+        Bytecode {
+            arithmetic: self.program.arithmetic,
+            constants: self.constants,
+            locals: self.layout.locals,
+            stack_limit: self.stack_depth,
+            instructions: self.instructions,
+        }
+    }
+}
+
+/// Program of the stack virtual machine, with its constant pool.
+/// [Bytecode::encode] serializes it and [Display] shows it in a readable form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bytecode {
+    pub(crate) arithmetic: Arithmetic,
+    pub(crate) constants: Vec<BigInt>,
+    pub(crate) locals: usize,
+    pub(crate) stack_limit: usize,
+    pub(crate) instructions: Vec<Instruction>,
+}
+
+/// Error in the serialized [Bytecode].
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Input does not start with [MAGIC].
+    Magic,
+    /// Unsupported version of the format.
+    Version(u16),
+    /// Unknown arithmetic mode.
+    Arithmetic(u8),
+    /// Input ends in the middle of the bytecode.
+    UnexpectedEnd,
+    /// Input continues after the last instruction.
+    TrailingBytes,
+    /// Unknown opcode of the instruction with the given index.
+    Opcode { index: usize, opcode: u8 },
+    /// Instruction with the given index cannot be executed.
+    Invalid { index: usize, reason: &'static str },
+    /// Constant with the given index does not fit in 32 bits in a bounded arithmetic mode,
+    /// pushed first by the instruction with the given index, if any.
+    ConstantOutOfRange {
+        instruction: Option<usize>,
+        constant: usize,
+    },
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Magic => f.write_str("not an Instant bytecode file"),
+            Self::Version(version) => write!(f, "unsupported bytecode version {}", version),
+            Self::Arithmetic(mode) => write!(f, "unknown arithmetic mode {}", mode),
+            Self::UnexpectedEnd => f.write_str("unexpected end of bytecode"),
+            Self::TrailingBytes => f.write_str("trailing bytes after the bytecode"),
+            Self::Opcode { index, opcode } => {
+                write!(f, "unknown opcode {:#04x} of instruction {}", opcode, index)
+            }
+            Self::Invalid { index, reason } => write!(f, "instruction {} {}", index, reason),
+            Self::ConstantOutOfRange {
+                instruction: Some(instruction),
+                constant,
+            } => write!(
+                f,
+                "constant #{} pushed by instruction {} is out of the 32-bit range",
+                constant, instruction
+            ),
+            Self::ConstantOutOfRange {
+                instruction: None,
+                constant,
+            } => write!(f, "constant #{} is out of the 32-bit range", constant),
+        }
+    }
+}
+
+/// Appends the unsigned LEB128 encoding of the value.
+pub(super) fn write_varint(bytes: &mut Vec<u8>, mut val: u64) {
+    while val >= 0x80 {
+        bytes.push(val as u8 | 0x80);
+        val >>= 7;
+    }
+    bytes.push(val as u8);
+}
+
+/// Reader of the serialized [Bytecode].
+struct Reader<'b> {
+    bytes: &'b [u8],
+}
+
+impl<'b> Reader<'b> {
+    fn bytes(&mut self, len: usize) -> Result<&'b [u8], DecodeError> {
+        if self.bytes.len() < len {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (bytes, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        self.bytes(1).map(|bytes| bytes[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, DecodeError> {
+        let mut val = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            val |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(val);
+            }
+        }
+
+        Err(DecodeError::UnexpectedEnd)
+    }
+
+    fn size(&mut self) -> Result<usize, DecodeError> {
+        usize::try_from(self.varint()?).map_err(|_| DecodeError::UnexpectedEnd)
+    }
+}
+
+impl Bytecode {
+    /// Returns the arithmetic mode of the program.
+    pub fn arithmetic(&self) -> Arithmetic {
+        self.arithmetic
+    }
+
+    /// Serializes the bytecode. The format is:
+    /// - [MAGIC] and [VERSION] as a little-endian u16,
+    /// - arithmetic mode as a byte: 0 wrapping, 1 checked, 2 unbounded,
+    /// - number of constants, then each of them as the length and the bytes of its
+    ///   little-endian two's complement representation,
+    /// - number of local variables and the stack limit,
+    /// - number of instructions, then each of them as an opcode byte, followed by the index
+    ///   for [Instruction::Const], [Instruction::Load] and [Instruction::Store].
+    ///
+    /// All numbers except the version are unsigned LEB128.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.push(match self.arithmetic {
+            Arithmetic::Wrapping => 0,
+            Arithmetic::Checked => 1,
+            Arithmetic::Unbounded => 2,
+        });
+
+        write_varint(&mut bytes, self.constants.len() as u64);
+        for constant in &self.constants {
+            let constant = constant.to_signed_bytes_le();
+            write_varint(&mut bytes, constant.len() as u64);
+            bytes.extend(constant);
+        }
+
+        write_varint(&mut bytes, self.locals as u64);
+        write_varint(&mut bytes, self.stack_limit as u64);
+
+        write_varint(&mut bytes, self.instructions.len() as u64);
+        for instruction in &self.instructions {
+            bytes.push(instruction.opcode());
+            if let Instruction::Const(idx) | Instruction::Load(idx) | Instruction::Store(idx) =
+                instruction
+            {
+                write_varint(&mut bytes, *idx as u64);
+            }
+        }
+
+        bytes
+    }
+
+    /// Deserializes the bytecode, see [Bytecode::encode] for the format.
+    /// The instructions are verified, so that they never access missing constants, local variables
+    /// or stack values and never exceed the stack limit.
+    pub fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader { bytes };
+        if reader.bytes(MAGIC.len()) != Ok(&MAGIC) {
+            return Err(DecodeError::Magic);
+        }
+        let version = u16::from_le_bytes([reader.byte()?, reader.byte()?]);
+        if version != VERSION {
+            return Err(DecodeError::Version(version));
+        }
+        let arithmetic = match reader.byte()? {
+            0 => Arithmetic::Wrapping,
+            1 => Arithmetic::Checked,
+            2 => Arithmetic::Unbounded,
+            other => return Err(DecodeError::Arithmetic(other)),
+        };
+
+        let mut constants = Vec::new();
+        for _ in 0..reader.varint()? {
+            let len = reader.size()?;
+            constants.push(BigInt::from_signed_bytes_le(reader.bytes(len)?));
+        }
+
+        let locals = reader.size()?;
+        let stack_limit = reader.size()?;
+
+        let count = reader.varint()?;
+        let mut instructions = Vec::new();
+        for index in 0..count as usize {
+            let opcode = reader.byte()?;
+            let instruction = match opcode {
+                0x01 => Instruction::Const(reader.size()?),
+                0x02 => Instruction::Load(reader.size()?),
+                0x03 => Instruction::Store(reader.size()?),
+                0x10 => Instruction::Bin(Op::Add),
+                0x11 => Instruction::Bin(Op::Sub),
+                0x12 => Instruction::Bin(Op::Mul),
+                0x13 => Instruction::Bin(Op::Div),
+                0x20 => Instruction::Machine(MachineOp::Shl),
+                0x21 => Instruction::Machine(MachineOp::Sar),
+                0x22 => Instruction::Machine(MachineOp::Shr),
+                0x23 => Instruction::Machine(MachineOp::MulHigh),
+                0x30 => Instruction::Dup,
+                0x31 => Instruction::Swap,
+                0x32 => Instruction::Pop,
+                0x40 => Instruction::Print,
+                opcode => return Err(DecodeError::Opcode { index, opcode }),
+            };
+            instructions.push(instruction);
+        }
+        if !reader.bytes.is_empty() {
+            return Err(DecodeError::TrailingBytes);
+        }
+
+        let bytecode = Self {
+            arithmetic,
+            constants,
+            locals,
+            stack_limit,
+            instructions,
+        };
+        bytecode.verify()?;
+
+        Ok(bytecode)
+    }
+
+    fn verify(&self) -> Result<(), DecodeError> {
+        let bounded = self.arithmetic != Arithmetic::Unbounded;
+        if bounded {
+            if let Some(constant) = self
+                .constants
+                .iter()
+                .position(|constant| i32::try_from(constant).is_err())
+            {
+                let instruction = self
+                    .instructions
+                    .iter()
+                    .position(|instruction| *instruction == Instruction::Const(constant));
+                return Err(DecodeError::ConstantOutOfRange {
+                    instruction,
+                    constant,
+                });
+            }
+        }
+        // Both limits are preallocated by the virtual machine.
+        if self.locals > self.instructions.len() + 1 || self.stack_limit > self.instructions.len() {
+            return Err(DecodeError::Invalid {
+                index: self.instructions.len(),
+                reason: "is preceded by too few instructions for the limits",
+            });
+        }
+
+        let mut depth = 0;
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            let invalid = |reason| Err(DecodeError::Invalid { index, reason });
+            match instruction {
+                Instruction::Const(idx) if *idx >= self.constants.len() => {
+                    return invalid("pushes a missing constant")
+                }
+                Instruction::Load(slot) | Instruction::Store(slot) if *slot >= self.locals => {
+                    return invalid("accesses a missing local variable")
+                }
+                Instruction::Machine(_) if !bounded => {
+                    return invalid("is a machine operation in unbounded arithmetic")
+                }
+                _ => {}
+            }
+            if depth < instruction.inputs() {
+                return invalid("pops from an empty stack");
+            }
+            depth = (depth as isize + instruction.stack_effect()) as usize;
+            if depth > self.stack_limit {
+                return invalid("exceeds the stack limit");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Bytecode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "; version: {}", VERSION)?;
+        writeln!(f, "; arithmetic: {}", self.arithmetic)?;
+        writeln!(f, "; locals: {}", self.locals)?;
+        writeln!(f, "; stack: {}", self.stack_limit)?;
+        for (idx, constant) in self.constants.iter().enumerate() {
+            writeln!(f, "#{} = {}", idx, constant)?;
+        }
+        for instruction in &self.instructions {
+            writeln!(f, "{}", instruction)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser;
+
+    fn bytecode(source: &str, arithmetic: Arithmetic) -> Bytecode {
+        let stmts = parser::parse(source, arithmetic).unwrap();
+        BytecodeBackend.process(&Program::lower(&stmts, arithmetic).unwrap())
+    }
+
+    #[test]
+    fn generation() {
+        let bytecode = bytecode("a = 7 / 2; 7 - a * a; a", Arithmetic::Wrapping);
+
+        assert_eq!(bytecode.constants, [7.into(), 2.into()]);
+        assert_eq!(
+            bytecode.instructions,
+            [
+                Instruction::Const(0),
+                Instruction::Const(1),
+                Instruction::Bin(Op::Div),
+                Instruction::Store(0),
+                Instruction::Load(0),
+                Instruction::Load(0),
+                Instruction::Bin(Op::Mul),
+                Instruction::Const(0),
+                Instruction::Swap,
+                Instruction::Bin(Op::Sub),
+                Instruction::Print,
+                Instruction::Load(0),
+                Instruction::Print,
+            ]
+        );
+        assert_eq!(bytecode.stack_limit, 2);
+        assert_eq!(bytecode.locals, 1);
+        assert_eq!(
+            bytecode.to_string().lines().take(7).collect::<Vec<_>>(),
+            [
+                "; version: 1",
+                "; arithmetic: wrapping",
+                "; locals: 1",
+                "; stack: 2",
+                "#0 = 7",
+                "#1 = 2",
+                "const #0",
+            ]
+        );
+    }
+
+    #[test]
+    fn serialization() {
+        // Literals parsed in unbounded mode wrap around when lowered, here to -300.
+        let stmts = parser::parse(
+            "a = 100000000000000000000 * 4294966996; a + a",
+            Arithmetic::Unbounded,
+        )
+        .unwrap();
+        let bytecode =
+            |arithmetic| BytecodeBackend.process(&Program::lower(&stmts, arithmetic).unwrap());
+        for arithmetic in [Arithmetic::Wrapping, Arithmetic::Unbounded] {
+            let bytecode = bytecode(arithmetic);
+            assert_eq!(Bytecode::decode(&bytecode.encode()), Ok(bytecode));
+        }
+        assert!(bytecode(Arithmetic::Wrapping)
+            .constants
+            .contains(&(-300).into()));
+
+        let bytes = bytecode(Arithmetic::Unbounded).encode();
+        assert_eq!(
+            bytes[..18],
+            [
+                b'I', b'N', b'S', b'B', 1, 0, 2, // header
+                2, 9, 0, 0, 0x10, 0x63, 0x2d, 0x5e, 0xc7, 0x6b, 0x05, // 10^20
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_bytecode() {
+        let valid = bytecode("1", Arithmetic::Wrapping).encode();
+        let header = &valid[..7];
+        let with = |rest: &[u8]| [header, rest].concat();
+
+        assert_eq!(Bytecode::decode(b"INS"), Err(DecodeError::Magic));
+        assert_eq!(
+            Bytecode::decode(b"INSB\x02\x00"),
+            Err(DecodeError::Version(2))
+        );
+        assert_eq!(
+            Bytecode::decode(&valid[..valid.len() - 1]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert_eq!(
+            Bytecode::decode(&[&valid[..], &[0]].concat()),
+            Err(DecodeError::TrailingBytes)
+        );
+        assert_eq!(
+            Bytecode::decode(&with(&[0, 1, 1, 1, 0x50])),
+            Err(DecodeError::Opcode {
+                index: 0,
+                opcode: 0x50
+            })
+        );
+        assert_eq!(
+            Bytecode::decode(&with(&[0, 1, 1, 1, 0x01, 0])),
+            Err(DecodeError::Invalid {
+                index: 0,
+                reason: "pushes a missing constant"
+            })
+        );
+        // The constant 2^32 in the bounded mode of the header.
+        assert_eq!(
+            Bytecode::decode(&with(&[1, 5, 0, 0, 0, 0, 1, 0, 1, 1, 0x01, 0])),
+            Err(DecodeError::ConstantOutOfRange {
+                instruction: Some(0),
+                constant: 0
+            })
+        );
+        assert_eq!(
+            Bytecode::decode(&with(&[1, 5, 0, 0, 0, 0, 1, 0, 0, 0])),
+            Err(DecodeError::ConstantOutOfRange {
+                instruction: None,
+                constant: 0
+            })
+        );
+        assert_eq!(
+            Bytecode::decode(&with(&[0, 1, 1, 1, 0x40])),
+            Err(DecodeError::Invalid {
+                index: 0,
+                reason: "pops from an empty stack"
+            })
+        );
+        assert_eq!(
+            Bytecode::decode(&with(&[1, 1, 1, 0, 1, 2, 0x01, 0, 0x30])),
+            Err(DecodeError::Invalid {
+                index: 1,
+                reason: "exceeds the stack limit"
+            })
+        );
+    }
+}
@@ -0,0 +1,434 @@
+use super::Backend;
+use crate::{
+    ast::{self, Op},
+    ir::{Instruction as IrInstruction, MachineOp, Operand, Program},
+    Arithmetic,
+};
+use std::fmt::{self, Display, Formatter};
+
+/// Backend for generating portable C99 from an Instant program.
+/// Every value is a `const int32_t` variable named after its register, like in LLVM IR.
+///
+/// Signed overflow is undefined in C, so wrapping operations are computed on `unsigned long`,
+/// which is at least 32 bits wide and never promoted to a signed type, and converted back
+/// by the `wrap` helper without relying on implementation-defined conversions.
+/// Checked operations are computed on `long long` and compared with the bounds of `int32_t`.
+/// Divisions are guarded explicitly: by zero they print an error and exit the program
+/// with status 1, and so do overflows in [Arithmetic::Checked] mode.
+/// [Arithmetic::Unbounded] mode is not supported.
+#[derive(Default)]
+pub struct CBackend;
+
+impl Backend for CBackend {
+    type Representation = C;
+
+    fn process(&self, program: &Program) -> C {
+        assert!(
+            program.arithmetic != Arithmetic::Unbounded,
+            "unbounded arithmetic is not supported by the C backend"
+        );
+
+        let mut statements = Vec::new();
+        let mut divisions = false;
+        let checked = program.arithmetic == Arithmetic::Checked;
+        for instruction in &program.instructions {
+            let location = |operand| match operand {
+                Operand::Value(value) => Location::Variable(value.0),
+                Operand::Const(val) => Location::Constant(val),
+                Operand::BigConst(idx) => {
+                    Location::Constant(ast::wrapped_literal(&program.big_constants[idx]))
+                }
+            };
+
+            statements.push(match *instruction {
+                IrInstruction::Bin {
+                    dst,
+                    op: Op::Div,
+                    lhs,
+                    rhs,
+                } => {
+                    let rhs = location(rhs);
+                    let zero = !matches!(rhs, Location::Constant(val) if val != 0);
+                    divisions |= zero;
+                    Statement::Div {
+                        lhs: location(lhs),
+                        rhs,
+                        dst: dst.0,
+                        zero,
+                        minus_one: !matches!(rhs, Location::Constant(val) if val != -1),
+                        checked,
+                    }
+                }
+                IrInstruction::Bin { dst, op, lhs, rhs } => Statement::Bin {
+                    lhs: location(lhs),
+                    op,
+                    rhs: location(rhs),
+                    dst: dst.0,
+                    checked,
+                },
+                IrInstruction::Machine { dst, op, lhs, rhs } => Statement::Machine {
+                    lhs: location(lhs),
+                    op,
+                    rhs: location(rhs),
+                    dst: dst.0,
+                },
+                IrInstruction::Print(operand) => Statement::Print(location(operand)),
+            });
+        }
+
+        C {
+            statements,
+            divisions,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Location {
+    Variable(usize),
+    Constant(i32),
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Variable(num) => write!(f, "t{}", num),
+            // The literal 2147483648 does not fit in int, so it cannot be negated.
+            Self::Constant(i32::MIN) => f.write_str("INT32_MIN"),
+            Self::Constant(val) => write!(f, "{}", val),
+        }
+    }
+}
+
+enum Statement {
+    Print(Location),
+    /// Addition, subtraction or multiplication, calling `overflow` if `checked`
+    /// and the result does not fit in 32 bits.
+    Bin {
+        lhs: Location,
+        op: Op,
+        rhs: Location,
+        dst: usize,
+        checked: bool,
+    },
+    /// Division calling `divzero` if `zero` and the divisor is zero.
+    /// If `minus_one` and the divisor is -1, the dividend is negated, calling `overflow`
+    /// if `checked` and it is the minimum, since the division would be undefined.
+    Div {
+        lhs: Location,
+        rhs: Location,
+        dst: usize,
+        zero: bool,
+        minus_one: bool,
+        checked: bool,
+    },
+    Machine {
+        lhs: Location,
+        op: MachineOp,
+        rhs: Location,
+        dst: usize,
+    },
+}
+
+impl Display for Statement {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Print(loc) => write!(f, "printInt({});", loc),
+            Self::Bin {
+                lhs,
+                op,
+                rhs,
+                dst,
+                checked: false,
+            } => write!(
+                f,
+                "const int32_t t{} = wrap((unsigned long){} {} (unsigned long){});",
+                dst,
+                lhs,
+                op.symbol(),
+                rhs
+            ),
+            Self::Bin {
+                lhs,
+                op,
+                rhs,
+                dst,
+                checked: true,
+            } => {
+                writeln!(
+                    f,
+                    "const long long t{}w = (long long){} {} {};",
+                    dst,
+                    lhs,
+                    op.symbol(),
+                    rhs
+                )?;
+                writeln!(
+                    f,
+                    "\tif (t{0}w < INT32_MIN || t{0}w > INT32_MAX) overflow();",
+                    dst
+                )?;
+                write!(f, "\tconst int32_t t{0} = (int32_t)t{0}w;", dst)
+            }
+            Self::Div {
+                lhs,
+                rhs,
+                dst,
+                zero,
+                minus_one,
+                checked,
+            } => {
+                if *zero {
+                    writeln!(f, "if ({} == 0) divzero();", rhs)?;
+                    f.write_str("\t")?;
+                }
+                if let Location::Constant(0) = rhs {
+                    // The quotient is never used, but dividing by the literal would be reported.
+                    return write!(f, "const int32_t t{} = 0;", dst);
+                }
+                match (minus_one, checked) {
+                    (false, _) => write!(f, "const int32_t t{} = {} / {};", dst, lhs, rhs),
+                    (true, true) => {
+                        writeln!(
+                            f,
+                            "if ({} == INT32_MIN && {} == -1) overflow();",
+                            lhs, rhs
+                        )?;
+                        write!(f, "\tconst int32_t t{} = {} / {};", dst, lhs, rhs)
+                    }
+                    (true, false) => write!(
+                        f,
+                        "const int32_t t{} = {2} == -1 ? wrap(0UL - (unsigned long){1}) : {1} / {2};",
+                        dst, lhs, rhs
+                    ),
+                }
+            }
+            Self::Machine {
+                lhs,
+                op: MachineOp::MulHigh,
+                rhs,
+                dst,
+            } => {
+                writeln!(
+                    f,
+                    "const long long t{}p = (long long){} * {};",
+                    dst, lhs, rhs
+                )?;
+                write!(f, "\tconst int32_t t{0} = (int32_t)sar64(t{0}p, 32);", dst)
+            }
+            // Shifts by 32 bits or more are undefined in C.
+            Self::Machine {
+                lhs,
+                op: MachineOp::Shl,
+                rhs,
+                dst,
+            } => write!(
+                f,
+                "const int32_t t{} = wrap((unsigned long){} << ({} & 31));",
+                dst, lhs, rhs
+            ),
+            Self::Machine {
+                lhs,
+                op: MachineOp::Sar,
+                rhs,
+                dst,
+            } => write!(
+                f,
+                "const int32_t t{} = (int32_t)sar64({}, {} & 31);",
+                dst, lhs, rhs
+            ),
+            Self::Machine {
+                lhs,
+                op: MachineOp::Shr,
+                rhs,
+                dst,
+            } => write!(
+                f,
+                "const int32_t t{} = wrap(((unsigned long){} & 0xffffffffUL) >> ({} & 31));",
+                dst, lhs, rhs
+            ),
+        }
+    }
+}
+
+/// C99 translation unit of an Instant program.
+pub struct C {
+    statements: Vec<Statement>,
+    /// Whether any division calls `divzero`.
+    divisions: bool,
+}
+
+impl C {
+    /// Returns whether the `wrap`, `sar64` and `overflow` helpers are called by any statement.
+    fn helpers(&self) -> (bool, bool, bool) {
+        let (mut wrap, mut sar, mut overflow) = (false, false, false);
+        for statement in &self.statements {
+            match statement {
+                Statement::Bin { checked: true, .. }
+                | Statement::Div {
+                    minus_one: true,
+                    checked: true,
+                    ..
+                } => overflow = true,
+                Statement::Bin { .. }
+                | Statement::Div {
+                    minus_one: true, ..
+                }
+                | Statement::Machine {
+                    op: MachineOp::Shl | MachineOp::Shr,
+                    ..
+                } => wrap = true,
+                Statement::Machine { .. } => sar = true,
+                _ => {}
+            }
+        }
+
+        (wrap, sar, overflow)
+    }
+}
+
+impl Display for C {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (wrap, sar, overflow) = self.helpers();
+
+        writeln!(f, "#include <inttypes.h>")?;
+        writeln!(f, "#include <stdio.h>")?;
+        writeln!(f, "#include <stdlib.h>\n")?;
+
+        writeln!(f, "static void printInt(int32_t x) {{")?;
+        writeln!(f, "\tprintf(\"%\" PRId32 \"\\n\", x);")?;
+        writeln!(f, "}}\n")?;
+
+        if wrap {
+            writeln!(
+                f,
+                "/* Converts the low 32 bits to int32_t, without implementation-defined conversions. */"
+            )?;
+            writeln!(f, "static int32_t wrap(unsigned long x) {{")?;
+            writeln!(f, "\tx &= 0xffffffffUL;")?;
+            writeln!(
+                f,
+                "\treturn x <= INT32_MAX ? (int32_t)x : (int32_t)(x - 0x80000000UL) + INT32_MIN;"
+            )?;
+            writeln!(f, "}}\n")?;
+        }
+        if sar {
+            writeln!(
+                f,
+                "/* Arithmetic right shift, implementation-defined for negative values in C. */"
+            )?;
+            writeln!(f, "static long long sar64(long long x, int shift) {{")?;
+            writeln!(f, "\treturn x < 0 ? ~(~x >> shift) : x >> shift;")?;
+            writeln!(f, "}}\n")?;
+        }
+        if self.divisions {
+            writeln!(f, "static void divzero(void) {{")?;
+            writeln!(f, "\tfputs(\"division by zero\\n\", stderr);")?;
+            writeln!(f, "\texit(1);")?;
+            writeln!(f, "}}\n")?;
+        }
+        if overflow {
+            writeln!(f, "static void overflow(void) {{")?;
+            writeln!(f, "\tfputs(\"integer overflow\\n\", stderr);")?;
+            writeln!(f, "\texit(1);")?;
+            writeln!(f, "}}\n")?;
+        }
+
+        writeln!(f, "int main(void) {{")?;
+        for statement in &self.statements {
+            writeln!(f, "\t{}", statement)?;
+        }
+        writeln!(f, "\treturn 0;")?;
+        writeln!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        opt::{OptLevel, PassManager},
+        testing::{self, Execution},
+    };
+    use std::{env, fs, io, process::Command};
+
+    fn c(source: &str, arithmetic: Arithmetic) -> C {
+        CBackend.process(&testing::program(source, arithmetic))
+    }
+
+    #[test]
+    fn generation() {
+        let statements = |c: C| {
+            c.statements
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            statements(c("a = 7 / 2; a * 2147483647; a / (0 - a)", Arithmetic::Wrapping)),
+            [
+                "const int32_t t0 = 7 / 2;",
+                "const int32_t t1 = wrap((unsigned long)t0 * (unsigned long)2147483647);",
+                "printInt(t1);",
+                "const int32_t t2 = wrap((unsigned long)0 - (unsigned long)t0);",
+                "if (t2 == 0) divzero();\n\tconst int32_t t3 = t2 == -1 ? wrap(0UL - (unsigned long)t0) : t0 / t2;",
+                "printInt(t3);",
+            ]
+        );
+        assert_eq!(
+            statements(c("(0 - 2147483647 - 1) / 0 + 1", Arithmetic::Checked))[2..],
+            [
+                "if (0 == 0) divzero();\n\tconst int32_t t2 = 0;",
+                "const long long t3w = (long long)t2 + 1;\n\tif (t3w < INT32_MIN || t3w > INT32_MAX) overflow();\n\tconst int32_t t3 = (int32_t)t3w;",
+                "printInt(t3);",
+            ]
+        );
+    }
+
+    #[test]
+    fn execution() {
+        let sources = [
+            "a = 2147483647; a + 1; a * a; (0 - a - 1) / (0 - 1)",
+            "a = 0 - 2147483647 - 1; a - 1; a / (a + 2147483647); a / 7; a * 65536",
+            "a = 100; a / 3; a / (0 - 4); (0 - a) / 4; a / (a - 100); 1",
+            "a = 65536; a * a; 2",
+        ];
+        let directory = env::temp_dir().join(format!("instant-c-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let (source_path, executable) = (directory.join("program.c"), directory.join("program"));
+
+        testing::check(&sources, |program| {
+            fs::write(&source_path, CBackend.process(program).to_string()).unwrap();
+            let status = Command::new("cc")
+                .args(["-std=c99", "-pedantic-errors", "-o"])
+                .arg(&executable)
+                .arg(&source_path)
+                .status()
+                .ok()?;
+            assert!(status.success());
+            Execution::of(&mut Command::new(&executable))
+        });
+
+        // Folded programs call none of the helpers, which must not be emitted either.
+        for arithmetic in [Arithmetic::Wrapping, Arithmetic::Checked] {
+            let mut program = testing::program("x = 2 * 3; x + 1", arithmetic);
+            PassManager::new(OptLevel::O2, true)
+                .run(&mut program, &mut io::sink())
+                .unwrap();
+            let c = CBackend.process(&program);
+            assert_eq!(c.helpers(), (false, false, false));
+
+            fs::write(&source_path, c.to_string()).unwrap();
+            let status = Command::new("cc")
+                .args(["-std=c99", "-Wall", "-Wextra", "-pedantic", "-Werror", "-o"])
+                .arg(&executable)
+                .arg(&source_path)
+                .status()
+                .unwrap();
+            assert!(status.success(), "{}", arithmetic);
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,410 @@
+//! Static ELF64 executables for x86-64 Linux, written without an assembler or a linker.
+//! The [Assembly] generated by the [X86Backend](super::x86_64::X86Backend) is encoded into
+//! machine code and placed in a single loadable segment together with the headers.
+
+use super::x86_64::{ArithOp, Assembly, Condition, Instruction, Label, Operand, Register, ShiftOp};
+use std::collections::HashMap;
+
+/// Virtual address at which the file is loaded.
+const BASE_ADDRESS: u64 = 0x400000;
+
+/// Size of the ELF header.
+const ELF_HEADER_SIZE: u16 = 64;
+
+/// Size of a program header.
+const PROGRAM_HEADER_SIZE: u16 = 56;
+
+/// Returns the REX prefix with the given bits, if any of them is set.
+fn rex(wide: bool, reg: u8, rm: u8) -> Option<u8> {
+    let bits = u8::from(wide) << 3 | (reg >> 3) << 2 | rm >> 3;
+    (bits != 0).then_some(0x40 | bits)
+}
+
+/// Machine code of the instructions, with the offsets of the labels.
+struct Encoder {
+    code: Vec<u8>,
+    labels: HashMap<Label, usize>,
+    /// Offsets of 32-bit displacements relative to the end of their instruction,
+    /// to be filled in with the distance to the label.
+    fixups: Vec<(usize, Label)>,
+}
+
+impl Encoder {
+    /// Appends an instruction with a ModRM byte addressing a register or a slot below `rbp`.
+    /// `reg` is a register number or an opcode extension.
+    fn modrm(&mut self, wide: bool, opcode: &[u8], reg: u8, rm: Operand) {
+        match rm {
+            Operand::Register(rm) => {
+                self.code.extend(rex(wide, reg, rm.number()));
+                self.code.extend(opcode);
+                self.code.push(0xc0 | (reg & 7) << 3 | rm.number() & 7);
+            }
+            Operand::Local(slot) => {
+                self.code.extend(rex(wide, reg, Register::Rbp.number()));
+                self.code.extend(opcode);
+                let offset = Operand::local_offset(slot);
+                match i8::try_from(offset) {
+                    Ok(offset) => {
+                        self.code.push(0x45 | (reg & 7) << 3);
+                        self.code.push(offset as u8);
+                    }
+                    Err(_) => {
+                        self.code.push(0x85 | (reg & 7) << 3);
+                        self.code.extend(offset.to_le_bytes());
+                    }
+                }
+            }
+            Operand::Immediate(_) => unreachable!("immediates are not addressable"),
+        }
+    }
+
+    /// Appends an instruction with an opcode extension and an immediate, in the short form
+    /// taking a sign-extended byte if possible.
+    fn immediate(&mut self, wide: bool, extension: u8, rm: Operand, val: i32) {
+        match i8::try_from(val) {
+            Ok(val) => {
+                self.modrm(wide, &[0x83], extension, rm);
+                self.code.push(val as u8);
+            }
+            Err(_) => {
+                self.modrm(wide, &[0x81], extension, rm);
+                self.code.extend(val.to_le_bytes());
+            }
+        }
+    }
+
+    /// Appends a 32-bit displacement to the label, relative to the end of the instruction.
+    fn displacement(&mut self, label: Label) {
+        self.fixups.push((self.code.len(), label));
+        self.code.extend([0; 4]);
+    }
+
+    fn encode(&mut self, instruction: Instruction) {
+        let register = Operand::Register;
+        match instruction {
+            Instruction::Label(label) => {
+                let previous = self.labels.insert(label, self.code.len());
+                assert!(previous.is_none(), "label {} defined twice", label);
+            }
+            Instruction::Mov {
+                src: Operand::Immediate(val),
+                dst: Operand::Register(dst),
+            } => {
+                self.code.extend(rex(false, 0, dst.number()));
+                self.code.push(0xb8 + (dst.number() & 7));
+                self.code.extend(val.to_le_bytes());
+            }
+            Instruction::Mov {
+                src: Operand::Immediate(val),
+                dst,
+            } => {
+                self.modrm(false, &[0xc7], 0, dst);
+                self.code.extend(val.to_le_bytes());
+            }
+            Instruction::Mov {
+                src: Operand::Register(src),
+                dst,
+            } => self.modrm(false, &[0x89], src.number(), dst),
+            Instruction::Mov {
+                src,
+                dst: Operand::Register(dst),
+            } => self.modrm(false, &[0x8b], dst.number(), src),
+            Instruction::Mov { .. } => unreachable!("at most one operand is in memory"),
+            Instruction::Arith {
+                op: ArithOp::Imul,
+                src: Operand::Immediate(val),
+                dst,
+            } => match i8::try_from(val) {
+                Ok(val) => {
+                    self.modrm(false, &[0x6b], dst.number(), register(dst));
+                    self.code.push(val as u8);
+                }
+                Err(_) => {
+                    self.modrm(false, &[0x69], dst.number(), register(dst));
+                    self.code.extend(val.to_le_bytes());
+                }
+            },
+            Instruction::Arith {
+                op,
+                src: Operand::Immediate(val),
+                dst,
+            } => {
+                let extension = match op {
+                    ArithOp::Add => 0,
+                    ArithOp::Sub => 5,
+                    ArithOp::Imul => unreachable!("multiplication is handled above"),
+                };
+                self.immediate(false, extension, register(dst), val);
+            }
+            Instruction::Arith { op, src, dst } => {
+                let opcode: &[u8] = match op {
+                    ArithOp::Add => &[0x03],
+                    ArithOp::Sub => &[0x2b],
+                    ArithOp::Imul => &[0x0f, 0xaf],
+                };
+                self.modrm(false, opcode, dst.number(), src);
+            }
+            Instruction::Cmp { operand, val } => self.immediate(false, 7, operand, val),
+            Instruction::Neg(register) => {
+                self.modrm(false, &[0xf7], 3, Operand::Register(register))
+            }
+            Instruction::Cdq => self.code.push(0x99),
+            Instruction::Idiv(operand) => self.modrm(false, &[0xf7], 7, operand),
+            Instruction::Div(register) => {
+                self.modrm(false, &[0xf7], 6, Operand::Register(register))
+            }
+            Instruction::WideImul(operand) => self.modrm(false, &[0xf7], 5, operand),
+            Instruction::Shift { op, amount, dst } => {
+                let extension = match op {
+                    ShiftOp::Shl => 4,
+                    ShiftOp::Shr => 5,
+                    ShiftOp::Sar => 7,
+                };
+                match amount {
+                    Some(amount) => {
+                        self.modrm(false, &[0xc1], extension, register(dst));
+                        self.code.push(amount);
+                    }
+                    None => self.modrm(false, &[0xd3], extension, register(dst)),
+                }
+            }
+            Instruction::Jump { condition, target } => {
+                match condition {
+                    None => self.code.push(0xe9),
+                    Some(condition) => self.code.extend([
+                        0x0f,
+                        match condition {
+                            Condition::Overflow => 0x80,
+                            Condition::Equal => 0x84,
+                            Condition::NotEqual => 0x85,
+                            Condition::GreaterEqual => 0x8d,
+                        },
+                    ]),
+                }
+                self.displacement(target);
+            }
+            Instruction::Call(label) => {
+                self.code.push(0xe8);
+                self.displacement(label);
+            }
+            Instruction::Ret => self.code.push(0xc3),
+            Instruction::Syscall => self.code.extend([0x0f, 0x05]),
+            Instruction::Push(register) => {
+                self.code.extend(rex(false, 0, register.number()));
+                self.code.push(0x50 + (register.number() & 7));
+            }
+            Instruction::Pop(register) => {
+                self.code.extend(rex(false, 0, register.number()));
+                self.code.push(0x58 + (register.number() & 7));
+            }
+            Instruction::SetFrame => self.modrm(
+                true,
+                &[0x89],
+                Register::Rsp.number(),
+                register(Register::Rbp),
+            ),
+            Instruction::Reserve(bytes) => {
+                self.immediate(true, 5, register(Register::Rsp), bytes as i32)
+            }
+            Instruction::Release(bytes) => {
+                self.immediate(true, 0, register(Register::Rsp), bytes as i32)
+            }
+            // ModRM and SIB bytes addressing `(%rsp,%rsi)`.
+            Instruction::StoreByte => self.code.extend([0x88, 0x14, 0x34]),
+            Instruction::AddressOnStack => self.code.extend([0x48, 0x8d, 0x34, 0x34]),
+            Instruction::Address { label, dst } => {
+                self.code.extend(rex(true, dst.number(), 0));
+                // ModRM addressing relative to the instruction pointer.
+                self.code.extend([0x8d, 0x05 | (dst.number() & 7) << 3]);
+                self.displacement(label);
+            }
+            Instruction::Ascii(text) => self.code.extend(text.as_bytes()),
+        }
+    }
+
+    /// Fills in the displacements of the labels.
+    fn resolve(&mut self) {
+        for &(offset, label) in &self.fixups {
+            let target = self.labels[&label] as i64;
+            let displacement = i32::try_from(target - (offset as i64 + 4))
+                .expect("the code is smaller than 2 GiB");
+            self.code[offset..offset + 4].copy_from_slice(&displacement.to_le_bytes());
+        }
+    }
+}
+
+/// Returns the machine code of the assembly with the offset of its entry point `_start`.
+pub fn machine_code(assembly: &Assembly) -> (Vec<u8>, usize) {
+    let mut encoder = Encoder {
+        code: Vec::new(),
+        labels: HashMap::new(),
+        fixups: Vec::new(),
+    };
+    for instruction in &assembly.instructions {
+        encoder.encode(*instruction);
+    }
+    encoder.resolve();
+
+    let entry = encoder.labels[&Label::Named("_start")];
+    (encoder.code, entry)
+}
+
+/// Returns a static ELF64 executable running the assembly. The file has no sections,
+/// only a single readable and executable segment loaded at [BASE_ADDRESS],
+/// which maps the headers and the code following them.
+pub fn executable(assembly: &Assembly) -> Vec<u8> {
+    let (code, entry) = machine_code(assembly);
+    let headers_size = u64::from(ELF_HEADER_SIZE + PROGRAM_HEADER_SIZE);
+    let file_size = headers_size + code.len() as u64;
+
+    let mut bytes = Vec::with_capacity(file_size as usize);
+    // Identification: 64-bit, little-endian, version 1, System V ABI.
+    bytes.extend(b"\x7fELF");
+    bytes.extend([2, 1, 1, 0]);
+    bytes.extend([0; 8]);
+    // Executable file for x86-64, version 1.
+    bytes.extend(2u16.to_le_bytes());
+    bytes.extend(62u16.to_le_bytes());
+    bytes.extend(1u32.to_le_bytes());
+    bytes.extend((BASE_ADDRESS + headers_size + entry as u64).to_le_bytes());
+    // Offsets of the program headers and the section headers.
+    bytes.extend(u64::from(ELF_HEADER_SIZE).to_le_bytes());
+    bytes.extend(0u64.to_le_bytes());
+    // No flags, then sizes of the headers and numbers of the program and section headers.
+    bytes.extend(0u32.to_le_bytes());
+    bytes.extend(ELF_HEADER_SIZE.to_le_bytes());
+    bytes.extend(PROGRAM_HEADER_SIZE.to_le_bytes());
+    bytes.extend(1u16.to_le_bytes());
+    bytes.extend(0u16.to_le_bytes());
+    bytes.extend(0u16.to_le_bytes());
+    bytes.extend(0u16.to_le_bytes());
+
+    // Loadable segment, readable and executable, covering the whole file.
+    bytes.extend(1u32.to_le_bytes());
+    bytes.extend(5u32.to_le_bytes());
+    bytes.extend(0u64.to_le_bytes());
+    bytes.extend(BASE_ADDRESS.to_le_bytes());
+    bytes.extend(BASE_ADDRESS.to_le_bytes());
+    bytes.extend(file_size.to_le_bytes());
+    bytes.extend(file_size.to_le_bytes());
+    bytes.extend(0x1000u64.to_le_bytes());
+
+    bytes.extend(code);
+    bytes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        backend::{x86_64::X86Backend, Backend},
+        testing, Arithmetic,
+    };
+
+    fn encoded(instructions: &[Instruction]) -> Vec<u8> {
+        let assembly = Assembly {
+            instructions: [&[Instruction::Label(Label::Named("_start"))], instructions].concat(),
+        };
+        machine_code(&assembly).0
+    }
+
+    #[test]
+    fn encoding() {
+        let mov = |src, dst| Instruction::Mov { src, dst };
+        // Checked against the output of `as`.
+        assert_eq!(
+            encoded(&[
+                mov(Operand::Immediate(-1), Operand::Register(Register::R9)),
+                mov(Operand::Register(Register::R12), Operand::Local(0)),
+                mov(Operand::Local(40), Operand::Register(Register::Rbx)),
+                Instruction::Arith {
+                    op: ArithOp::Imul,
+                    src: Operand::Local(1),
+                    dst: Register::R15,
+                },
+                Instruction::Arith {
+                    op: ArithOp::Sub,
+                    src: Operand::Immediate(1000),
+                    dst: Register::Rsi,
+                },
+                Instruction::Cmp {
+                    operand: Operand::Register(Register::R8),
+                    val: -1,
+                },
+                Instruction::Shift {
+                    op: ShiftOp::Sar,
+                    amount: None,
+                    dst: Register::R10,
+                },
+                Instruction::Push(Register::R11),
+                Instruction::Reserve(16),
+            ]),
+            [
+                0x41, 0xb9, 0xff, 0xff, 0xff, 0xff, // movl $-1, %r9d
+                0x44, 0x89, 0x65, 0xfc, // movl %r12d, -4(%rbp)
+                0x8b, 0x9d, 0x5c, 0xff, 0xff, 0xff, // movl -164(%rbp), %ebx
+                0x44, 0x0f, 0xaf, 0x7d, 0xf8, // imull -8(%rbp), %r15d
+                0x81, 0xee, 0xe8, 0x03, 0x00, 0x00, // subl $1000, %esi
+                0x41, 0x83, 0xf8, 0xff, // cmpl $-1, %r8d
+                0x41, 0xd3, 0xfa, // sarl %cl, %r10d
+                0x41, 0x53, // pushq %r11
+                0x48, 0x83, 0xec, 0x10, // subq $16, %rsp
+            ]
+        );
+
+        let (target, after) = (Label::Numbered(0), Label::Numbered(1));
+        assert_eq!(
+            encoded(&[
+                Instruction::Label(target),
+                Instruction::Jump {
+                    condition: Some(Condition::NotEqual),
+                    target: after,
+                },
+                Instruction::Call(target),
+                Instruction::Label(after),
+                Instruction::Address {
+                    label: target,
+                    dst: Register::Rsi,
+                },
+            ]),
+            [
+                0x0f, 0x85, 0x05, 0x00, 0x00, 0x00, // jne .L1
+                0xe8, 0xf5, 0xff, 0xff, 0xff, // call .L0
+                0x48, 0x8d, 0x35, 0xee, 0xff, 0xff, 0xff, // leaq .L0(%rip), %rsi
+            ]
+        );
+    }
+
+    #[test]
+    fn headers() {
+        let assembly = X86Backend::default().process(&testing::program("1", Arithmetic::Wrapping));
+        let bytes = executable(&assembly);
+
+        assert_eq!(bytes[..4], *b"\x7fELF");
+        assert_eq!(bytes.len(), 120 + machine_code(&assembly).0.len());
+        // The program starts right after the headers.
+        assert_eq!(bytes[24..32], (BASE_ADDRESS + 120).to_le_bytes());
+        // The segment covers the whole file.
+        assert_eq!(bytes[96..104], (bytes.len() as u64).to_le_bytes());
+    }
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    #[test]
+    fn execution() {
+        use crate::testing::Execution;
+        use std::{env, fs, os::unix::fs::PermissionsExt, process::Command};
+
+        let sources = [
+            "a = 7; b = a * a - 100000 * a; b / 3; b / (a - 7)",
+            "a = 2147483647; a + 1; 0 - a - 1; (0 - a - 1) / (a - a - 1)",
+            "a = 100; b = a / 7 + a / 4096 * 3 - a * 64; b; a * b",
+        ];
+        let path = env::temp_dir().join(format!("instant-elf-{}", std::process::id()));
+        testing::check(&sources, |program| {
+            fs::write(&path, executable(&X86Backend::default().process(program))).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+            Execution::of(&mut Command::new(&path))
+        });
+        fs::remove_file(&path).unwrap();
+    }
+}
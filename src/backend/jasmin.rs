@@ -1,56 +1,97 @@
-use super::Backend;
+mod class_file;
+mod peephole;
+
+pub use class_file::LimitError;
+pub use peephole::Peephole;
+
+use super::{
+    stack::{Layout, Storage},
+    Backend,
+};
 use crate::{
-    ast::{Exp, Op, Stmt},
-    UndeclaredVariableError,
+    ast::{self, Op},
+    ir::{Instruction as IrInstruction, MachineOp, Operand, Program},
+    Arithmetic,
 };
 use std::{
     cmp::{self, Ordering},
-    collections::{hash_map::Entry, HashMap},
     fmt::{self, Display, Formatter},
 };
 
-impl Op {
-    fn commutative(self) -> bool {
-        matches!(self, Self::Add | Self::Mul)
-    }
-}
-
 /// Backend for generating [Jasmin](https://jasmin.sourceforge.net/) from an Instant program.
+/// In [Arithmetic::Checked] mode, overflows throw `java.lang.ArithmeticException`.
+/// In [Arithmetic::Unbounded] mode, values are represented with `java.math.BigInteger`.
 pub struct JasminBackend {
     class_name: String,
+    peephole: Peephole,
 }
 
 impl JasminBackend {
-    /// Creates a new instance of this struct.
+    /// Creates a new instance of this struct, with all [Peephole] rewrites enabled.
     /// The given class name will be used to create the class encapsulating the main function.
     pub fn new(class_name: String) -> Self {
-        Self { class_name }
+        Self {
+            class_name,
+            peephole: Peephole::ALL,
+        }
+    }
+
+    /// Sets the rewrites applied to the generated instructions.
+    pub fn with_peephole(self, peephole: Peephole) -> Self {
+        Self { peephole, ..self }
     }
 }
 
 impl Backend for JasminBackend {
     type Representation = Jasmin;
 
-    fn process<'a>(&self, program: &[Stmt<'a>]) -> Result<Jasmin, UndeclaredVariableError<'a>> {
-        let mut builder = JasminBuilder::new(self.class_name.clone());
+    fn process(&self, program: &Program) -> Jasmin {
+        let mut builder = JasminBuilder::new(self.class_name.clone(), program);
 
-        for stmt in program {
-            builder.add_stmt(stmt)?;
+        for instruction in &program.instructions {
+            builder.add_instruction(instruction);
         }
 
-        Ok(builder.build())
+        builder.build(self.peephole)
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum Instruction {
     GetPrintStream,
     Swap,
+    Pop,
     Println,
     IStore(usize),
     Push(i32),
     ILoad(usize),
     BinOp(Op),
+    /// Overflow checking `Math.addExact`, `Math.subtractExact` or `Math.multiplyExact` call.
+    ExactBinOp(Op),
+    /// Call to the overflow checking division helper generated in the given class.
+    DivideExact(String),
+    Dup,
+    AStore(usize),
+    ALoad(usize),
+    PushLong(i64),
+    PushString(String),
+    NewBigInteger,
+    /// Constructor call creating a `BigInteger` from a decimal string.
+    InitBigInteger,
+    /// `BigInteger.valueOf` call converting a long into a `BigInteger`.
+    BigValueOf,
+    BigBinOp(Op),
+    PrintlnBig,
+    IShl,
+    IShr,
+    IUShr,
+    I2L,
+    LMul,
+    LShr,
+    L2I,
+    Dup2,
+    /// Increment of the local variable in the given slot by a constant in the i8 range.
+    IInc(usize, i32),
 }
 
 impl Display for Instruction {
@@ -60,14 +101,14 @@ impl Display for Instruction {
                 f.write_str("getstatic java/lang/System/out Ljava/io/PrintStream;")
             }
             Self::Swap => f.write_str("swap"),
+            Self::Pop => f.write_str("pop"),
             Self::Println => f.write_str("invokevirtual java/io/PrintStream/println(I)V"),
             Self::IStore(i @ 0..=3) => write!(f, "istore_{}", i),
             Self::IStore(i) => write!(f, "istore {}", i),
+            Self::Push(-1) => f.write_str("iconst_m1"),
             Self::Push(i @ 0..=5) => write!(f, "iconst_{}", i),
-            Self::Push(i @ 6..=127) => write!(f, "bipush {}", i),
-            Self::Push(i @ 128..=32767) => {
-                write!(f, "sipush {} {}", i.to_le_bytes()[1], i.to_le_bytes()[0])
-            }
+            Self::Push(i @ -128..=127) => write!(f, "bipush {}", i),
+            Self::Push(i @ -32768..=32767) => write!(f, "sipush {}", i),
             Self::Push(i) => write!(f, "ldc {}", i),
             Self::ILoad(i @ 0..=3) => write!(f, "iload_{}", i),
             Self::ILoad(i) => write!(f, "iload {}", i),
@@ -75,144 +116,447 @@ impl Display for Instruction {
             Self::BinOp(Op::Div) => f.write_str("idiv"),
             Self::BinOp(Op::Mul) => f.write_str("imul"),
             Self::BinOp(Op::Sub) => f.write_str("isub"),
+            Self::ExactBinOp(op) => write!(
+                f,
+                "invokestatic java/lang/Math/{}(II)I",
+                match op {
+                    Op::Add => "addExact",
+                    Op::Sub => "subtractExact",
+                    Op::Mul => "multiplyExact",
+                    Op::Div => unreachable!("checked divisions call divideExact"),
+                }
+            ),
+            Self::DivideExact(class_name) => {
+                write!(f, "invokestatic {}/divideExact(II)I", class_name)
+            }
+            Self::Dup => f.write_str("dup"),
+            Self::AStore(i @ 0..=3) => write!(f, "astore_{}", i),
+            Self::AStore(i) => write!(f, "astore {}", i),
+            Self::ALoad(i @ 0..=3) => write!(f, "aload_{}", i),
+            Self::ALoad(i) => write!(f, "aload {}", i),
+            Self::PushLong(i) => write!(f, "ldc2_w {}", i),
+            Self::PushString(s) => write!(f, "ldc \"{}\"", s),
+            Self::NewBigInteger => f.write_str("new java/math/BigInteger"),
+            Self::InitBigInteger => {
+                f.write_str("invokespecial java/math/BigInteger/<init>(Ljava/lang/String;)V")
+            }
+            Self::BigValueOf => f.write_str(
+                "invokestatic java/math/BigInteger/valueOf(J)Ljava/math/BigInteger;",
+            ),
+            Self::BigBinOp(op) => write!(
+                f,
+                "invokevirtual java/math/BigInteger/{}(Ljava/math/BigInteger;)Ljava/math/BigInteger;",
+                match op {
+                    Op::Add => "add",
+                    Op::Sub => "subtract",
+                    Op::Mul => "multiply",
+                    Op::Div => "divide",
+                }
+            ),
+            Self::PrintlnBig => f.write_str("invokevirtual java/io/PrintStream/println(Ljava/lang/Object;)V"),
+            Self::IShl => f.write_str("ishl"),
+            Self::IShr => f.write_str("ishr"),
+            Self::IUShr => f.write_str("iushr"),
+            Self::I2L => f.write_str("i2l"),
+            Self::LMul => f.write_str("lmul"),
+            Self::LShr => f.write_str("lshr"),
+            Self::L2I => f.write_str("l2i"),
+            Self::Dup2 => f.write_str("dup2"),
+            Self::IInc(slot, val) => write!(f, "iinc {} {}", slot, val),
         }
     }
 }
 
+impl Instruction {
+    /// Returns the change in the stack size caused by this instruction, in slots.
+    /// Values are always popped before the results are pushed.
+    fn stack_effect(&self) -> isize {
+        match self {
+            Self::Swap | Self::IInc(..) => 0,
+            Self::GetPrintStream
+            | Self::Push(_)
+            | Self::ILoad(_)
+            | Self::Dup
+            | Self::ALoad(_)
+            | Self::PushString(_)
+            | Self::NewBigInteger
+            | Self::I2L => 1,
+            Self::PushLong(_) | Self::Dup2 => 2,
+            Self::Pop
+            | Self::IStore(_)
+            | Self::BinOp(_)
+            | Self::ExactBinOp(_)
+            | Self::DivideExact(_)
+            | Self::AStore(_)
+            | Self::BigValueOf
+            | Self::BigBinOp(_)
+            | Self::IShl
+            | Self::IShr
+            | Self::IUShr
+            | Self::LShr
+            | Self::L2I => -1,
+            Self::Println | Self::PrintlnBig | Self::InitBigInteger | Self::LMul => -2,
+        }
+    }
+}
+
+/// Returns the maximum stack size reached by the instructions, starting from an empty stack.
+fn stack_limit(instructions: &[Instruction]) -> usize {
+    let mut depth = 0;
+    let mut limit = 0;
+    for instruction in instructions {
+        depth += instruction.stack_effect();
+        limit = cmp::max(limit, depth);
+    }
+
+    limit as usize
+}
+
 #[derive(Debug)]
 struct ProcessedExp {
     instructions: Vec<Instruction>,
     depth: usize,
 }
 
-struct JasminBuilder<'a> {
+struct JasminBuilder<'p> {
     class_name: String,
+    program: &'p Program,
+    definitions: Vec<usize>,
+    storage: Vec<Storage>,
     stack_depth: usize,
-    locals: HashMap<&'a str, usize>,
+    locals: usize,
     instructions: Vec<Instruction>,
 }
 
-impl<'a> JasminBuilder<'a> {
-    fn new(class_name: String) -> Self {
+impl<'p> JasminBuilder<'p> {
+    fn new(class_name: String, program: &'p Program) -> Self {
+        let Layout {
+            definitions,
+            storage,
+            locals,
+        } = Layout::new(program);
+
         Self {
             class_name,
+            program,
+            definitions,
+            storage,
             stack_depth: 0,
-            locals: Default::default(),
+            locals,
             instructions: Default::default(),
         }
     }
 
-    fn process_exp(&self, exp: &Exp<'a>) -> Result<ProcessedExp, UndeclaredVariableError<'a>> {
-        match exp {
-            Exp::Lit(val) => Ok(ProcessedExp {
-                instructions: vec![Instruction::Push(*val)],
+    /// Returns the instruction performing the given operation on two values from the top of the stack.
+    /// All variants consume two values and push one, so they do not affect the stack depth.
+    fn bin_op(&self, op: Op) -> Instruction {
+        match (self.program.arithmetic, op) {
+            (Arithmetic::Wrapping, op) => Instruction::BinOp(op),
+            (Arithmetic::Checked, Op::Div) => Instruction::DivideExact(self.class_name.clone()),
+            (Arithmetic::Checked, op) => Instruction::ExactBinOp(op),
+            (Arithmetic::Unbounded, op) => Instruction::BigBinOp(op),
+        }
+    }
+
+    fn load(&self, slot: usize) -> Instruction {
+        match self.program.arithmetic {
+            Arithmetic::Unbounded => Instruction::ALoad(slot),
+            _ => Instruction::ILoad(slot),
+        }
+    }
+
+    fn store(&self, slot: usize) -> Instruction {
+        match self.program.arithmetic {
+            Arithmetic::Unbounded => Instruction::AStore(slot),
+            _ => Instruction::IStore(slot),
+        }
+    }
+
+    fn process_operand(&self, operand: Operand) -> ProcessedExp {
+        let unbounded = self.program.arithmetic == Arithmetic::Unbounded;
+
+        match operand {
+            Operand::Const(val) if unbounded => ProcessedExp {
+                instructions: vec![Instruction::PushLong(val.into()), Instruction::BigValueOf],
+                depth: 2,
+            },
+            Operand::Const(val) => ProcessedExp {
+                instructions: vec![Instruction::Push(val)],
+                depth: 1,
+            },
+            Operand::BigConst(idx) if unbounded => ProcessedExp {
+                instructions: vec![
+                    Instruction::NewBigInteger,
+                    Instruction::Dup,
+                    Instruction::PushString(self.program.big_constants[idx].clone()),
+                    Instruction::InitBigInteger,
+                ],
+                depth: 3,
+            },
+            Operand::BigConst(idx) => ProcessedExp {
+                instructions: vec![Instruction::Push(ast::wrapped_literal(
+                    &self.program.big_constants[idx],
+                ))],
                 depth: 1,
-            }),
-            Exp::Var { name, position } => {
-                let slot = self
-                    .locals
-                    .get(name)
-                    .copied()
-                    .ok_or(UndeclaredVariableError {
-                        name: *name,
-                        byte_offset: *position,
-                    })?;
-
-                Ok(ProcessedExp {
-                    instructions: vec![Instruction::ILoad(slot)],
+            },
+            Operand::Value(value) => match self.storage[value.0] {
+                Storage::Local(slot) => ProcessedExp {
+                    instructions: vec![self.load(slot)],
                     depth: 1,
-                })
+                },
+                Storage::Inline | Storage::Dup | Storage::Discard => {
+                    self.process_definition(&self.program.instructions[self.definitions[value.0]])
+                }
+            },
+        }
+    }
+
+    fn process_definition(&self, instruction: &IrInstruction) -> ProcessedExp {
+        match instruction {
+            IrInstruction::Bin { lhs, op, rhs, .. }
+                if self.associative(*op) && !self.duplicated(*lhs, *rhs) =>
+            {
+                let mut operands = Vec::new();
+                self.collect_chain(*op, *lhs, &mut operands);
+                self.collect_chain(*op, *rhs, &mut operands);
+
+                self.process_chain(*op, operands)
+            }
+            IrInstruction::Bin { lhs, op, rhs, .. } => {
+                self.process_bin(*lhs, *rhs, self.bin_op(*op), op.commutative())
             }
-            Exp::Bi { lhs, op, rhs } => {
-                let mut lhs = self.process_exp(lhs)?;
-                let mut rhs = self.process_exp(rhs)?;
-
-                let (instructions, depth) = match rhs.depth.cmp(&lhs.depth) {
-                    Ordering::Equal => {
-                        lhs.instructions.reserve(rhs.instructions.len() + 1);
-                        lhs.instructions.extend(rhs.instructions);
-                        lhs.instructions.push(Instruction::BinOp(*op));
-
-                        (lhs.instructions, rhs.depth + 1)
-                    }
-                    Ordering::Greater => {
-                        rhs.instructions.reserve(lhs.instructions.len() + 2);
-                        rhs.instructions.extend(lhs.instructions);
-                        if !op.commutative() {
-                            rhs.instructions.push(Instruction::Swap);
-                        }
-                        rhs.instructions.push(Instruction::BinOp(*op));
-
-                        (rhs.instructions, rhs.depth)
-                    }
-                    Ordering::Less => {
-                        lhs.instructions.reserve(rhs.instructions.len() + 1);
-                        lhs.instructions.extend(rhs.instructions);
-                        lhs.instructions.push(Instruction::BinOp(*op));
-
-                        (lhs.instructions, lhs.depth)
-                    }
+            IrInstruction::Machine {
+                lhs,
+                op: MachineOp::MulHigh,
+                rhs,
+                ..
+            } => self.process_mul_high(*lhs, *rhs),
+            IrInstruction::Machine { lhs, op, rhs, .. } => {
+                assert!(
+                    self.program.arithmetic != Arithmetic::Unbounded,
+                    "machine operations are not supported in unbounded arithmetic"
+                );
+                let instruction = match op {
+                    MachineOp::Shl => Instruction::IShl,
+                    MachineOp::Sar => Instruction::IShr,
+                    MachineOp::Shr => Instruction::IUShr,
+                    MachineOp::MulHigh => unreachable!("high multiplication is handled separately"),
                 };
 
-                Ok(ProcessedExp {
-                    instructions,
-                    depth,
-                })
+                self.process_bin(*lhs, *rhs, instruction, false)
+            }
+            IrInstruction::Print(_) => unreachable!("values are never defined by prints"),
+        }
+    }
+
+    /// Returns whether the operands of nested operations of the given kind may be regrouped
+    /// and reordered. Overflows in [Arithmetic::Checked] mode depend on the grouping.
+    fn associative(&self, op: Op) -> bool {
+        op.commutative() && self.program.arithmetic != Arithmetic::Checked
+    }
+
+    /// Returns whether the operands are a single value computed once and duplicated.
+    fn duplicated(&self, lhs: Operand, rhs: Operand) -> bool {
+        match (lhs, lhs == rhs) {
+            (Operand::Value(value), true) => matches!(self.storage[value.0], Storage::Dup),
+            _ => false,
+        }
+    }
+
+    /// Collects the operands of the chain of the given associative operation rooted at the operand,
+    /// descending only into values computed inline.
+    fn collect_chain(&self, op: Op, operand: Operand, operands: &mut Vec<Operand>) {
+        if let Operand::Value(value) = operand {
+            if let (
+                Storage::Inline,
+                IrInstruction::Bin {
+                    op: inner,
+                    lhs,
+                    rhs,
+                    ..
+                },
+            ) = (
+                self.storage[value.0],
+                &self.program.instructions[self.definitions[value.0]],
+            ) {
+                if *inner == op && !self.duplicated(*lhs, *rhs) {
+                    self.collect_chain(op, *lhs, operands);
+                    self.collect_chain(op, *rhs, operands);
+                    return;
+                }
             }
         }
+
+        operands.push(operand);
+    }
+
+    /// Combines the operands of an associative operation into the accumulator on the top of the stack,
+    /// starting from the deepest ones. The resulting depth is the lowest possible:
+    /// the depth of the deepest operand or the second deepest one plus the accumulator.
+    fn process_chain(&self, op: Op, operands: Vec<Operand>) -> ProcessedExp {
+        let mut operands = operands
+            .into_iter()
+            .map(|operand| self.process_operand(operand))
+            .collect::<Vec<_>>();
+        operands.sort_by_key(|exp| cmp::Reverse(exp.depth));
+
+        let mut instructions = Vec::new();
+        let mut depth = 0;
+        for (i, exp) in operands.into_iter().enumerate() {
+            depth = cmp::max(depth, exp.depth + cmp::min(i, 1));
+            instructions.extend(exp.instructions);
+            if i > 0 {
+                instructions.push(self.bin_op(op));
+            }
+        }
+
+        ProcessedExp {
+            instructions,
+            depth,
+        }
+    }
+
+    /// Computes the high half of the product in the 64-bit arithmetic.
+    /// Operands are never reordered, as `swap` does not work on longs.
+    fn process_mul_high(&self, lhs: Operand, rhs: Operand) -> ProcessedExp {
+        assert!(
+            self.program.arithmetic != Arithmetic::Unbounded,
+            "machine operations are not supported in unbounded arithmetic"
+        );
+
+        let mut exp = self.process_operand(lhs);
+        exp.instructions.push(Instruction::I2L);
+        let depth = if lhs == rhs {
+            exp.instructions.push(Instruction::Dup2);
+            cmp::max(exp.depth, 4)
+        } else {
+            let rhs = self.process_operand(rhs);
+            exp.instructions.extend(rhs.instructions);
+            exp.instructions.push(Instruction::I2L);
+            cmp::max(exp.depth, 2 + cmp::max(rhs.depth, 2))
+        };
+
+        exp.instructions.push(Instruction::LMul);
+        exp.instructions.push(Instruction::Push(32));
+        exp.instructions.push(Instruction::LShr);
+        exp.instructions.push(Instruction::L2I);
+
+        ProcessedExp {
+            instructions: exp.instructions,
+            depth,
+        }
     }
 
-    fn add_stmt(&mut self, stmt: &Stmt<'a>) -> Result<(), UndeclaredVariableError<'a>> {
-        let depth = match stmt {
-            Stmt::Exp(exp) => {
-                let exp = self.process_exp(exp)?;
+    /// Computes both operands in the Sethi–Ullman order and applies the given instruction.
+    /// The instruction has to consume two values and push one.
+    fn process_bin(
+        &self,
+        lhs: Operand,
+        rhs: Operand,
+        instruction: Instruction,
+        commutative: bool,
+    ) -> ProcessedExp {
+        if self.duplicated(lhs, rhs) {
+            let mut exp = self.process_operand(lhs);
+            exp.instructions.push(Instruction::Dup);
+            exp.instructions.push(instruction);
+
+            return ProcessedExp {
+                instructions: exp.instructions,
+                depth: cmp::max(exp.depth, 2),
+            };
+        }
+
+        let mut lhs = self.process_operand(lhs);
+        let mut rhs = self.process_operand(rhs);
+
+        let (instructions, depth) = match rhs.depth.cmp(&lhs.depth) {
+            Ordering::Equal => {
+                lhs.instructions.reserve(rhs.instructions.len() + 1);
+                lhs.instructions.extend(rhs.instructions);
+                lhs.instructions.push(instruction);
+
+                (lhs.instructions, rhs.depth + 1)
+            }
+            Ordering::Greater => {
+                rhs.instructions.reserve(lhs.instructions.len() + 2);
+                rhs.instructions.extend(lhs.instructions);
+                if !commutative {
+                    rhs.instructions.push(Instruction::Swap);
+                }
+                rhs.instructions.push(instruction);
+
+                (rhs.instructions, rhs.depth)
+            }
+            Ordering::Less => {
+                lhs.instructions.reserve(rhs.instructions.len() + 1);
+                lhs.instructions.extend(rhs.instructions);
+                lhs.instructions.push(instruction);
+
+                (lhs.instructions, lhs.depth)
+            }
+        };
+
+        ProcessedExp {
+            instructions,
+            depth,
+        }
+    }
+
+    fn add_instruction(&mut self, instruction: &IrInstruction) {
+        let depth = match instruction {
+            IrInstruction::Print(operand) => {
+                let println = match self.program.arithmetic {
+                    Arithmetic::Unbounded => Instruction::PrintlnBig,
+                    _ => Instruction::Println,
+                };
+
+                let exp = self.process_operand(*operand);
                 if exp.depth > 1 {
                     self.instructions.reserve(exp.instructions.len() + 3);
                     self.instructions.extend(exp.instructions);
                     self.instructions.push(Instruction::GetPrintStream);
                     self.instructions.push(Instruction::Swap);
-                    self.instructions.push(Instruction::Println);
+                    self.instructions.push(println);
 
                     exp.depth
                 } else {
                     self.instructions.reserve(exp.instructions.len() + 2);
                     self.instructions.push(Instruction::GetPrintStream);
                     self.instructions.extend(exp.instructions);
-                    self.instructions.push(Instruction::Println);
+                    self.instructions.push(println);
 
                     2
                 }
             }
-            Stmt::Ass { var, exp } => {
-                let exp = self.process_exp(exp)?;
-
-                let next_local = self.locals.len() + 1;
-                let slot = match self.locals.entry(*var) {
-                    Entry::Occupied(e) => *e.get(),
-                    Entry::Vacant(e) => {
-                        e.insert(next_local);
-                        next_local
-                    }
+            IrInstruction::Bin { dst, .. } | IrInstruction::Machine { dst, .. } => {
+                let last = match self.storage[dst.0] {
+                    Storage::Inline | Storage::Dup => return,
+                    Storage::Local(slot) => self.store(slot),
+                    Storage::Discard => Instruction::Pop,
                 };
 
+                let exp = self.process_definition(instruction);
                 self.instructions.reserve(exp.instructions.len() + 1);
                 self.instructions.extend(exp.instructions);
-                self.instructions.push(Instruction::IStore(slot));
+                self.instructions.push(last);
 
                 exp.depth
             }
         };
 
         self.stack_depth = cmp::max(self.stack_depth, depth);
-
-        Ok(())
     }
 
-    fn build(self) -> Jasmin {
+    fn build(mut self, peephole: Peephole) -> Jasmin {
+        debug_assert_eq!(self.stack_depth, stack_limit(&self.instructions));
+        peephole.apply(&mut self.instructions, &mut self.locals);
+
         Jasmin {
             class_name: self.class_name,
-            stack_limit: self.stack_depth,
-            locals: self.locals.len() + 1,
+            arithmetic: self.program.arithmetic,
+            stack_limit: stack_limit(&self.instructions),
+            locals: self.locals,
             instructions: self.instructions,
         }
     }
@@ -221,6 +565,7 @@ impl<'a> JasminBuilder<'a> {
 /// [Jasmin](https://jasmin.sourceforge.net/) representation of an Instant program.
 pub struct Jasmin {
     class_name: String,
+    arithmetic: Arithmetic,
     stack_limit: usize,
     locals: usize,
     instructions: Vec<Instruction>,
@@ -240,6 +585,32 @@ impl Display for Jasmin {
         writeln!(f, "return")?;
         writeln!(f, ".end method\n")?;
 
+        if self.arithmetic == Arithmetic::Checked {
+            writeln!(f, ".method private static divideExact(II)I")?;
+            writeln!(f, ".limit stack 3")?;
+            writeln!(f, ".limit locals 2")?;
+            writeln!(f, "iload_1")?;
+            writeln!(f, "iconst_m1")?;
+            writeln!(f, "if_icmpne Divide")?;
+            writeln!(f, "iload_0")?;
+            writeln!(f, "ldc {}", i32::MIN)?;
+            writeln!(f, "if_icmpne Divide")?;
+            writeln!(f, "new java/lang/ArithmeticException")?;
+            writeln!(f, "dup")?;
+            writeln!(f, "ldc \"integer overflow\"")?;
+            writeln!(
+                f,
+                "invokespecial java/lang/ArithmeticException/<init>(Ljava/lang/String;)V"
+            )?;
+            writeln!(f, "athrow")?;
+            writeln!(f, "Divide:")?;
+            writeln!(f, "iload_0")?;
+            writeln!(f, "iload_1")?;
+            writeln!(f, "idiv")?;
+            writeln!(f, "ireturn")?;
+            writeln!(f, ".end method\n")?;
+        }
+
         writeln!(f, ".method public static main([Ljava/lang/String;)V")?;
         writeln!(f, ".limit stack {}", self.stack_limit)?;
         writeln!(f, ".limit locals {}", self.locals)?;
@@ -256,6 +627,10 @@ impl Display for Jasmin {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::{
+        ast::{Exp, Stmt},
+        parser,
+    };
 
     #[test]
     fn instructions_representation() {
@@ -267,39 +642,54 @@ mod test {
         assert_eq!(Instruction::Push(5).to_string(), "iconst_5");
         assert_eq!(Instruction::Push(6).to_string(), "bipush 6");
         assert_eq!(Instruction::Push(127).to_string(), "bipush 127");
-        assert_eq!(Instruction::Push(128).to_string(), "sipush 0 128");
-        assert_eq!(Instruction::Push(32767).to_string(), "sipush 127 255");
+        assert_eq!(Instruction::Push(128).to_string(), "sipush 128");
+        assert_eq!(Instruction::Push(32767).to_string(), "sipush 32767");
         assert_eq!(Instruction::Push(32768).to_string(), "ldc 32768");
+        assert_eq!(Instruction::Push(-1).to_string(), "iconst_m1");
+        assert_eq!(Instruction::Push(-128).to_string(), "bipush -128");
+        assert_eq!(Instruction::Push(-129).to_string(), "sipush -129");
+        assert_eq!(Instruction::Push(-32769).to_string(), "ldc -32769");
 
         assert_eq!(Instruction::ILoad(0).to_string(), "iload_0");
         assert_eq!(Instruction::ILoad(3).to_string(), "iload_3");
         assert_eq!(Instruction::ILoad(4).to_string(), "iload 4");
     }
 
+    /// Lowers the given statements and processes the operand printed by the last one.
+    fn process_printed(stmts: &[Stmt], arithmetic: Arithmetic) -> ProcessedExp {
+        let program = Program::lower(stmts, arithmetic).unwrap();
+        let builder = JasminBuilder::new("dummy".into(), &program);
+
+        match program.instructions.last() {
+            Some(IrInstruction::Print(operand)) => builder.process_operand(*operand),
+            _ => panic!("program should end with a print"),
+        }
+    }
+
+    /// Parses the source and processes the operand printed by its last statement.
+    fn process_source(source: &str, arithmetic: Arithmetic) -> ProcessedExp {
+        process_printed(&parser::parse(source, arithmetic).unwrap(), arithmetic)
+    }
+
+    fn program(source: &str, arithmetic: Arithmetic) -> Program {
+        let stmts = parser::parse(source, arithmetic).unwrap();
+        Program::lower(&stmts, arithmetic).unwrap()
+    }
+
     #[test]
     fn expression_optimization() {
-        let builder = JasminBuilder::new("dummy".into());
-
-        let processed = builder.process_exp(&Exp::Lit(0)).unwrap();
+        let processed = process_printed(&[Stmt::Exp(Exp::Lit(0))], Arithmetic::Wrapping);
         assert_eq!(processed.instructions, [Instruction::Push(0)]);
         assert_eq!(processed.depth, 1);
 
-        let error = builder
-            .process_exp(&Exp::Var {
-                name: "name",
-                position: 0,
-            })
-            .expect_err("undeclared variable access should result in an error");
-        assert_eq!(error.name, "name");
-        assert_eq!(error.byte_offset, 0);
-
-        let processed = builder
-            .process_exp(&Exp::Bi {
+        let processed = process_printed(
+            &[Stmt::Exp(Exp::Bi {
                 lhs: Exp::Lit(0).into(),
                 op: Op::Add,
                 rhs: Exp::Lit(1).into(),
-            })
-            .unwrap();
+            })],
+            Arithmetic::Wrapping,
+        );
         assert_eq!(
             processed.instructions,
             [
@@ -310,8 +700,8 @@ mod test {
         );
         assert_eq!(processed.depth, 2);
 
-        let processed = builder
-            .process_exp(&Exp::Bi {
+        let processed = process_printed(
+            &[Stmt::Exp(Exp::Bi {
                 lhs: Exp::Lit(0).into(),
                 op: Op::Mul,
                 rhs: Exp::Bi {
@@ -320,8 +710,9 @@ mod test {
                     rhs: Exp::Lit(5).into(),
                 }
                 .into(),
-            })
-            .unwrap();
+            })],
+            Arithmetic::Wrapping,
+        );
         assert_eq!(
             processed.instructions,
             [
@@ -334,8 +725,8 @@ mod test {
         );
         assert_eq!(processed.depth, 2);
 
-        let processed = builder
-            .process_exp(&Exp::Bi {
+        let processed = process_printed(
+            &[Stmt::Exp(Exp::Bi {
                 lhs: Exp::Lit(0).into(),
                 op: Op::Div,
                 rhs: Exp::Bi {
@@ -344,8 +735,9 @@ mod test {
                     rhs: Exp::Lit(5).into(),
                 }
                 .into(),
-            })
-            .unwrap();
+            })],
+            Arithmetic::Wrapping,
+        );
         assert_eq!(
             processed.instructions,
             [
@@ -359,4 +751,259 @@ mod test {
         );
         assert_eq!(processed.depth, 2);
     }
+
+    #[test]
+    fn associative_chains() {
+        // Balanced tree of additions of 16 literals.
+        fn balanced(first: i32, len: i32) -> String {
+            match len {
+                1 => first.to_string(),
+                _ => format!(
+                    "({}) + ({})",
+                    balanced(first, len / 2),
+                    balanced(first + len / 2, len / 2)
+                ),
+            }
+        }
+
+        let source = balanced(1, 16);
+        assert_eq!(process_source(&source, Arithmetic::Wrapping).depth, 2);
+        assert_eq!(process_source(&source, Arithmetic::Unbounded).depth, 3);
+        // Operations that may fail keep their order, so the left operands are stored in locals.
+        assert_eq!(process_source(&source, Arithmetic::Checked).depth, 2);
+
+        let processed = process_source("1 * 2 * (3 * 4)", Arithmetic::Wrapping);
+        assert_eq!(
+            processed.instructions,
+            [
+                Instruction::Push(1),
+                Instruction::Push(2),
+                Instruction::BinOp(Op::Mul),
+                Instruction::Push(3),
+                Instruction::BinOp(Op::Mul),
+                Instruction::Push(4),
+                Instruction::BinOp(Op::Mul),
+            ]
+        );
+        assert_eq!(processed.depth, 2);
+
+        // The deepest operand is computed first, other operations are not regrouped.
+        let processed = process_source("1 + (2 - 3 - (4 - 5) + 6 * 7)", Arithmetic::Wrapping);
+        assert_eq!(
+            processed.instructions,
+            [
+                Instruction::Push(2),
+                Instruction::Push(3),
+                Instruction::BinOp(Op::Sub),
+                Instruction::Push(4),
+                Instruction::Push(5),
+                Instruction::BinOp(Op::Sub),
+                Instruction::BinOp(Op::Sub),
+                Instruction::Push(6),
+                Instruction::Push(7),
+                Instruction::BinOp(Op::Mul),
+                Instruction::BinOp(Op::Add),
+                Instruction::Push(1),
+                Instruction::BinOp(Op::Add),
+            ]
+        );
+        assert_eq!(processed.depth, 3);
+    }
+
+    #[test]
+    fn value_storage() {
+        let program = program("a = 1 / 0; b = 2 * 3; 5; a", Arithmetic::Wrapping);
+        let jasmin = JasminBackend::new("dummy".into())
+            .with_peephole(Peephole::NONE)
+            .process(&program);
+
+        assert_eq!(
+            jasmin.instructions,
+            [
+                Instruction::Push(1),
+                Instruction::Push(0),
+                Instruction::BinOp(Op::Div),
+                Instruction::IStore(0),
+                Instruction::Push(2),
+                Instruction::Push(3),
+                Instruction::BinOp(Op::Mul),
+                Instruction::Pop,
+                Instruction::GetPrintStream,
+                Instruction::Push(5),
+                Instruction::Println,
+                Instruction::GetPrintStream,
+                Instruction::ILoad(0),
+                Instruction::Println,
+            ]
+        );
+        assert_eq!(jasmin.stack_limit, 2);
+        assert_eq!(jasmin.locals, 1);
+    }
+
+    #[test]
+    fn local_allocation() {
+        let stores = |jasmin: &Jasmin| {
+            jasmin
+                .instructions
+                .iter()
+                .filter_map(|instruction| match instruction {
+                    Instruction::IStore(slot) => Some(*slot),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let source = format!("a = 7 / 3; {}a", "a; a = a + 1; ".repeat(10000));
+        let program = program(&source, Arithmetic::Wrapping);
+        let jasmin = JasminBackend::new("dummy".into())
+            .with_peephole(Peephole::NONE)
+            .process(&program);
+        assert_eq!(stores(&jasmin), [0; 10000]);
+        assert_eq!(jasmin.locals, 1);
+
+        let program = self::program(
+            "a = 7 / 1; b = 7 / 2; a; c = 7 / 3; b; b = 7 / 4; b; b; a + c",
+            Arithmetic::Wrapping,
+        );
+        let jasmin = JasminBackend::new("dummy".into())
+            .with_peephole(Peephole::NONE)
+            .process(&program);
+        assert_eq!(stores(&jasmin), [0, 1, 2, 1]);
+        assert_eq!(jasmin.locals, 3);
+    }
+
+    #[test]
+    fn checked_arithmetic() {
+        let processed = process_source("a = 3 * 4; a; 2 / (a * (1 + 5))", Arithmetic::Checked);
+        assert_eq!(
+            processed.instructions,
+            [
+                Instruction::Push(1),
+                Instruction::Push(5),
+                Instruction::ExactBinOp(Op::Add),
+                Instruction::ILoad(0),
+                Instruction::ExactBinOp(Op::Mul),
+                Instruction::Push(2),
+                Instruction::Swap,
+                Instruction::DivideExact("dummy".into()),
+            ]
+        );
+        assert_eq!(processed.depth, 2);
+
+        assert_eq!(
+            Instruction::ExactBinOp(Op::Sub).to_string(),
+            "invokestatic java/lang/Math/subtractExact(II)I"
+        );
+        assert_eq!(
+            Instruction::DivideExact("dummy".into()).to_string(),
+            "invokestatic dummy/divideExact(II)I"
+        );
+    }
+
+    #[test]
+    fn unbounded_arithmetic() {
+        let program = program("a = 12345678901234567890 * 2; a; a", Arithmetic::Unbounded);
+        let mut builder = JasminBuilder::new("dummy".into(), &program);
+        builder.add_instruction(&program.instructions[0]);
+        assert_eq!(
+            builder.instructions,
+            [
+                Instruction::NewBigInteger,
+                Instruction::Dup,
+                Instruction::PushString("12345678901234567890".into()),
+                Instruction::InitBigInteger,
+                Instruction::PushLong(2),
+                Instruction::BigValueOf,
+                Instruction::BigBinOp(Op::Mul),
+                Instruction::AStore(0),
+            ]
+        );
+        assert_eq!(builder.stack_depth, 3);
+
+        let processed = process_source("a = 12345678901234567890; a - 7", Arithmetic::Unbounded);
+        assert_eq!(
+            processed.instructions,
+            [
+                Instruction::NewBigInteger,
+                Instruction::Dup,
+                Instruction::PushString("12345678901234567890".into()),
+                Instruction::InitBigInteger,
+                Instruction::PushLong(7),
+                Instruction::BigValueOf,
+                Instruction::BigBinOp(Op::Sub),
+            ]
+        );
+        assert_eq!(processed.depth, 3);
+    }
+
+    #[test]
+    fn duplicated_values() {
+        let mut program = program("a = 7 / 0; (a + 1) * (a + 1)", Arithmetic::Wrapping);
+        crate::opt::cse::cse(&mut program);
+        let jasmin = JasminBackend::new("dummy".into())
+            .with_peephole(Peephole::NONE)
+            .process(&program);
+
+        assert_eq!(
+            jasmin.instructions,
+            [
+                Instruction::Push(7),
+                Instruction::Push(0),
+                Instruction::BinOp(Op::Div),
+                Instruction::Push(1),
+                Instruction::BinOp(Op::Add),
+                Instruction::Dup,
+                Instruction::BinOp(Op::Mul),
+                Instruction::GetPrintStream,
+                Instruction::Swap,
+                Instruction::Println,
+            ]
+        );
+        assert_eq!(jasmin.stack_limit, 2);
+        assert_eq!(jasmin.locals, 1);
+    }
+
+    #[test]
+    fn machine_operations() {
+        let mut program = program("a = 7 / 0; a * 8; a / 3", Arithmetic::Wrapping);
+        crate::opt::simplify::simplify(&mut program, true);
+        let jasmin = JasminBackend::new("dummy".into())
+            .with_peephole(Peephole::NONE)
+            .process(&program);
+
+        assert_eq!(
+            jasmin.instructions,
+            [
+                Instruction::Push(7),
+                Instruction::Push(0),
+                Instruction::BinOp(Op::Div),
+                Instruction::IStore(0),
+                Instruction::ILoad(0),
+                Instruction::Push(3),
+                Instruction::IShl,
+                Instruction::GetPrintStream,
+                Instruction::Swap,
+                Instruction::Println,
+                Instruction::ILoad(0),
+                Instruction::I2L,
+                Instruction::Push(1431655766),
+                Instruction::I2L,
+                Instruction::LMul,
+                Instruction::Push(32),
+                Instruction::LShr,
+                Instruction::L2I,
+                Instruction::IStore(0),
+                Instruction::ILoad(0),
+                Instruction::Push(31),
+                Instruction::IUShr,
+                Instruction::ILoad(0),
+                Instruction::BinOp(Op::Add),
+                Instruction::GetPrintStream,
+                Instruction::Swap,
+                Instruction::Println,
+            ]
+        );
+        assert_eq!(jasmin.stack_limit, 4);
+        assert_eq!(jasmin.locals, 1);
+    }
 }
@@ -0,0 +1,613 @@
+use super::{Instruction, Jasmin};
+use crate::{ast::Op, Arithmetic};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+};
+
+/// Version of the class file format, the last one not requiring stack map frames.
+const MAJOR_VERSION: u16 = 49;
+
+const ACC_PUBLIC: u16 = 0x0001;
+const ACC_PRIVATE: u16 = 0x0002;
+const ACC_STATIC: u16 = 0x0008;
+const ACC_SUPER: u16 = 0x0020;
+
+/// Maximum of the 16-bit counts and lengths of the class file format.
+const LIMIT: usize = u16::MAX as usize;
+
+/// Limit of the class file format exceeded by the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitError {
+    /// Number of the constant pool entries, longs taking two of them.
+    Constants(usize),
+    /// Length in bytes of a string constant.
+    Text(usize),
+    /// Maximum depth of the operand stack of `main`.
+    Stack(usize),
+    /// Number of the local variables of `main`.
+    Locals(usize),
+    /// Length in bytes of the code of `main`.
+    Code(usize),
+}
+
+impl Display for LimitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (what, count, limit) = match *self {
+            Self::Constants(count) => ("constants", count, LIMIT - 1),
+            Self::Text(length) => ("bytes of a string constant", length, LIMIT),
+            Self::Stack(depth) => ("stack slots", depth, LIMIT),
+            Self::Locals(count) => ("local variables", count, LIMIT),
+            Self::Code(length) => ("bytes of code", length, LIMIT),
+        };
+        write!(
+            f,
+            "{} {} exceed the limit of {} in a class file",
+            count, what, limit
+        )
+    }
+}
+
+/// Entry of the constant pool.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Constant {
+    Utf8(String),
+    Integer(i32),
+    Long(i64),
+    String(u16),
+    Class(u16),
+    NameAndType(u16, u16),
+    Fieldref(u16, u16),
+    Methodref(u16, u16),
+}
+
+/// Constant pool of a class file, without duplicate entries.
+#[derive(Default)]
+struct ConstantPool {
+    bytes: Vec<u8>,
+    indices: HashMap<Constant, u16>,
+    /// Number of slots taken, longs take two of them. Index 0 is not used.
+    /// The indices beyond the limit are truncated, and the pool is then rejected.
+    slots: usize,
+    /// Length of the longest string, rejected beyond the limit.
+    longest: usize,
+}
+
+impl ConstantPool {
+    /// Returns the index of the constant, adding it to the pool if needed.
+    fn add(&mut self, constant: Constant) -> u16 {
+        if let Some(&idx) = self.indices.get(&constant) {
+            return idx;
+        }
+
+        match &constant {
+            Constant::Utf8(text) => {
+                // Modified UTF-8 differs only for null and supplementary characters,
+                // which never appear in names and Instant literals.
+                self.bytes.push(1);
+                self.bytes.extend((text.len() as u16).to_be_bytes());
+                self.longest = self.longest.max(text.len());
+                self.bytes.extend(text.as_bytes());
+            }
+            Constant::Integer(val) => {
+                self.bytes.push(3);
+                self.bytes.extend(val.to_be_bytes());
+            }
+            Constant::Long(val) => {
+                self.bytes.push(5);
+                self.bytes.extend(val.to_be_bytes());
+            }
+            Constant::Class(name) => {
+                self.bytes.push(7);
+                self.bytes.extend(name.to_be_bytes());
+            }
+            Constant::String(text) => {
+                self.bytes.push(8);
+                self.bytes.extend(text.to_be_bytes());
+            }
+            Constant::Fieldref(class, name_and_type) => {
+                self.bytes.push(9);
+                self.bytes.extend(class.to_be_bytes());
+                self.bytes.extend(name_and_type.to_be_bytes());
+            }
+            Constant::Methodref(class, name_and_type) => {
+                self.bytes.push(10);
+                self.bytes.extend(class.to_be_bytes());
+                self.bytes.extend(name_and_type.to_be_bytes());
+            }
+            Constant::NameAndType(name, descriptor) => {
+                self.bytes.push(12);
+                self.bytes.extend(name.to_be_bytes());
+                self.bytes.extend(descriptor.to_be_bytes());
+            }
+        }
+
+        let idx = (self.slots + 1) as u16;
+        self.slots += if matches!(constant, Constant::Long(_)) {
+            2
+        } else {
+            1
+        };
+        self.indices.insert(constant, idx);
+        idx
+    }
+
+    /// Returns the error if an index or a string of the pool exceeds its limit.
+    fn check(&self) -> Result<(), LimitError> {
+        if self.slots >= LIMIT {
+            return Err(LimitError::Constants(self.slots));
+        }
+        if self.longest > LIMIT {
+            return Err(LimitError::Text(self.longest));
+        }
+        Ok(())
+    }
+
+    fn utf8(&mut self, text: &str) -> u16 {
+        self.add(Constant::Utf8(text.into()))
+    }
+
+    fn class(&mut self, name: &str) -> u16 {
+        let name = self.utf8(name);
+        self.add(Constant::Class(name))
+    }
+
+    fn string(&mut self, text: &str) -> u16 {
+        let text = self.utf8(text);
+        self.add(Constant::String(text))
+    }
+
+    fn name_and_type(&mut self, name: &str, descriptor: &str) -> u16 {
+        let name = self.utf8(name);
+        let descriptor = self.utf8(descriptor);
+        self.add(Constant::NameAndType(name, descriptor))
+    }
+
+    fn field(&mut self, class: &str, name: &str, descriptor: &str) -> u16 {
+        let class = self.class(class);
+        let name_and_type = self.name_and_type(name, descriptor);
+        self.add(Constant::Fieldref(class, name_and_type))
+    }
+
+    fn method(&mut self, class: &str, name: &str, descriptor: &str) -> u16 {
+        let class = self.class(class);
+        let name_and_type = self.name_and_type(name, descriptor);
+        self.add(Constant::Methodref(class, name_and_type))
+    }
+}
+
+/// Bytecode of a method body.
+struct Code<'c> {
+    pool: &'c mut ConstantPool,
+    bytes: Vec<u8>,
+}
+
+impl Code<'_> {
+    /// Appends an instruction on a local variable, with the `wide` prefix for slots above 255.
+    fn local(&mut self, short: u8, opcode: u8, slot: usize) {
+        match (u8::try_from(slot), slot) {
+            (_, 0..=3) => self.bytes.push(short + slot as u8),
+            (Ok(slot), _) => self.bytes.extend([opcode, slot]),
+            (Err(_), _) => {
+                self.bytes.extend([0xc4, opcode]);
+                self.bytes.extend((slot as u16).to_be_bytes());
+            }
+        }
+    }
+
+    /// Appends `ldc` of the constant, or `ldc_w` for indices above 255.
+    fn ldc(&mut self, idx: u16) {
+        match u8::try_from(idx) {
+            Ok(idx) => self.bytes.extend([0x12, idx]),
+            Err(_) => {
+                self.bytes.push(0x13);
+                self.bytes.extend(idx.to_be_bytes());
+            }
+        }
+    }
+
+    /// Appends an instruction taking the index of a constant.
+    fn indexed(&mut self, opcode: u8, idx: u16) {
+        self.bytes.push(opcode);
+        self.bytes.extend(idx.to_be_bytes());
+    }
+
+    fn invoke(&mut self, opcode: u8, class: &str, name: &str, descriptor: &str) {
+        let method = self.pool.method(class, name, descriptor);
+        self.indexed(opcode, method);
+    }
+
+    fn add(&mut self, instruction: &Instruction) {
+        const INVOKEVIRTUAL: u8 = 0xb6;
+        const INVOKESPECIAL: u8 = 0xb7;
+        const INVOKESTATIC: u8 = 0xb8;
+        const BIG_INTEGER: &str = "java/math/BigInteger";
+        const PRINT_STREAM: &str = "java/io/PrintStream";
+
+        match instruction {
+            Instruction::GetPrintStream => {
+                let field = self
+                    .pool
+                    .field("java/lang/System", "out", "Ljava/io/PrintStream;");
+                self.indexed(0xb2, field);
+            }
+            Instruction::Swap => self.bytes.push(0x5f),
+            Instruction::Pop => self.bytes.push(0x57),
+            Instruction::Println => self.invoke(INVOKEVIRTUAL, PRINT_STREAM, "println", "(I)V"),
+            Instruction::IStore(slot) => self.local(0x3b, 0x36, *slot),
+            Instruction::Push(val @ -1..=5) => self.bytes.push((0x03 + val) as u8),
+            Instruction::Push(val @ -128..=127) => self.bytes.extend([0x10, *val as u8]),
+            Instruction::Push(val @ -32768..=32767) => {
+                self.bytes.push(0x11);
+                self.bytes.extend((*val as i16).to_be_bytes());
+            }
+            Instruction::Push(val) => {
+                let constant = self.pool.add(Constant::Integer(*val));
+                self.ldc(constant);
+            }
+            Instruction::ILoad(slot) => self.local(0x1a, 0x15, *slot),
+            Instruction::BinOp(op) => self.bytes.push(match op {
+                Op::Add => 0x60,
+                Op::Sub => 0x64,
+                Op::Mul => 0x68,
+                Op::Div => 0x6c,
+            }),
+            Instruction::ExactBinOp(op) => {
+                let name = match op {
+                    Op::Add => "addExact",
+                    Op::Sub => "subtractExact",
+                    Op::Mul => "multiplyExact",
+                    Op::Div => unreachable!("checked divisions call divideExact"),
+                };
+                self.invoke(INVOKESTATIC, "java/lang/Math", name, "(II)I");
+            }
+            Instruction::DivideExact(class_name) => {
+                self.invoke(INVOKESTATIC, class_name, "divideExact", "(II)I")
+            }
+            Instruction::Dup => self.bytes.push(0x59),
+            Instruction::AStore(slot) => self.local(0x4b, 0x3a, *slot),
+            Instruction::ALoad(slot) => self.local(0x2a, 0x19, *slot),
+            Instruction::PushLong(val) => {
+                let constant = self.pool.add(Constant::Long(*val));
+                self.indexed(0x14, constant);
+            }
+            Instruction::PushString(text) => {
+                let constant = self.pool.string(text);
+                self.ldc(constant);
+            }
+            Instruction::NewBigInteger => {
+                let class = self.pool.class(BIG_INTEGER);
+                self.indexed(0xbb, class);
+            }
+            Instruction::InitBigInteger => self.invoke(
+                INVOKESPECIAL,
+                BIG_INTEGER,
+                "<init>",
+                "(Ljava/lang/String;)V",
+            ),
+            Instruction::BigValueOf => self.invoke(
+                INVOKESTATIC,
+                BIG_INTEGER,
+                "valueOf",
+                "(J)Ljava/math/BigInteger;",
+            ),
+            Instruction::BigBinOp(op) => {
+                let name = match op {
+                    Op::Add => "add",
+                    Op::Sub => "subtract",
+                    Op::Mul => "multiply",
+                    Op::Div => "divide",
+                };
+                self.invoke(
+                    INVOKEVIRTUAL,
+                    BIG_INTEGER,
+                    name,
+                    "(Ljava/math/BigInteger;)Ljava/math/BigInteger;",
+                );
+            }
+            Instruction::PrintlnBig => self.invoke(
+                INVOKEVIRTUAL,
+                PRINT_STREAM,
+                "println",
+                "(Ljava/lang/Object;)V",
+            ),
+            Instruction::IShl => self.bytes.push(0x78),
+            Instruction::IShr => self.bytes.push(0x7a),
+            Instruction::IUShr => self.bytes.push(0x7c),
+            Instruction::I2L => self.bytes.push(0x85),
+            Instruction::LMul => self.bytes.push(0x69),
+            Instruction::LShr => self.bytes.push(0x7b),
+            Instruction::L2I => self.bytes.push(0x88),
+            Instruction::Dup2 => self.bytes.push(0x5c),
+            Instruction::IInc(slot, val) => match u8::try_from(*slot) {
+                Ok(slot) => self.bytes.extend([0x84, slot, *val as u8]),
+                Err(_) => {
+                    self.bytes.extend([0xc4, 0x84]);
+                    self.bytes.extend((*slot as u16).to_be_bytes());
+                    self.bytes.extend((*val as i16).to_be_bytes());
+                }
+            },
+        }
+    }
+
+    /// Appends the `divideExact` helper of [Arithmetic::Checked] mode,
+    /// throwing `ArithmeticException` on the overflow of the minimum divided by -1.
+    fn divide_exact(&mut self) {
+        const IF_ICMPNE: u8 = 0xa0;
+        let mut branches = Vec::new();
+        self.bytes.extend([0x1b, 0x02]);
+        branches.push(self.bytes.len());
+        self.bytes.extend([IF_ICMPNE, 0, 0]);
+        self.bytes.push(0x1a);
+        let min = self.pool.add(Constant::Integer(i32::MIN));
+        self.ldc(min);
+        branches.push(self.bytes.len());
+        self.bytes.extend([IF_ICMPNE, 0, 0]);
+
+        let exception = "java/lang/ArithmeticException";
+        let class = self.pool.class(exception);
+        self.indexed(0xbb, class);
+        self.bytes.push(0x59);
+        let message = self.pool.string("integer overflow");
+        self.ldc(message);
+        self.invoke(0xb7, exception, "<init>", "(Ljava/lang/String;)V");
+        self.bytes.push(0xbf);
+
+        // Branch offsets are relative to the branch instructions.
+        let divide = self.bytes.len();
+        for branch in branches {
+            let offset = (divide - branch) as i16;
+            self.bytes[branch + 1..branch + 3].copy_from_slice(&offset.to_be_bytes());
+        }
+        self.bytes.extend([0x1a, 0x1b, 0x6c, 0xac]);
+    }
+}
+
+/// Appends a method with its `Code` attribute.
+fn method(
+    bytes: &mut Vec<u8>,
+    pool: &mut ConstantPool,
+    (access, name, descriptor): (u16, &str, &str),
+    (max_stack, max_locals): (usize, usize),
+    code: &[u8],
+) {
+    bytes.extend(access.to_be_bytes());
+    bytes.extend(pool.utf8(name).to_be_bytes());
+    bytes.extend(pool.utf8(descriptor).to_be_bytes());
+    bytes.extend(1u16.to_be_bytes());
+
+    bytes.extend(pool.utf8("Code").to_be_bytes());
+    bytes.extend((12 + code.len() as u32).to_be_bytes());
+    bytes.extend((max_stack as u16).to_be_bytes());
+    bytes.extend((max_locals as u16).to_be_bytes());
+    bytes.extend((code.len() as u32).to_be_bytes());
+    bytes.extend(code);
+    // No exception handlers and no attributes.
+    bytes.extend([0; 4]);
+}
+
+impl Jasmin {
+    /// Returns the binary JVM class file of the program, the same class as the one assembled
+    /// by Jasmin from [Display](std::fmt::Display), with the limits of `main` taken from the
+    /// computed stack limit and number of locals. Programs exceeding the 16-bit limits
+    /// of the format are rejected instead of written into a corrupt class.
+    pub fn class_file(&self) -> Result<Vec<u8>, LimitError> {
+        if self.stack_limit > LIMIT {
+            return Err(LimitError::Stack(self.stack_limit));
+        }
+        if self.locals > LIMIT {
+            return Err(LimitError::Locals(self.locals));
+        }
+
+        let mut pool = ConstantPool::default();
+        let this_class = pool.class(&self.class_name);
+        let super_class = pool.class("java/lang/Object");
+
+        let mut methods = Vec::new();
+        let mut method_count = 2u16;
+        let init = pool.method("java/lang/Object", "<init>", "()V");
+        let [high, low] = init.to_be_bytes();
+        method(
+            &mut methods,
+            &mut pool,
+            (ACC_PUBLIC, "<init>", "()V"),
+            (1, 1),
+            &[0x2a, 0xb7, high, low, 0xb1],
+        );
+
+        if self.arithmetic == Arithmetic::Checked {
+            let mut code = Code {
+                pool: &mut pool,
+                bytes: Vec::new(),
+            };
+            code.divide_exact();
+            let code = code.bytes;
+            method(
+                &mut methods,
+                &mut pool,
+                (ACC_PRIVATE | ACC_STATIC, "divideExact", "(II)I"),
+                (3, 2),
+                &code,
+            );
+            method_count += 1;
+        }
+
+        let mut code = Code {
+            pool: &mut pool,
+            bytes: Vec::new(),
+        };
+        for instruction in &self.instructions {
+            code.add(instruction);
+        }
+        code.bytes.push(0xb1);
+        let code = code.bytes;
+        method(
+            &mut methods,
+            &mut pool,
+            (ACC_PUBLIC | ACC_STATIC, "main", "([Ljava/lang/String;)V"),
+            (self.stack_limit, self.locals),
+            &code,
+        );
+
+        pool.check()?;
+        if code.len() > LIMIT {
+            return Err(LimitError::Code(code.len()));
+        }
+
+        let mut bytes = vec![0xca, 0xfe, 0xba, 0xbe];
+        bytes.extend(0u16.to_be_bytes());
+        bytes.extend(MAJOR_VERSION.to_be_bytes());
+        bytes.extend((pool.slots as u16 + 1).to_be_bytes());
+        bytes.extend(&pool.bytes);
+        bytes.extend((ACC_PUBLIC | ACC_SUPER).to_be_bytes());
+        bytes.extend(this_class.to_be_bytes());
+        bytes.extend(super_class.to_be_bytes());
+        // No interfaces and no fields.
+        bytes.extend([0; 4]);
+        bytes.extend(method_count.to_be_bytes());
+        bytes.extend(methods);
+        // No attributes.
+        bytes.extend([0; 2]);
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        backend::jasmin::JasminBackend, backend::Backend, interpreter::Interpreter, ir::Program,
+        parser,
+    };
+    use std::{env, fs, process::Command};
+
+    #[test]
+    fn encoding() {
+        let mut pool = ConstantPool::default();
+        let mut code = Code {
+            pool: &mut pool,
+            bytes: Vec::new(),
+        };
+        let instructions = [
+            Instruction::Push(-1),
+            Instruction::Push(5),
+            Instruction::Push(-128),
+            Instruction::Push(128),
+            Instruction::Push(-32769),
+            Instruction::Push(-32769),
+            Instruction::PushLong(7),
+            Instruction::IStore(3),
+            Instruction::IStore(4),
+            Instruction::ILoad(300),
+            Instruction::IInc(256, -1),
+        ];
+        for instruction in &instructions {
+            code.add(instruction);
+        }
+
+        assert_eq!(
+            code.bytes,
+            [
+                0x02, // iconst_m1
+                0x08, // iconst_5
+                0x10, 0x80, // bipush -128
+                0x11, 0x00, 0x80, // sipush 128
+                0x12, 0x01, // ldc -32769
+                0x12, 0x01, // ldc -32769
+                0x14, 0x00, 0x02, // ldc2_w 7
+                0x3e, // istore_3
+                0x36, 0x04, // istore 4
+                0xc4, 0x15, 0x01, 0x2c, // wide iload 300
+                0xc4, 0x84, 0x01, 0x00, 0xff, 0xff, // wide iinc 256 -1
+            ]
+        );
+        // Longs take two slots of the pool.
+        assert_eq!(pool.slots, 3);
+        assert_eq!(pool.add(Constant::Integer(1)), 4);
+    }
+
+    #[test]
+    fn limits() {
+        let class_file = |source: &str, arithmetic| {
+            let stmts = parser::parse(source, arithmetic).unwrap();
+            let program = Program::lower(&stmts, arithmetic).unwrap();
+            JasminBackend::new("Test".into())
+                .process(&program)
+                .class_file()
+        };
+
+        let literals = (0..70000)
+            .map(|i| (100000 + i).to_string())
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            class_file(&literals.join("; "), Arithmetic::Wrapping),
+            Err(LimitError::Constants(_))
+        ));
+        assert_eq!(
+            class_file(&"9".repeat(70000), Arithmetic::Unbounded),
+            Err(LimitError::Text(70000))
+        );
+        assert!(matches!(
+            class_file(
+                &format!("a = 1{}", "; a".repeat(20000)),
+                Arithmetic::Wrapping
+            ),
+            Err(LimitError::Code(_))
+        ));
+
+        let jasmin = |stack_limit, locals| Jasmin {
+            class_name: "Test".into(),
+            arithmetic: Arithmetic::Wrapping,
+            stack_limit,
+            locals,
+            instructions: Vec::new(),
+        };
+        assert_eq!(jasmin(70000, 1).class_file(), Err(LimitError::Stack(70000)));
+        assert_eq!(
+            jasmin(1, 70000).class_file(),
+            Err(LimitError::Locals(70000))
+        );
+        assert!(jasmin(65535, 65535).class_file().is_ok());
+    }
+
+    #[test]
+    fn execution() {
+        let source = "a = 100000; b = a * a / 7; b - 70000 * a; c = b / 3; c * c; a / (b - b)";
+        let dir = env::temp_dir().join(format!("instant-class-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let arithmetics = [
+            Arithmetic::Wrapping,
+            Arithmetic::Checked,
+            Arithmetic::Unbounded,
+        ];
+        for arithmetic in arithmetics {
+            let stmts = parser::parse(source, arithmetic).unwrap();
+            let mut expected = Vec::new();
+            assert!(Interpreter::new(arithmetic)
+                .run(&stmts, &mut expected)
+                .is_err());
+            let program = Program::lower(&stmts, arithmetic).unwrap();
+            let class_file = JasminBackend::new("Test".into())
+                .process(&program)
+                .class_file()
+                .unwrap();
+            assert_eq!(class_file[..8], [0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 49]);
+            fs::write(dir.join("Test.class"), class_file).unwrap();
+
+            let Ok(output) = Command::new("java")
+                .arg("-Xverify:all")
+                .arg("-cp")
+                .arg(&dir)
+                .arg("Test")
+                .output()
+            else {
+                break;
+            };
+            assert_eq!(output.stdout, expected);
+            assert_eq!(output.status.code(), Some(1));
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,365 @@
+use super::Instruction;
+use crate::ast::Op;
+
+/// Local rewrites of the generated Jasmin instructions, each of them can be enabled separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Peephole {
+    /// `istore n; iload n` becomes `dup; istore n`, same for references.
+    pub store_load: bool,
+    /// Pairs of `swap`s and `swap`s before commutative operations are removed.
+    pub swaps: bool,
+    /// `System.out` is kept in a local variable, if this makes the code shorter.
+    pub print_stream: bool,
+    /// `iload n; <const>; iadd; istore n` and its variants become `iinc n <const>`.
+    pub iinc: bool,
+}
+
+impl Peephole {
+    /// All rewrites enabled.
+    pub const ALL: Self = Self {
+        store_load: true,
+        swaps: true,
+        print_stream: true,
+        iinc: true,
+    };
+
+    /// All rewrites disabled.
+    pub const NONE: Self = Self {
+        store_load: false,
+        swaps: false,
+        print_stream: false,
+        iinc: false,
+    };
+
+    /// Applies the enabled rewrites. New local variables are allocated after the given number of slots.
+    pub(super) fn apply(self, instructions: &mut Vec<Instruction>, locals: &mut usize) {
+        // Increments have to be matched before their stores are rewritten.
+        if self.iinc {
+            rewrite(instructions, iinc);
+        }
+        if self.store_load {
+            rewrite(instructions, store_load);
+        }
+        if self.swaps {
+            rewrite(instructions, swaps);
+        }
+        if self.print_stream {
+            cache_print_stream(instructions, locals);
+        }
+    }
+}
+
+/// Rewrite rule, called on every prefix of the result.
+/// If it matches the end of the prefix, it returns the number of the matched instructions
+/// and their replacement.
+type Rule = fn(&[Instruction]) -> Option<(usize, Vec<Instruction>)>;
+
+/// Rewrites the instructions with the given rule, until it no longer matches.
+fn rewrite(instructions: &mut Vec<Instruction>, rule: Rule) {
+    let mut result = Vec::with_capacity(instructions.len());
+    for instruction in instructions.drain(..) {
+        result.push(instruction);
+        while let Some((matched, replacement)) = rule(&result) {
+            result.truncate(result.len() - matched);
+            result.extend(replacement);
+        }
+    }
+
+    *instructions = result;
+}
+
+fn iinc(instructions: &[Instruction]) -> Option<(usize, Vec<Instruction>)> {
+    use Instruction::{BinOp, ILoad, IStore, Push};
+
+    let (slot, val) = match instructions {
+        [.., ILoad(load), Push(val), BinOp(Op::Add), IStore(store)]
+        | [.., Push(val), ILoad(load), BinOp(Op::Add), IStore(store)]
+            if load == store =>
+        {
+            (*store, *val)
+        }
+        [.., ILoad(load), Push(val), BinOp(Op::Sub), IStore(store)] if load == store => {
+            (*store, val.checked_neg()?)
+        }
+        _ => return None,
+    };
+
+    i8::try_from(val)
+        .ok()
+        .map(|_| (4, vec![Instruction::IInc(slot, val)]))
+}
+
+fn store_load(instructions: &[Instruction]) -> Option<(usize, Vec<Instruction>)> {
+    match instructions {
+        [.., Instruction::IStore(store), Instruction::ILoad(load)] if store == load => {
+            Some((2, vec![Instruction::Dup, Instruction::IStore(*store)]))
+        }
+        [.., Instruction::AStore(store), Instruction::ALoad(load)] if store == load => {
+            Some((2, vec![Instruction::Dup, Instruction::AStore(*store)]))
+        }
+        _ => None,
+    }
+}
+
+fn swaps(instructions: &[Instruction]) -> Option<(usize, Vec<Instruction>)> {
+    match instructions {
+        [.., Instruction::Swap, Instruction::Swap] => Some((2, vec![])),
+        [.., Instruction::Swap, op @ (Instruction::BinOp(op_kind)
+        | Instruction::ExactBinOp(op_kind)
+        | Instruction::BigBinOp(op_kind))]
+            if op_kind.commutative() =>
+        {
+            Some((2, vec![op.clone()]))
+        }
+        _ => None,
+    }
+}
+
+/// Replaces `getstatic` of `System.out` with a load from a new local variable,
+/// if the saved bytes outweigh the initial store.
+fn cache_print_stream(instructions: &mut Vec<Instruction>, locals: &mut usize) {
+    let slot = *locals;
+    let (load, store) = match slot {
+        0..=3 => (1, 1),
+        _ => (2, 2),
+    };
+    let getstatic = 3;
+
+    let uses = instructions
+        .iter()
+        .filter(|instruction| **instruction == Instruction::GetPrintStream)
+        .count();
+    if uses * (getstatic - load) <= getstatic + store {
+        return;
+    }
+
+    for instruction in instructions.iter_mut() {
+        if *instruction == Instruction::GetPrintStream {
+            *instruction = Instruction::ALoad(slot);
+        }
+    }
+    instructions.splice(
+        0..0,
+        [Instruction::GetPrintStream, Instruction::AStore(slot)],
+    );
+    *locals += 1;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::backend::jasmin::stack_limit;
+
+    fn optimized(
+        peephole: Peephole,
+        mut instructions: Vec<Instruction>,
+        mut locals: usize,
+    ) -> (Vec<Instruction>, usize) {
+        peephole.apply(&mut instructions, &mut locals);
+        (instructions, locals)
+    }
+
+    #[test]
+    fn store_load() {
+        let peephole = Peephole {
+            store_load: true,
+            ..Peephole::NONE
+        };
+        let instructions = vec![
+            Instruction::Push(1),
+            Instruction::Push(2),
+            Instruction::BinOp(Op::Add),
+            Instruction::IStore(4),
+            Instruction::ILoad(4),
+            Instruction::ILoad(4),
+            Instruction::BinOp(Op::Mul),
+            Instruction::AStore(1),
+            Instruction::ALoad(1),
+            Instruction::Pop,
+        ];
+        assert_eq!(stack_limit(&instructions), 2);
+
+        let (optimized, locals) = optimized(peephole, instructions.clone(), 5);
+        assert_eq!(
+            optimized,
+            [
+                Instruction::Push(1),
+                Instruction::Push(2),
+                Instruction::BinOp(Op::Add),
+                Instruction::Dup,
+                Instruction::Dup,
+                Instruction::IStore(4),
+                Instruction::BinOp(Op::Mul),
+                Instruction::Dup,
+                Instruction::AStore(1),
+                Instruction::Pop,
+            ]
+        );
+        assert_eq!(locals, 5);
+        assert_eq!(stack_limit(&optimized), 3);
+
+        assert_eq!(
+            self::optimized(Peephole::NONE, instructions.clone(), 5),
+            (instructions, 5)
+        );
+    }
+
+    #[test]
+    fn swaps() {
+        let peephole = Peephole {
+            swaps: true,
+            ..Peephole::NONE
+        };
+        let instructions = vec![
+            Instruction::Push(1),
+            Instruction::Push(2),
+            Instruction::Swap,
+            Instruction::Push(3),
+            Instruction::Swap,
+            Instruction::Swap,
+            Instruction::Swap,
+            Instruction::BinOp(Op::Sub),
+            Instruction::Swap,
+            Instruction::BinOp(Op::Mul),
+            Instruction::Push(4),
+            Instruction::Swap,
+            Instruction::ExactBinOp(Op::Add),
+        ];
+
+        let (optimized, _) = self::optimized(peephole, instructions.clone(), 1);
+        assert_eq!(
+            optimized,
+            [
+                Instruction::Push(1),
+                Instruction::Push(2),
+                Instruction::Swap,
+                Instruction::Push(3),
+                Instruction::Swap,
+                Instruction::BinOp(Op::Sub),
+                Instruction::BinOp(Op::Mul),
+                Instruction::Push(4),
+                Instruction::ExactBinOp(Op::Add),
+            ]
+        );
+        assert_eq!(stack_limit(&optimized), 3);
+
+        assert_eq!(
+            self::optimized(Peephole::NONE, instructions.clone(), 1),
+            (instructions, 1)
+        );
+    }
+
+    #[test]
+    fn print_stream() {
+        let peephole = Peephole {
+            print_stream: true,
+            ..Peephole::NONE
+        };
+        let print = |val| {
+            [
+                Instruction::GetPrintStream,
+                Instruction::Push(val),
+                Instruction::Println,
+            ]
+        };
+
+        // Two loads of System.out from slot 3 do not pay off the store.
+        let instructions = [print(1), print(2)].concat();
+        assert_eq!(
+            optimized(peephole, instructions.clone(), 3),
+            (instructions, 3)
+        );
+
+        let instructions = [print(1), print(2), print(3)].concat();
+        let (optimized, locals) = optimized(peephole, instructions.clone(), 3);
+        assert_eq!(
+            optimized,
+            [
+                Instruction::GetPrintStream,
+                Instruction::AStore(3),
+                Instruction::ALoad(3),
+                Instruction::Push(1),
+                Instruction::Println,
+                Instruction::ALoad(3),
+                Instruction::Push(2),
+                Instruction::Println,
+                Instruction::ALoad(3),
+                Instruction::Push(3),
+                Instruction::Println,
+            ]
+        );
+        assert_eq!(locals, 4);
+        assert_eq!(stack_limit(&optimized), 2);
+
+        // Loads from slot 4 are longer.
+        assert_eq!(
+            self::optimized(peephole, instructions.clone(), 4),
+            (instructions, 4)
+        );
+        let instructions = [print(1), print(2), print(3), print(4), print(5), print(6)].concat();
+        assert_eq!(self::optimized(peephole, instructions, 4).1, 5);
+    }
+
+    #[test]
+    fn iinc() {
+        let peephole = Peephole {
+            iinc: true,
+            ..Peephole::NONE
+        };
+        let instructions = vec![
+            Instruction::ILoad(2),
+            Instruction::Push(1),
+            Instruction::BinOp(Op::Add),
+            Instruction::IStore(2),
+            Instruction::Push(-128),
+            Instruction::ILoad(2),
+            Instruction::BinOp(Op::Add),
+            Instruction::IStore(2),
+            Instruction::ILoad(2),
+            Instruction::Push(128),
+            Instruction::BinOp(Op::Sub),
+            Instruction::IStore(2),
+            Instruction::ILoad(2),
+            Instruction::Push(128),
+            Instruction::BinOp(Op::Add),
+            Instruction::IStore(2),
+            Instruction::ILoad(2),
+            Instruction::Push(1),
+            Instruction::BinOp(Op::Add),
+            Instruction::IStore(3),
+            Instruction::ILoad(2),
+            Instruction::Push(1),
+            Instruction::BinOp(Op::Mul),
+            Instruction::IStore(2),
+        ];
+
+        let (optimized, _) = self::optimized(peephole, instructions.clone(), 4);
+        assert_eq!(
+            optimized,
+            [
+                Instruction::IInc(2, 1),
+                Instruction::IInc(2, -128),
+                Instruction::IInc(2, -128),
+                Instruction::ILoad(2),
+                Instruction::Push(128),
+                Instruction::BinOp(Op::Add),
+                Instruction::IStore(2),
+                Instruction::ILoad(2),
+                Instruction::Push(1),
+                Instruction::BinOp(Op::Add),
+                Instruction::IStore(3),
+                Instruction::ILoad(2),
+                Instruction::Push(1),
+                Instruction::BinOp(Op::Mul),
+                Instruction::IStore(2),
+            ]
+        );
+        assert_eq!(Instruction::IInc(2, -128).to_string(), "iinc 2 -128");
+        assert_eq!(stack_limit(&optimized), 2);
+
+        assert_eq!(
+            self::optimized(Peephole::NONE, instructions.clone(), 4),
+            (instructions, 4)
+        );
+    }
+}
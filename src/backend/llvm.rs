@@ -1,17 +1,21 @@
 use super::Backend;
 use crate::{
-    ast::{Exp, Op, Stmt},
-    UndeclaredVariableError,
-};
-use std::{
-    collections::HashMap,
-    fmt::{self, Display, Formatter},
+    ast::{self, Op},
+    ir::{Instruction as IrInstruction, MachineOp, Operand, Program},
+    Arithmetic,
 };
+use std::fmt::{self, Display, Formatter};
 
+/// Operand of an [Instruction]: a register or an immediate, like in the [Program] IR.
 #[derive(Clone, Copy)]
-enum Location {
+pub(crate) enum Location {
     Register(usize),
     Immediate(i32),
+    /// Pointer to a constant arbitrary-precision integer.
+    Literal {
+        index: usize,
+        limbs: usize,
+    },
 }
 
 impl Display for Location {
@@ -19,104 +23,268 @@ impl Display for Location {
         match self {
             Self::Register(num) => write!(f, "%t{}", num),
             Self::Immediate(val) => write!(f, "{}", val),
+            Self::Literal { index, limbs } => write!(
+                f,
+                "bitcast ({{ i32, i32, [{} x i32] }}* @lit{} to %big*)",
+                limbs, index
+            ),
+        }
+    }
+}
+
+/// Arbitrary-precision integer in the format used by the bundled runtime.
+struct BigLiteral {
+    /// -1, 0 or 1.
+    sign: i32,
+    /// Little-endian digits in base 10^9, without leading zeros.
+    limbs: Vec<u32>,
+}
+
+impl BigLiteral {
+    fn new(negative: bool, digits: &str) -> Self {
+        let digits = digits.trim_start_matches('0').as_bytes();
+        let limbs = digits
+            .rchunks(9)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .fold(0, |acc, digit| acc * 10 + u32::from(digit - b'0'))
+            })
+            .collect::<Vec<_>>();
+        let sign = match (limbs.is_empty(), negative) {
+            (true, _) => 0,
+            (false, true) => -1,
+            (false, false) => 1,
+        };
+
+        Self { sign, limbs }
+    }
+}
+
+impl Display for BigLiteral {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{{ i32, i32, [{0} x i32] }} {{ i32 {1}, i32 {0}, [{0} x i32] ",
+            self.limbs.len(),
+            self.sign,
+        )?;
+        if self.limbs.is_empty() {
+            f.write_str("zeroinitializer")?;
+        } else {
+            f.write_str("[")?;
+            for (i, limb) in self.limbs.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "i32 {}", limb)?;
+            }
+            f.write_str("]")?;
         }
+        f.write_str(" }")
     }
 }
 
 /// Backend for generating LLVM IR from an Instant program.
+/// Divisions by zero print an error and exit the program with status 1,
+/// in [Arithmetic::Checked] mode so do overflows.
+/// In [Arithmetic::Unbounded] mode, a bundled arbitrary-precision runtime is used.
 #[derive(Default)]
 pub struct LLVMBackend;
 
 impl Backend for LLVMBackend {
     type Representation = LLVMIr;
 
-    fn process<'a>(&self, program: &[Stmt<'a>]) -> Result<LLVMIr, UndeclaredVariableError<'a>> {
-        let mut builder = LLVMIrBuilder::default();
+    fn process(&self, program: &Program) -> LLVMIr {
+        let mut builder = LLVMIrBuilder {
+            program,
+            instructions: Default::default(),
+            literals: Default::default(),
+            divisions: false,
+        };
 
-        for stmt in program {
-            builder.add_stmt(stmt)?;
+        for instruction in &program.instructions {
+            builder.add_instruction(instruction);
         }
 
-        Ok(builder.build())
+        builder.build()
     }
 }
 
-#[derive(Default)]
-struct LLVMIrBuilder<'a> {
-    variables: HashMap<&'a str, Location>,
-    next_register: usize,
+struct LLVMIrBuilder<'p> {
+    program: &'p Program,
     instructions: Vec<Instruction>,
+    literals: Vec<BigLiteral>,
+    divisions: bool,
 }
 
-impl<'a> LLVMIrBuilder<'a> {
-    fn add_exp(&mut self, exp: &Exp<'a>) -> Result<Location, UndeclaredVariableError<'a>> {
-        match exp {
-            Exp::Lit(val) => Ok(Location::Immediate(*val)),
-            Exp::Var { name, position } => {
-                self.variables
-                    .get(name)
-                    .copied()
-                    .ok_or(UndeclaredVariableError {
-                        name: *name,
-                        byte_offset: *position,
-                    })
-            }
-            Exp::Bi { lhs, op, rhs } => {
-                let lhs = self.add_exp(lhs)?;
-                let rhs = self.add_exp(rhs)?;
+impl<'p> LLVMIrBuilder<'p> {
+    fn add_literal(&mut self, literal: BigLiteral) -> Location {
+        let location = Location::Literal {
+            index: self.literals.len(),
+            limbs: literal.limbs.len(),
+        };
+        self.literals.push(literal);
 
-                let dst_register = self.next_register;
-                self.next_register += 1;
-                let dst = Location::Register(dst_register);
+        location
+    }
 
-                self.instructions.push(Instruction::Bin {
-                    lhs,
-                    op: *op,
-                    rhs,
-                    dst,
-                });
+    fn location(&mut self, operand: Operand) -> Location {
+        let unbounded = self.program.arithmetic == Arithmetic::Unbounded;
 
-                Ok(dst)
+        match operand {
+            Operand::Value(value) => Location::Register(value.0),
+            Operand::Const(val) if unbounded => {
+                self.add_literal(BigLiteral::new(val < 0, &val.unsigned_abs().to_string()))
+            }
+            Operand::Const(val) => Location::Immediate(val),
+            Operand::BigConst(idx) if unbounded => {
+                let constant = &self.program.big_constants[idx];
+                let digits = constant.trim_start_matches('-');
+                self.add_literal(BigLiteral::new(digits.len() < constant.len(), digits))
+            }
+            Operand::BigConst(idx) => {
+                Location::Immediate(ast::wrapped_literal(&self.program.big_constants[idx]))
             }
         }
     }
 
-    fn add_stmt(&mut self, stmt: &Stmt<'a>) -> Result<(), UndeclaredVariableError<'a>> {
-        match stmt {
-            Stmt::Exp(exp) => {
-                let loc = self.add_exp(exp)?;
-                self.instructions.push(Instruction::Print(loc));
+    fn add_instruction(&mut self, instruction: &IrInstruction) {
+        match instruction {
+            IrInstruction::Bin { dst, op, lhs, rhs } => {
+                // Divisions by other constants can neither fail nor overflow.
+                let guarded =
+                    *op == Op::Div && !matches!(*rhs, Operand::Const(val) if val != 0 && val != -1);
+                let lhs = self.location(*lhs);
+                let rhs = self.location(*rhs);
+
+                self.instructions.push(match self.program.arithmetic {
+                    Arithmetic::Unbounded => Instruction::BigBin {
+                        lhs,
+                        op: *op,
+                        rhs,
+                        dst: Location::Register(dst.0),
+                    },
+                    arithmetic if guarded => {
+                        self.divisions = true;
+                        Instruction::Div {
+                            lhs,
+                            rhs,
+                            dst: dst.0,
+                            checked: arithmetic == Arithmetic::Checked,
+                        }
+                    }
+                    Arithmetic::Checked if *op != Op::Div => Instruction::CheckedBin {
+                        lhs,
+                        op: *op,
+                        rhs,
+                        dst: dst.0,
+                    },
+                    _ => Instruction::Bin {
+                        lhs,
+                        op: *op,
+                        rhs,
+                        dst: Location::Register(dst.0),
+                    },
+                });
+            }
+            IrInstruction::Machine { dst, op, lhs, rhs } => {
+                assert!(
+                    self.program.arithmetic != Arithmetic::Unbounded,
+                    "machine operations are not supported in unbounded arithmetic"
+                );
+                let lhs = self.location(*lhs);
+                let rhs = self.location(*rhs);
+
+                self.instructions.push(Instruction::Machine {
+                    lhs,
+                    op: *op,
+                    rhs,
+                    dst: dst.0,
+                });
             }
-            Stmt::Ass { var, exp } => {
-                let loc = self.add_exp(exp)?;
-                self.variables.insert(*var, loc);
+            IrInstruction::Print(operand) => {
+                let loc = self.location(*operand);
+                self.instructions.push(match self.program.arithmetic {
+                    Arithmetic::Unbounded => Instruction::BigPrint(loc),
+                    _ => Instruction::Print(loc),
+                });
             }
         }
-
-        Ok(())
     }
 
     fn build(self) -> LLVMIr {
         LLVMIr {
+            arithmetic: self.program.arithmetic,
             instructions: self.instructions,
+            literals: self.literals,
+            divisions: self.divisions,
         }
     }
 }
 
-enum Instruction {
+/// Instruction of `main`, also translated to native code by the [JIT](crate::jit).
+pub(crate) enum Instruction {
     Print(Location),
+    BigPrint(Location),
     Bin {
         lhs: Location,
         op: Op,
         rhs: Location,
         dst: Location,
     },
+    /// Binary operation branching to the `overflow` block if the result does not fit in 32 bits.
+    /// Continues in a new block labeled after the destination register.
+    CheckedBin {
+        lhs: Location,
+        op: Op,
+        rhs: Location,
+        dst: usize,
+    },
+    /// Binary operation on arbitrary-precision integers.
+    BigBin {
+        lhs: Location,
+        op: Op,
+        rhs: Location,
+        dst: Location,
+    },
+    /// Division branching to the `divzero` block if the divisor is zero.
+    /// When `checked`, it also branches to the `overflow` block if the quotient does not fit
+    /// in 32 bits, otherwise the quotient wraps around.
+    /// Continues in new blocks labeled after the destination register.
+    Div {
+        lhs: Location,
+        rhs: Location,
+        dst: usize,
+        checked: bool,
+    },
+    /// Low-level operation, using helper registers prefixed with the destination register.
+    Machine {
+        lhs: Location,
+        op: MachineOp,
+        rhs: Location,
+        dst: usize,
+    },
 }
 
 impl Display for Instruction {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match self {
             Self::Print(loc) => write!(f, "call void @printInt(i32 {})", loc),
+            Self::BigPrint(loc) => write!(f, "call void @big.print(%big* {})", loc),
+            Self::BigBin { lhs, op, rhs, dst } => write!(
+                f,
+                "{} = call %big* @big.{}(%big* {}, %big* {})",
+                dst,
+                match op {
+                    Op::Add => "add",
+                    Op::Sub => "sub",
+                    Op::Mul => "mul",
+                    Op::Div => "div",
+                },
+                lhs,
+                rhs,
+            ),
             Self::Bin { lhs, op, rhs, dst } => write!(
                 f,
                 "{} = {} i32 {}, {}",
@@ -130,13 +298,101 @@ impl Display for Instruction {
                 lhs,
                 rhs,
             ),
+            Self::Machine {
+                lhs,
+                op: MachineOp::MulHigh,
+                rhs,
+                dst,
+            } => {
+                writeln!(f, "%t{}.l = sext i32 {} to i64", dst, lhs)?;
+                writeln!(f, "\t%t{}.r = sext i32 {} to i64", dst, rhs)?;
+                writeln!(f, "\t%t{0}.p = mul i64 %t{0}.l, %t{0}.r", dst)?;
+                writeln!(f, "\t%t{0}.h = ashr i64 %t{0}.p, 32", dst)?;
+                write!(f, "\t%t{0} = trunc i64 %t{0}.h to i32", dst)
+            }
+            Self::Machine { lhs, op, rhs, dst } => {
+                // Shifts by 32 bits or more are undefined in LLVM.
+                writeln!(f, "%t{}.s = and i32 {}, 31", dst, rhs)?;
+                write!(
+                    f,
+                    "\t%t{0} = {1} i32 {2}, %t{0}.s",
+                    dst,
+                    match op {
+                        MachineOp::Shl => "shl",
+                        MachineOp::Sar => "ashr",
+                        MachineOp::Shr => "lshr",
+                        MachineOp::MulHigh =>
+                            unreachable!("high multiplication is handled separately"),
+                    },
+                    lhs,
+                )
+            }
+            Self::Div {
+                lhs,
+                rhs,
+                dst,
+                checked: true,
+            } => {
+                writeln!(f, "%t{}.z = icmp eq i32 {}, 0", dst, rhs)?;
+                writeln!(f, "\tbr i1 %t{0}.z, label %divzero, label %t{0}.nz", dst)?;
+                writeln!(f, "t{}.nz:", dst)?;
+                writeln!(f, "\t%t{}.l = icmp eq i32 {}, {}", dst, lhs, i32::MIN)?;
+                writeln!(f, "\t%t{}.r = icmp eq i32 {}, -1", dst, rhs)?;
+                writeln!(f, "\t%t{0}.o = and i1 %t{0}.l, %t{0}.r", dst)?;
+                writeln!(f, "\tbr i1 %t{0}.o, label %overflow, label %t{0}.ok", dst)?;
+                writeln!(f, "t{}.ok:", dst)?;
+                write!(f, "\t%t{} = sdiv i32 {}, {}", dst, lhs, rhs)
+            }
+            Self::Div {
+                lhs,
+                rhs,
+                dst,
+                checked: false,
+            } => {
+                // sdiv of the minimum by -1 is undefined, so the division by -1 is a negation.
+                writeln!(f, "%t{}.z = icmp eq i32 {}, 0", dst, rhs)?;
+                writeln!(f, "\tbr i1 %t{0}.z, label %divzero, label %t{0}.nz", dst)?;
+                writeln!(f, "t{}.nz:", dst)?;
+                writeln!(f, "\t%t{}.m = icmp eq i32 {}, -1", dst, rhs)?;
+                writeln!(f, "\t%t{0}.d = select i1 %t{0}.m, i32 1, i32 {1}", dst, rhs)?;
+                writeln!(f, "\t%t{0}.q = sdiv i32 {1}, %t{0}.d", dst, lhs)?;
+                writeln!(f, "\t%t{}.n = sub i32 0, {}", dst, lhs)?;
+                write!(
+                    f,
+                    "\t%t{0} = select i1 %t{0}.m, i32 %t{0}.n, i32 %t{0}.q",
+                    dst
+                )
+            }
+            Self::CheckedBin { lhs, op, rhs, dst } => {
+                writeln!(
+                    f,
+                    "%t{}.r = call {{i32, i1}} @llvm.{}.with.overflow.i32(i32 {}, i32 {})",
+                    dst,
+                    match op {
+                        Op::Add => "sadd",
+                        Op::Sub => "ssub",
+                        Op::Mul => "smul",
+                        Op::Div => unreachable!("division is handled separately"),
+                    },
+                    lhs,
+                    rhs,
+                )?;
+                writeln!(f, "\t%t{0} = extractvalue {{i32, i1}} %t{0}.r, 0", dst)?;
+                writeln!(f, "\t%t{0}.o = extractvalue {{i32, i1}} %t{0}.r, 1", dst)?;
+                writeln!(f, "\tbr i1 %t{0}.o, label %overflow, label %t{0}.ok", dst)?;
+                write!(f, "t{}.ok:", dst)
+            }
         }
     }
 }
 
 /// LLVM Intermediate Representation of an Instant program.
 pub struct LLVMIr {
-    instructions: Vec<Instruction>,
+    arithmetic: Arithmetic,
+    pub(crate) instructions: Vec<Instruction>,
+    literals: Vec<BigLiteral>,
+    /// Whether any division branches to the `divzero` block.
+    divisions: bool,
 }
 
 impl Display for LLVMIr {
@@ -153,28 +409,128 @@ impl Display for LLVMIr {
         writeln!(f, "\tret void")?;
         writeln!(f, "}}\n")?;
 
+        if self.divisions {
+            writeln!(
+                f,
+                "@divzero.msg = internal constant [18 x i8] c\"division by zero\\0A\\00\"\n"
+            )?;
+        }
+        if self.arithmetic == Arithmetic::Checked || self.divisions {
+            writeln!(f, "declare i64 @write(i32, i8*, i64)")?;
+            writeln!(f, "declare void @exit(i32)\n")?;
+        }
+
+        if self.arithmetic == Arithmetic::Checked {
+            writeln!(
+                f,
+                "@overflow.msg = internal constant [18 x i8] c\"integer overflow\\0A\\00\"\n"
+            )?;
+            for op in ["sadd", "ssub", "smul"] {
+                writeln!(
+                    f,
+                    "declare {{i32, i1}} @llvm.{}.with.overflow.i32(i32, i32)",
+                    op
+                )?;
+            }
+            writeln!(f)?;
+        }
+
+        if self.arithmetic == Arithmetic::Unbounded {
+            writeln!(f, "{}", include_str!("bigint.ll"))?;
+            for (i, literal) in self.literals.iter().enumerate() {
+                writeln!(f, "@lit{} = internal constant {}", i, literal)?;
+            }
+            writeln!(f)?;
+        }
+
         writeln!(f, "define i32 @main(i32 %argc, i8** %argv) {{")?;
         for instruction in &self.instructions {
             writeln!(f, "\t{}", instruction)?;
         }
         writeln!(f, "\tret i32 0")?;
+        if self.arithmetic == Arithmetic::Checked {
+            writeln!(f, "overflow:")?;
+            writeln!(
+                f,
+                "\t%overflow.msg = getelementptr [18 x i8], [18 x i8]* @overflow.msg, i32 0, i32 0"
+            )?;
+            writeln!(f, "\tcall i64 @write(i32 2, i8* %overflow.msg, i64 17)")?;
+            writeln!(f, "\tcall void @exit(i32 1)")?;
+            writeln!(f, "\tunreachable")?;
+        }
+        if self.divisions {
+            writeln!(f, "divzero:")?;
+            writeln!(
+                f,
+                "\t%divzero.msg = getelementptr [18 x i8], [18 x i8]* @divzero.msg, i32 0, i32 0"
+            )?;
+            writeln!(f, "\tcall i64 @write(i32 2, i8* %divzero.msg, i64 17)")?;
+            writeln!(f, "\tcall void @exit(i32 1)")?;
+            writeln!(f, "\tunreachable")?;
+        }
         writeln!(f, "}}")
     }
 }
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::parser;
+
+    fn program(source: &str, arithmetic: Arithmetic) -> Program {
+        let stmts = parser::parse(source, arithmetic).unwrap();
+        Program::lower(&stmts, arithmetic).unwrap()
+    }
+
+    #[test]
+    fn register_propagation() {
+        let ir = LLVMBackend.process(&program("a = 2; b = a / 3; b", Arithmetic::Wrapping));
+
+        assert_eq!(
+            ir.instructions
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["%t0 = sdiv i32 2, 3", "call void @printInt(i32 %t0)"]
+        );
+    }
 
     #[test]
-    fn undeclared_variable() {
-        let mut builder = LLVMIrBuilder::default();
-        let error = builder
-            .add_stmt(&Stmt::Exp(Exp::Var {
-                name: "name",
-                position: 0,
-            }))
-            .expect_err("undeclared variable access should result in an error");
-        assert_eq!(error.name, "name");
-        assert_eq!(error.byte_offset, 0);
+    fn division_guards() {
+        let guards = |arithmetic| {
+            let ir = LLVMBackend.process(&program("7 / 2; 7 / 0", arithmetic));
+            let guards = ir
+                .instructions
+                .iter()
+                .filter(|instruction| matches!(instruction, Instruction::Div { .. }))
+                .count();
+            (guards, ir.divisions, ir.to_string().contains("divzero:"))
+        };
+
+        assert_eq!(guards(Arithmetic::Wrapping), (1, true, true));
+        assert_eq!(guards(Arithmetic::Checked), (1, true, true));
+        // The runtime checks divisions of arbitrary-precision integers.
+        assert_eq!(guards(Arithmetic::Unbounded), (0, false, false));
+    }
+
+    #[test]
+    fn big_literals() {
+        let literal = BigLiteral::new(false, "0001234567890123456789");
+        assert_eq!(literal.sign, 1);
+        assert_eq!(literal.limbs, [123456789, 234567890, 1]);
+        assert_eq!(
+            literal.to_string(),
+            "{ i32, i32, [3 x i32] } { i32 1, i32 3, [3 x i32] [i32 123456789, i32 234567890, i32 1] }"
+        );
+
+        let literal = BigLiteral::new(true, "000");
+        assert_eq!(literal.sign, 0);
+        assert_eq!(
+            literal.to_string(),
+            "{ i32, i32, [0 x i32] } { i32 0, i32 0, [0 x i32] zeroinitializer }"
+        );
+
+        assert_eq!(BigLiteral::new(true, "2147483648").sign, -1);
+        assert_eq!(ast::wrapped_literal("4294967338"), 42);
+        assert_eq!(ast::wrapped_literal("2147483648"), i32::MIN);
     }
 }
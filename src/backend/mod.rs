@@ -1,7 +1,14 @@
+pub mod bytecode;
+pub mod c;
+pub mod elf;
 pub mod jasmin;
 pub mod llvm;
+pub mod riscv;
+mod stack;
+pub mod wasm;
+pub mod x86_64;
 
-use crate::{ast::Stmt, UndeclaredVariableError};
+use crate::ir::Program;
 use std::fmt::Display;
 
 /// Trait for genereting specific representation from an Instant program.
@@ -9,8 +16,5 @@ pub trait Backend {
     type Representation: Display;
 
     /// This method generated a specific representation of the given Instant program.
-    fn process<'a>(
-        &self,
-        program: &[Stmt<'a>],
-    ) -> Result<Self::Representation, UndeclaredVariableError<'a>>;
+    fn process(&self, program: &Program) -> Self::Representation;
 }
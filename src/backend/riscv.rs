@@ -0,0 +1,1139 @@
+use super::Backend;
+use crate::{
+    ast::{self, Op},
+    ir::{Instruction as IrInstruction, MachineOp, Operand, Program},
+    Arithmetic,
+};
+use std::fmt::{self, Display, Formatter};
+
+/// Backend for generating GNU assembler for RV32IM, following the Linux system call convention:
+/// printing and exiting use the `write` (64) and `exit` (93) calls through `ecall`.
+/// The [simulator](crate::simulator) executes the generated code.
+///
+/// Values live in registers, allocated with a linear scan in the order of the instructions:
+/// a register is freed after the last use of its value and the first free one is taken.
+/// When all of them are taken, the value is spilled to a 32-bit slot on the stack.
+///
+/// Division by zero and, in [Arithmetic::Checked] mode, overflows print an error to STDERR
+/// and exit with code 1. In [Arithmetic::Wrapping] mode, `div` already returns the minimum
+/// when dividing it by -1. [Arithmetic::Unbounded] mode is not supported.
+pub struct RiscVBackend {
+    registers: usize,
+}
+
+impl Default for RiscVBackend {
+    fn default() -> Self {
+        Self {
+            registers: Register::ALLOCATABLE.len(),
+        }
+    }
+}
+
+impl RiscVBackend {
+    /// Limits the number of registers holding values, at least 1.
+    pub fn with_registers(self, registers: usize) -> Self {
+        assert!(
+            (1..=Register::ALLOCATABLE.len()).contains(&registers),
+            "invalid number of registers"
+        );
+        Self { registers }
+    }
+}
+
+impl Backend for RiscVBackend {
+    type Representation = Assembly;
+
+    fn process(&self, program: &Program) -> Assembly {
+        assert!(
+            program.arithmetic != Arithmetic::Unbounded,
+            "unbounded arithmetic is not supported by the RISC-V backend"
+        );
+
+        let mut last_uses = vec![None; program.value_count()];
+        for (i, instruction) in program.instructions.iter().enumerate() {
+            for operand in instruction.operands() {
+                if let Operand::Value(value) = operand {
+                    last_uses[value.0] = Some(i);
+                }
+            }
+        }
+
+        let mut builder = RiscVBuilder {
+            program,
+            last_uses,
+            locations: vec![None; program.value_count()],
+            free: vec![true; self.registers],
+            free_slots: Vec::new(),
+            slots: 0,
+            labels: 0,
+            divisions: false,
+            instructions: Vec::new(),
+        };
+        for (i, instruction) in program.instructions.iter().enumerate() {
+            builder.add_instruction(i, instruction);
+        }
+
+        builder.build()
+    }
+}
+
+/// Integer register, in the order of the machine encoding, with its ABI name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    Zero,
+    Ra,
+    Sp,
+    Gp,
+    Tp,
+    T0,
+    T1,
+    T2,
+    S0,
+    S1,
+    A0,
+    A1,
+    A2,
+    A3,
+    A4,
+    A5,
+    A6,
+    A7,
+    S2,
+    S3,
+    S4,
+    S5,
+    S6,
+    S7,
+    S8,
+    S9,
+    S10,
+    S11,
+    T3,
+    T4,
+    T5,
+    T6,
+}
+
+impl Register {
+    /// Registers holding values, in the order of allocation. The temporaries are left for
+    /// constants, spilled operands and checks, and `a0`-`a2` and `a7` for the system calls.
+    pub const ALLOCATABLE: [Self; 15] = [
+        Self::S1,
+        Self::S2,
+        Self::S3,
+        Self::S4,
+        Self::S5,
+        Self::S6,
+        Self::S7,
+        Self::S8,
+        Self::S9,
+        Self::S10,
+        Self::S11,
+        Self::A3,
+        Self::A4,
+        Self::A5,
+        Self::A6,
+    ];
+
+    /// Returns the number of the register in the machine encoding.
+    pub fn number(self) -> usize {
+        self as usize
+    }
+
+    /// Returns the ABI name of the register.
+    pub fn name(self) -> &'static str {
+        [
+            "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3",
+            "a4", "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11",
+            "t3", "t4", "t5", "t6",
+        ][self as usize]
+    }
+}
+
+impl Display for Register {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Label {
+    Named(&'static str),
+    Numbered(usize),
+}
+
+impl Display for Label {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Named(name) => f.write_str(name),
+            Self::Numbered(num) => write!(f, ".L{}", num),
+        }
+    }
+}
+
+/// Register-register operation of RV32I and the M extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegOp {
+    Add,
+    Sub,
+    Sll,
+    Slt,
+    Sltu,
+    Xor,
+    Srl,
+    Sra,
+    Or,
+    And,
+    Mul,
+    Mulh,
+    Mulhsu,
+    Mulhu,
+    Div,
+    Divu,
+    Rem,
+    Remu,
+}
+
+impl RegOp {
+    fn mnemonic(self) -> &'static str {
+        match self {
+            Self::Add => "add",
+            Self::Sub => "sub",
+            Self::Sll => "sll",
+            Self::Slt => "slt",
+            Self::Sltu => "sltu",
+            Self::Xor => "xor",
+            Self::Srl => "srl",
+            Self::Sra => "sra",
+            Self::Or => "or",
+            Self::And => "and",
+            Self::Mul => "mul",
+            Self::Mulh => "mulh",
+            Self::Mulhsu => "mulhsu",
+            Self::Mulhu => "mulhu",
+            Self::Div => "div",
+            Self::Divu => "divu",
+            Self::Rem => "rem",
+            Self::Remu => "remu",
+        }
+    }
+}
+
+/// Register-immediate operation of RV32I.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImmOp {
+    Addi,
+    Slti,
+    Sltiu,
+    Xori,
+    Ori,
+    Andi,
+    Slli,
+    Srli,
+    Srai,
+}
+
+impl ImmOp {
+    fn mnemonic(self) -> &'static str {
+        match self {
+            Self::Addi => "addi",
+            Self::Slti => "slti",
+            Self::Sltiu => "sltiu",
+            Self::Xori => "xori",
+            Self::Ori => "ori",
+            Self::Andi => "andi",
+            Self::Slli => "slli",
+            Self::Srli => "srli",
+            Self::Srai => "srai",
+        }
+    }
+}
+
+/// Width of a memory access, signed or unsigned for loads narrower than a word.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    Byte,
+    ByteUnsigned,
+    Half,
+    HalfUnsigned,
+    Word,
+}
+
+impl Width {
+    fn load(self) -> &'static str {
+        match self {
+            Self::Byte => "lb",
+            Self::ByteUnsigned => "lbu",
+            Self::Half => "lh",
+            Self::HalfUnsigned => "lhu",
+            Self::Word => "lw",
+        }
+    }
+
+    fn store(self) -> &'static str {
+        match self {
+            Self::Byte | Self::ByteUnsigned => "sb",
+            Self::Half | Self::HalfUnsigned => "sh",
+            Self::Word => "sw",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    Equal,
+    NotEqual,
+    Less,
+    GreaterEqual,
+    LessUnsigned,
+    GreaterEqualUnsigned,
+}
+
+impl Condition {
+    fn mnemonic(self) -> &'static str {
+        match self {
+            Self::Equal => "beq",
+            Self::NotEqual => "bne",
+            Self::Less => "blt",
+            Self::GreaterEqual => "bge",
+            Self::LessUnsigned => "bltu",
+            Self::GreaterEqualUnsigned => "bgeu",
+        }
+    }
+
+    /// Returns the condition holding exactly when this one does not.
+    fn inverse(self) -> Self {
+        match self {
+            Self::Equal => Self::NotEqual,
+            Self::NotEqual => Self::Equal,
+            Self::Less => Self::GreaterEqual,
+            Self::GreaterEqual => Self::Less,
+            Self::LessUnsigned => Self::GreaterEqualUnsigned,
+            Self::GreaterEqualUnsigned => Self::LessUnsigned,
+        }
+    }
+}
+
+/// RV32IM instruction. Immediates are assumed to fit in their fields:
+/// 12 bits for operations, loads and stores, 5 bits for shifts and 20 bits for `lui`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Label(Label),
+    Op {
+        op: RegOp,
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+    },
+    OpImm {
+        op: ImmOp,
+        dst: Register,
+        src: Register,
+        imm: i32,
+    },
+    /// Loads the 20-bit immediate into the upper bits of the register.
+    Lui {
+        dst: Register,
+        imm: u32,
+    },
+    Load {
+        width: Width,
+        dst: Register,
+        base: Register,
+        offset: i32,
+    },
+    Store {
+        width: Width,
+        src: Register,
+        base: Register,
+        offset: i32,
+    },
+    Branch {
+        condition: Condition,
+        lhs: Register,
+        rhs: Register,
+        target: Label,
+    },
+    /// Jumps to the label, saving the address of the next instruction in the register.
+    Jal {
+        dst: Register,
+        target: Label,
+    },
+    /// Jumps to the address in the register plus the offset, saving the address
+    /// of the next instruction in `dst`.
+    Jalr {
+        dst: Register,
+        base: Register,
+        offset: i32,
+    },
+    /// `la` pseudo-instruction, loading the address of the label.
+    LoadAddress {
+        dst: Register,
+        label: Label,
+    },
+    /// `call` pseudo-instruction, expanding to `auipc` and `jalr` to call the label
+    /// anywhere in the address space, saving the return address in `ra`.
+    Call {
+        target: Label,
+    },
+    /// `jump` pseudo-instruction, expanding to `auipc` of the address into the register
+    /// and `jalr` to jump to the label anywhere in the address space.
+    Jump {
+        target: Label,
+        scratch: Register,
+    },
+    Ecall,
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Label(label) => write!(f, "{}:", label),
+            Self::Op { op, dst, lhs, rhs } => {
+                write!(f, "\t{} {}, {}, {}", op.mnemonic(), dst, lhs, rhs)
+            }
+            Self::OpImm { op, dst, src, imm } => {
+                write!(f, "\t{} {}, {}, {}", op.mnemonic(), dst, src, imm)
+            }
+            Self::Lui { dst, imm } => write!(f, "\tlui {}, {}", dst, imm),
+            Self::Load {
+                width,
+                dst,
+                base,
+                offset,
+            } => write!(f, "\t{} {}, {}({})", width.load(), dst, offset, base),
+            Self::Store {
+                width,
+                src,
+                base,
+                offset,
+            } => write!(f, "\t{} {}, {}({})", width.store(), src, offset, base),
+            Self::Branch {
+                condition,
+                lhs,
+                rhs,
+                target,
+            } => write!(f, "\t{} {}, {}, {}", condition.mnemonic(), lhs, rhs, target),
+            Self::Jal { dst, target } => write!(f, "\tjal {}, {}", dst, target),
+            Self::Jalr { dst, base, offset } => {
+                write!(f, "\tjalr {}, {}({})", dst, offset, base)
+            }
+            Self::LoadAddress { dst, label } => write!(f, "\tla {}, {}", dst, label),
+            Self::Call { target } => write!(f, "\tcall {}", target),
+            Self::Jump { target, scratch } => write!(f, "\tjump {}, {}", target, scratch),
+            Self::Ecall => f.write_str("\tecall"),
+        }
+    }
+}
+
+const START: Label = Label::Named("_start");
+const PRINT_INT: Label = Label::Named("printInt");
+const DIVZERO: Label = Label::Named("divzero");
+const OVERFLOW: Label = Label::Named("overflow");
+const FAIL: Label = Label::Named("fail");
+const DIVZERO_MSG: Label = Label::Named("divzero.msg");
+const OVERFLOW_MSG: Label = Label::Named("overflow.msg");
+const PRINT_INT_DIGIT: Label = Label::Named("printInt.digit");
+const PRINT_INT_WRITE: Label = Label::Named("printInt.write");
+
+/// System call numbers of Linux.
+const WRITE: i32 = 64;
+const EXIT: i32 = 93;
+
+fn addi(dst: Register, src: Register, imm: i32) -> Instruction {
+    Instruction::OpImm {
+        op: ImmOp::Addi,
+        dst,
+        src,
+        imm,
+    }
+}
+
+fn op(op: RegOp, dst: Register, lhs: Register, rhs: Register) -> Instruction {
+    Instruction::Op { op, dst, lhs, rhs }
+}
+
+fn branch(condition: Condition, lhs: Register, rhs: Register, target: Label) -> Instruction {
+    Instruction::Branch {
+        condition,
+        lhs,
+        rhs,
+        target,
+    }
+}
+
+/// Returns whether the value fits in a 12-bit immediate.
+fn short(val: i32) -> bool {
+    (-2048..2048).contains(&val)
+}
+
+/// Returns the instructions loading the constant into the register, like the `li` pseudo-instruction.
+fn load_immediate(dst: Register, val: i32) -> Vec<Instruction> {
+    if short(val) {
+        return vec![addi(dst, Register::Zero, val)];
+    }
+
+    // `addi` sign-extends the lower 12 bits, which the upper part compensates.
+    let low = (val << 20) >> 20;
+    let mut instructions = vec![Instruction::Lui {
+        dst,
+        imm: (val.wrapping_sub(low) as u32) >> 12,
+    }];
+    if low != 0 {
+        instructions.push(addi(dst, dst, low));
+    }
+
+    instructions
+}
+
+/// Returns `printInt`, printing `a0` in a separate line with a single `write` system call.
+/// The digits are written backwards into a buffer on the stack, from the absolute value
+/// treated as unsigned, so that the minimum needs no special case.
+fn print_int() -> Vec<Instruction> {
+    use Instruction::*;
+    use Register::*;
+
+    let store_byte = |src| Store {
+        width: Width::Byte,
+        src,
+        base: T0,
+        offset: 0,
+    };
+
+    vec![
+        Label(PRINT_INT),
+        addi(Sp, Sp, -16),
+        addi(T0, Sp, 15),
+        addi(T1, Zero, 10),
+        store_byte(T1),
+        addi(T2, A0, 0),
+        branch(Condition::GreaterEqual, A0, Zero, PRINT_INT_DIGIT),
+        op(RegOp::Sub, T2, Zero, A0),
+        Label(PRINT_INT_DIGIT),
+        op(RegOp::Remu, T3, T2, T1),
+        op(RegOp::Divu, T2, T2, T1),
+        addi(T3, T3, b'0'.into()),
+        addi(T0, T0, -1),
+        store_byte(T3),
+        branch(Condition::NotEqual, T2, Zero, PRINT_INT_DIGIT),
+        branch(Condition::GreaterEqual, A0, Zero, PRINT_INT_WRITE),
+        addi(T3, Zero, b'-'.into()),
+        addi(T0, T0, -1),
+        store_byte(T3),
+        Label(PRINT_INT_WRITE),
+        addi(A0, Zero, 1),
+        addi(A1, T0, 0),
+        addi(A2, Sp, 16),
+        op(RegOp::Sub, A2, A2, T0),
+        addi(A7, Zero, WRITE),
+        Ecall,
+        addi(Sp, Sp, 16),
+        Jalr {
+            dst: Zero,
+            base: Ra,
+            offset: 0,
+        },
+    ]
+}
+
+/// Returns the handlers of the runtime errors the program may jump to,
+/// writing the message to STDERR and exiting with code 1.
+fn error_handlers(divisions: bool, checked: bool) -> Vec<Instruction> {
+    use Instruction::*;
+    use Register::*;
+
+    let mut instructions = Vec::new();
+    let messages = [
+        (divisions, DIVZERO, DIVZERO_MSG),
+        (checked, OVERFLOW, OVERFLOW_MSG),
+    ];
+    for (used, label, message) in messages {
+        if used {
+            instructions.extend([
+                Label(label),
+                LoadAddress {
+                    dst: A1,
+                    label: message,
+                },
+                Jal {
+                    dst: Zero,
+                    target: FAIL,
+                },
+            ]);
+        }
+    }
+    if instructions.is_empty() {
+        return instructions;
+    }
+
+    // Both messages are 17 bytes long.
+    instructions.extend([
+        Label(FAIL),
+        addi(A0, Zero, 2),
+        addi(A2, Zero, 17),
+        addi(A7, Zero, WRITE),
+        Ecall,
+        addi(A0, Zero, 1),
+        addi(A7, Zero, EXIT),
+        Ecall,
+    ]);
+
+    instructions
+}
+
+/// Place of a value: a register or a slot on the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Location {
+    Register(Register),
+    Slot(u32),
+}
+
+struct RiscVBuilder<'p> {
+    program: &'p Program,
+    /// Index of the last instruction using each value.
+    last_uses: Vec<Option<usize>>,
+    locations: Vec<Option<Location>>,
+    /// Whether each of the allowed [Register::ALLOCATABLE] registers is free.
+    free: Vec<bool>,
+    free_slots: Vec<u32>,
+    slots: u32,
+    labels: usize,
+    divisions: bool,
+    instructions: Vec<Instruction>,
+}
+
+impl RiscVBuilder<'_> {
+    fn new_label(&mut self) -> Label {
+        self.labels += 1;
+        Label::Numbered(self.labels - 1)
+    }
+
+    /// Jumps to the handler of a runtime error if the condition holds. The handlers follow
+    /// the whole program, possibly beyond the 4 KiB reach of a branch, so the inverse condition
+    /// branches over a `jal`, reaching 1 MiB, which [Self::build] replaces in longer programs.
+    fn fail_if(&mut self, condition: Condition, lhs: Register, rhs: Register, handler: Label) {
+        let skip = self.new_label();
+        self.instructions.extend([
+            branch(condition.inverse(), lhs, rhs, skip),
+            Instruction::Jal {
+                dst: Register::Zero,
+                target: handler,
+            },
+            Instruction::Label(skip),
+        ]);
+    }
+
+    /// Returns the base register and the offset of the slot, computing the address in `t6`
+    /// if the offset does not fit in 12 bits.
+    fn slot_address(&mut self, slot: u32) -> (Register, i32) {
+        let offset = slot as i32 * 4;
+        if short(offset) {
+            return (Register::Sp, offset);
+        }
+
+        self.instructions
+            .extend(load_immediate(Register::T6, offset));
+        self.instructions
+            .push(op(RegOp::Add, Register::T6, Register::Sp, Register::T6));
+        (Register::T6, 0)
+    }
+
+    /// Returns the register holding the operand, loading it into `scratch` if needed.
+    fn operand(&mut self, operand: Operand, scratch: Register) -> Register {
+        let val = match operand {
+            Operand::Value(value) => match self.locations[value.0] {
+                Some(Location::Register(register)) => return register,
+                Some(Location::Slot(slot)) => {
+                    let (base, offset) = self.slot_address(slot);
+                    self.instructions.push(Instruction::Load {
+                        width: Width::Word,
+                        dst: scratch,
+                        base,
+                        offset,
+                    });
+                    return scratch;
+                }
+                None => unreachable!("values are used after their definitions"),
+            },
+            Operand::Const(0) => return Register::Zero,
+            Operand::Const(val) => val,
+            Operand::BigConst(idx) => ast::wrapped_literal(&self.program.big_constants[idx]),
+        };
+
+        self.instructions.extend(load_immediate(scratch, val));
+        scratch
+    }
+
+    /// Frees the location of the operand if it is used for the last time by the instruction.
+    fn release(&mut self, operand: Operand, position: usize) {
+        let Operand::Value(value) = operand else {
+            return;
+        };
+        if self.last_uses[value.0] != Some(position) {
+            return;
+        }
+
+        match self.locations[value.0].take() {
+            Some(Location::Register(register)) => {
+                let idx = Register::ALLOCATABLE
+                    .iter()
+                    .position(|&allocatable| allocatable == register)
+                    .unwrap();
+                self.free[idx] = true;
+            }
+            Some(Location::Slot(slot)) => self.free_slots.push(slot),
+            None => {}
+        }
+    }
+
+    /// Returns the location of a new value: the first free register or the lowest free slot.
+    fn allocate(&mut self) -> Location {
+        if let Some(idx) = self.free.iter().position(|&free| free) {
+            self.free[idx] = false;
+            return Location::Register(Register::ALLOCATABLE[idx]);
+        }
+
+        self.free_slots.sort_unstable_by(|a, b| b.cmp(a));
+        Location::Slot(self.free_slots.pop().unwrap_or_else(|| {
+            self.slots += 1;
+            self.slots - 1
+        }))
+    }
+
+    /// Saves the result computed in the register to the location of the value.
+    fn save(&mut self, result: Register, location: Option<Location>) {
+        match location {
+            Some(Location::Register(register)) if register != result => {
+                self.instructions.push(addi(register, result, 0))
+            }
+            Some(Location::Slot(slot)) => {
+                let (base, offset) = self.slot_address(slot);
+                self.instructions.push(Instruction::Store {
+                    width: Width::Word,
+                    src: result,
+                    base,
+                    offset,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    fn add_instruction(&mut self, position: usize, instruction: &IrInstruction) {
+        use Register::*;
+
+        let (dst, lhs, rhs) = match *instruction {
+            IrInstruction::Print(operand) => {
+                let register = self.operand(operand, A0);
+                if register != A0 {
+                    self.instructions.push(addi(A0, register, 0));
+                }
+                self.instructions.push(Instruction::Jal {
+                    dst: Ra,
+                    target: PRINT_INT,
+                });
+                self.release(operand, position);
+                return;
+            }
+            IrInstruction::Bin { dst, lhs, rhs, .. }
+            | IrInstruction::Machine { dst, lhs, rhs, .. } => (dst, lhs, rhs),
+        };
+
+        // The operands are read before the result is written, so the result may take
+        // the register of an operand used for the last time.
+        let immediate = match *instruction {
+            IrInstruction::Bin {
+                op: Op::Add,
+                rhs: Operand::Const(val),
+                ..
+            } if self.program.arithmetic == Arithmetic::Wrapping && short(val) => Some(val),
+            IrInstruction::Bin {
+                op: Op::Sub,
+                rhs: Operand::Const(val),
+                ..
+            } if self.program.arithmetic == Arithmetic::Wrapping
+                && val != i32::MIN
+                && short(-val) =>
+            {
+                Some(-val)
+            }
+            IrInstruction::Machine {
+                op: MachineOp::Shl | MachineOp::Sar | MachineOp::Shr,
+                rhs: Operand::Const(val),
+                ..
+            } => Some(val & 31),
+            _ => None,
+        };
+        let lhs_register = self.operand(lhs, T0);
+        let rhs_register = match immediate {
+            Some(_) => Zero,
+            None => self.operand(rhs, T1),
+        };
+        self.release(lhs, position);
+        if rhs != lhs {
+            self.release(rhs, position);
+        }
+
+        // Unused values are computed only for the runtime errors.
+        let location = self.last_uses[dst.0].map(|_| self.allocate());
+        self.locations[dst.0] = location;
+        let result = match location {
+            Some(Location::Register(register)) => register,
+            _ => T2,
+        };
+
+        match *instruction {
+            IrInstruction::Bin { op, rhs, .. } => {
+                let result = self.bin(op, lhs_register, rhs_register, rhs, immediate, result);
+                self.save(result, location);
+            }
+            IrInstruction::Machine { op, .. } => {
+                self.instructions.push(match (op, immediate) {
+                    (MachineOp::MulHigh, _) => {
+                        self::op(RegOp::Mulh, result, lhs_register, rhs_register)
+                    }
+                    (op, Some(imm)) => Instruction::OpImm {
+                        op: match op {
+                            MachineOp::Shl => ImmOp::Slli,
+                            MachineOp::Sar => ImmOp::Srai,
+                            _ => ImmOp::Srli,
+                        },
+                        dst: result,
+                        src: lhs_register,
+                        imm,
+                    },
+                    // The shift amount is taken modulo 32 by the instructions.
+                    (op, None) => self::op(
+                        match op {
+                            MachineOp::Shl => RegOp::Sll,
+                            MachineOp::Sar => RegOp::Sra,
+                            _ => RegOp::Srl,
+                        },
+                        result,
+                        lhs_register,
+                        rhs_register,
+                    ),
+                });
+                self.save(result, location);
+            }
+            IrInstruction::Print(_) => unreachable!("prints are handled separately"),
+        }
+    }
+
+    /// Computes the operation, returning the register holding the result:
+    /// `result` or `t2` for checked operations.
+    fn bin(
+        &mut self,
+        op: Op,
+        lhs: Register,
+        rhs: Register,
+        rhs_operand: Operand,
+        immediate: Option<i32>,
+        result: Register,
+    ) -> Register {
+        use Register::*;
+
+        if let Some(imm) = immediate {
+            self.instructions.push(addi(result, lhs, imm));
+            return result;
+        }
+
+        let checked = self.program.arithmetic == Arithmetic::Checked;
+        let reg_op = match op {
+            Op::Add => RegOp::Add,
+            Op::Sub => RegOp::Sub,
+            Op::Mul => RegOp::Mul,
+            Op::Div => return self.divide(lhs, rhs, rhs_operand, result),
+        };
+        if !checked {
+            self.instructions.push(self::op(reg_op, result, lhs, rhs));
+            return result;
+        }
+
+        // The result is compared with the operands, so it cannot overwrite them.
+        self.instructions.push(self::op(reg_op, T2, lhs, rhs));
+        self.instructions.extend(match op {
+            // The sum is less than the left operand exactly when the right one is negative.
+            Op::Add => [
+                self::op(RegOp::Slt, T3, T2, lhs),
+                Instruction::OpImm {
+                    op: ImmOp::Slti,
+                    dst: T4,
+                    src: rhs,
+                    imm: 0,
+                },
+            ],
+            // The difference is less than the left operand exactly when the right one is positive.
+            Op::Sub => [
+                self::op(RegOp::Slt, T3, T2, lhs),
+                self::op(RegOp::Slt, T4, Zero, rhs),
+            ],
+            // The upper half of the product is the sign extension of the lower one.
+            _ => [
+                self::op(RegOp::Mulh, T3, lhs, rhs),
+                Instruction::OpImm {
+                    op: ImmOp::Srai,
+                    dst: T4,
+                    src: T2,
+                    imm: 31,
+                },
+            ],
+        });
+        self.fail_if(Condition::NotEqual, T3, T4, OVERFLOW);
+
+        T2
+    }
+
+    /// Divides with `div`, which never traps: a zero divisor jumps to `divzero` before,
+    /// and in [Arithmetic::Checked] mode, so does the minimum divided by -1 to `overflow`.
+    /// Divisions by other constants need no checks.
+    fn divide(
+        &mut self,
+        lhs: Register,
+        rhs: Register,
+        operand: Operand,
+        result: Register,
+    ) -> Register {
+        use Register::*;
+
+        let divisor = match operand {
+            Operand::Const(val) => Some(val),
+            Operand::BigConst(idx) => Some(ast::wrapped_literal(&self.program.big_constants[idx])),
+            Operand::Value(_) => None,
+        };
+        if divisor.is_none() || divisor == Some(0) {
+            self.divisions = true;
+            self.fail_if(Condition::Equal, rhs, Zero, DIVZERO);
+        }
+
+        if self.program.arithmetic == Arithmetic::Checked && matches!(divisor, None | Some(-1)) {
+            let done = self.new_label();
+            if divisor.is_none() {
+                self.instructions.extend([
+                    addi(T3, Zero, -1),
+                    branch(Condition::NotEqual, rhs, T3, done),
+                ]);
+            }
+            self.instructions.extend(load_immediate(T3, i32::MIN));
+            self.instructions.extend([
+                branch(Condition::NotEqual, lhs, T3, done),
+                Instruction::Jal {
+                    dst: Zero,
+                    target: OVERFLOW,
+                },
+                Instruction::Label(done),
+            ]);
+        }
+
+        self.instructions.push(op(RegOp::Div, result, lhs, rhs));
+        result
+    }
+
+    fn build(self) -> Assembly {
+        use Register::*;
+
+        let frame = (self.slots * 4).next_multiple_of(16) as i32;
+        let mut instructions = vec![Instruction::Label(START)];
+        if frame > 0 {
+            if short(-frame) {
+                instructions.push(addi(Sp, Sp, -frame));
+            } else {
+                instructions.extend(load_immediate(T0, frame));
+                instructions.push(op(RegOp::Sub, Sp, Sp, T0));
+            }
+        }
+        instructions.extend(self.instructions);
+        instructions.extend([addi(A0, Zero, 0), addi(A7, Zero, EXIT), Instruction::Ecall]);
+        instructions.extend(print_int());
+
+        let checked = self.program.arithmetic == Arithmetic::Checked;
+        instructions.extend(error_handlers(self.divisions, checked));
+
+        // Beyond the 1 MiB reach of `jal`, with at most 8 bytes per instruction,
+        // the calls and jumps use `auipc` and `jalr`.
+        if 8 * instructions.len() >= 1 << 20 {
+            for instruction in &mut instructions {
+                *instruction = match *instruction {
+                    Instruction::Jal { dst: Ra, target } => Instruction::Call { target },
+                    Instruction::Jal { dst: Zero, target } => Instruction::Jump {
+                        target,
+                        scratch: T1,
+                    },
+                    instruction => instruction,
+                };
+            }
+        }
+        let mut data = Vec::new();
+        if self.divisions {
+            data.push((DIVZERO_MSG, "division by zero\n"));
+        }
+        if checked {
+            data.push((OVERFLOW_MSG, "integer overflow\n"));
+        }
+
+        Assembly { instructions, data }
+    }
+}
+
+/// RV32IM assembly of an Instant program, with its entry point `_start`, the runtime
+/// and the messages of the runtime errors.
+pub struct Assembly {
+    pub(crate) instructions: Vec<Instruction>,
+    pub(crate) data: Vec<(Label, &'static str)>,
+}
+
+impl Display for Assembly {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "\t.text")?;
+        writeln!(f, "\t.globl _start")?;
+        for instruction in &self.instructions {
+            writeln!(f, "{}", instruction)?;
+        }
+
+        if !self.data.is_empty() {
+            writeln!(f, "\t.section .rodata")?;
+        }
+        for (label, text) in &self.data {
+            writeln!(f, "{}:", label)?;
+            writeln!(f, "\t.ascii \"{}\"", text.escape_default())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn generation() {
+        let assembly = RiscVBackend::default().process(&testing::simplified(
+            "a = 3000 + 4; b = a - 5; a * b; b / a",
+            Arithmetic::Wrapping,
+        ));
+        assert_eq!(
+            assembly.to_string().lines().take(19).collect::<Vec<_>>(),
+            [
+                "\t.text",
+                "\t.globl _start",
+                "_start:",
+                "\tlui t0, 1",
+                "\taddi t0, t0, -1096",
+                "\taddi s1, t0, 4",
+                "\taddi s2, s1, -5",
+                "\tmul s3, s1, s2",
+                "\taddi a0, s3, 0",
+                "\tjal ra, printInt",
+                // The quotient takes the register of the divisor, read for the last time.
+                "\tbne s1, zero, .L0",
+                "\tjal zero, divzero",
+                ".L0:",
+                "\tdiv s1, s2, s1",
+                "\taddi a0, s1, 0",
+                "\tjal ra, printInt",
+                "\taddi a0, zero, 0",
+                "\taddi a7, zero, 93",
+                "\tecall",
+            ]
+        );
+    }
+
+    #[test]
+    fn immediates() {
+        let load = |val| {
+            load_immediate(Register::T0, val)
+                .iter()
+                .map(|instruction| instruction.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(load(-2048), ["\taddi t0, zero, -2048"]);
+        assert_eq!(load(0x12345678), ["\tlui t0, 74565", "\taddi t0, t0, 1656"]);
+        assert_eq!(load(0x12345fff), ["\tlui t0, 74566", "\taddi t0, t0, -1"]);
+        assert_eq!(load(i32::MIN), ["\tlui t0, 524288"]);
+        assert_eq!(load(-1), ["\taddi t0, zero, -1"]);
+    }
+
+    #[test]
+    fn spilling() {
+        // All the variables are live until the last statement, needing more than 2 KiB of slots.
+        let count = 600;
+        let mut source = "v0 = 7".to_string();
+        for i in 1..count {
+            source.push_str(&format!("; v{} = v{} * 3 - {}", i, i - 1, i));
+        }
+        source.push_str("; v0");
+        for i in 1..count {
+            source.push_str(&format!(" + v{}", i));
+        }
+
+        for registers in [1, 2, Register::ALLOCATABLE.len()] {
+            testing::check(&[&source], |program| {
+                let assembly = RiscVBackend::default()
+                    .with_registers(registers)
+                    .process(program);
+
+                // Slots beyond 2 KiB are addressed through `t6`.
+                let far = assembly.instructions.iter().any(|instruction| {
+                    matches!(
+                        instruction,
+                        Instruction::Load {
+                            base: Register::T6,
+                            ..
+                        }
+                    )
+                });
+                assert!(far);
+
+                Some(testing::simulate(&assembly))
+            });
+        }
+    }
+
+    #[test]
+    fn long_programs() {
+        // The checks at the start are more than 4 KiB away from the handlers at the end.
+        let mut source = "a = 7; b = a - 7; a / b; a / (b - 1)".to_string();
+        for _ in 0..500 {
+            source.push_str("; a = a * 3 - b");
+        }
+
+        let assembly =
+            RiscVBackend::default().process(&testing::simplified(&source, Arithmetic::Checked));
+        assert!(assembly.instructions.len() > 1024);
+
+        // The simulator checks that the offsets of the branches fit in their fields.
+        let execution = testing::simulate(&assembly);
+        assert_eq!(execution, testing::interpret(&source, Arithmetic::Checked));
+        assert_eq!(execution.code, 1);
+    }
+
+    #[test]
+    fn far_jumps() {
+        // The call and the check at the start are more than 1 MiB away from `printInt`
+        // and the handlers at the end.
+        let mut source = "a = 7; b = a - 7; a; a / b".to_string();
+        for _ in 0..40000 {
+            source.push_str("; a = a * 3 - b");
+        }
+
+        let assembly =
+            RiscVBackend::default().process(&testing::simplified(&source, Arithmetic::Checked));
+        let code = assembly
+            .instructions
+            .iter()
+            .filter(|instruction| !matches!(instruction, Instruction::Label(_)))
+            .count();
+        assert!(4 * code > 1 << 20);
+        assert!(assembly
+            .instructions
+            .contains(&Instruction::Call { target: PRINT_INT }));
+
+        // The simulator checks that the offsets of the jumps fit in their fields.
+        let execution = testing::simulate(&assembly);
+        assert_eq!(execution, testing::interpret(&source, Arithmetic::Checked));
+        assert_eq!(execution.code, 1);
+    }
+}
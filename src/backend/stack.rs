@@ -0,0 +1,240 @@
+//! Placement of the [Program] values, shared by the backends targeting stack machines.
+
+use crate::{
+    ir::{Instruction, Operand, Program},
+    Arithmetic,
+};
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+/// How a stack machine backend handles a value of the [Program].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Storage {
+    /// Value is computed directly on the stack, as a part of its only user.
+    Inline,
+    /// Value is both operands of its only user. It is computed directly on the stack and duplicated,
+    /// which never needs more stack than loading it twice from a local variable.
+    Dup,
+    /// Value is computed at its definition and saved in the local variable with the given index.
+    Local(usize),
+    /// Value is never used, it is computed at its definition only for the runtime errors.
+    Discard,
+}
+
+/// Assigns slots to the values stored in local variables, with a linear scan over their live ranges.
+/// A slot is freed after the last load of its value and the lowest free slot is always taken,
+/// so that the short forms of the instructions on slots 0-3 are used as much as possible.
+/// At least one slot is counted: in the JVM, slot 0 initially holds the `args` parameter of `main`,
+/// which is never used.
+/// Returns the number of slots needed.
+fn allocate_locals(program: &Program, users: &[Vec<usize>], storage: &mut [Storage]) -> usize {
+    // Values computed on the stack are emitted as a part of their only user.
+    let mut positions = (0..program.instructions.len()).collect::<Vec<_>>();
+    for (i, instruction) in program.instructions.iter().enumerate().rev() {
+        if let Some(dst) = instruction.dst() {
+            if let Storage::Inline | Storage::Dup = storage[dst.0] {
+                positions[i] = positions[users[dst.0][0]];
+            }
+        }
+    }
+
+    let mut free = BinaryHeap::from([Reverse(0)]);
+    let mut active = BinaryHeap::new();
+    let mut locals = 1;
+    for (i, instruction) in program.instructions.iter().enumerate() {
+        let Some(dst) = instruction.dst() else {
+            continue;
+        };
+        let Storage::Local(slot) = &mut storage[dst.0] else {
+            continue;
+        };
+
+        // Values loaded for the last time by this instruction are loaded before the store.
+        while let Some(&Reverse((end, freed))) = active.peek() {
+            if end > i {
+                break;
+            }
+            active.pop();
+            free.push(Reverse(freed));
+        }
+
+        *slot = match free.pop() {
+            Some(Reverse(slot)) => slot,
+            None => {
+                locals += 1;
+                locals - 1
+            }
+        };
+        let end = users[dst.0].iter().map(|&user| positions[user]).max();
+        active.push(Reverse((end.unwrap_or(i), *slot)));
+    }
+
+    locals
+}
+
+/// Placement of all values of a [Program].
+pub(super) struct Layout {
+    /// Index of the instruction defining each value.
+    pub definitions: Vec<usize>,
+    pub storage: Vec<Storage>,
+    /// Number of local variable slots.
+    pub locals: usize,
+}
+
+impl Layout {
+    pub fn new(program: &Program) -> Self {
+        let mut definitions = vec![0; program.value_count()];
+        let mut users = vec![Vec::new(); program.value_count()];
+        // Prints and, in Checked mode, operations failing with different errors keep their order.
+        let checked = program.arithmetic == Arithmetic::Checked;
+        let mut barriers = vec![0; program.instructions.len() + 1];
+        for (i, instruction) in program.instructions.iter().enumerate() {
+            if let Some(dst) = instruction.dst() {
+                definitions[dst.0] = i;
+            }
+            for operand in instruction.operands() {
+                if let Operand::Value(value) = operand {
+                    users[value.0].push(i);
+                }
+            }
+            let barrier = matches!(instruction, Instruction::Print(_))
+                || checked && instruction.may_fail(program.arithmetic);
+            barriers[i + 1] = barriers[i] + usize::from(barrier);
+        }
+
+        // A value can be computed right where it is used only if this does not move
+        // a possible runtime error over a barrier.
+        let mut storage = users
+            .iter()
+            .zip(&definitions)
+            .map(|(users, &definition)| match users[..] {
+                [] => Storage::Discard,
+                [user] if barriers[user] == barriers[definition + 1] => Storage::Inline,
+                [first, second]
+                    if first == second && barriers[first] == barriers[definition + 1] =>
+                {
+                    Storage::Dup
+                }
+                _ => Storage::Local(0),
+            })
+            .collect::<Vec<_>>();
+        let locals = allocate_locals(program, &users, &mut storage);
+
+        Self {
+            definitions,
+            storage,
+            locals,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        backend::{
+            bytecode::BytecodeBackend, jasmin::JasminBackend, wasm::WasmBackend,
+            x86_64::X86Backend, Backend,
+        },
+        testing::{self, Execution},
+        vm,
+    };
+    use std::{env, fs, process::Command};
+
+    /// Programs whose operations fail with different errors in [Arithmetic::Checked] mode.
+    /// The first error in the order of the source must be reported.
+    const SOURCES: [&str; 5] = [
+        "x = 2147483647; z = 3; a = x + 1; b = 1 / ((z * z) * (z * z) - 81); a + b",
+        "x = 2147483647; z = 3; a = x + 1; b = 1 / (z * (z * (z * z)) - 81); a + b",
+        "x = 2147483647; z = 0; (z * (z * (1 / z))) + (x + 1)",
+        "x = 2147483647; z = 0; (x + 1) * (x * (z * (1 / z)))",
+        "x = 2147483647; z = 0; x; a = 1 / z; b = x * x; z; b - a",
+    ];
+
+    /// Replaces the message of the runtime error, reported by the target in its own words after
+    /// the prefix, with the one of the interpreter.
+    fn translated(execution: Execution, prefix: &str, messages: &[(&str, &str)]) -> Execution {
+        let stderr = String::from_utf8(execution.stderr.unwrap()).unwrap();
+        let message = stderr.lines().find_map(|line| line.strip_prefix(prefix));
+        let stderr = message.map(|message| {
+            let message = messages
+                .iter()
+                .find(|(own, _)| *own == message)
+                .map_or(message, |(_, translated)| translated);
+            format!("{}\n", message).into_bytes()
+        });
+
+        Execution {
+            stderr: Some(stderr.unwrap_or_default()),
+            ..execution
+        }
+    }
+
+    #[test]
+    fn virtual_machine() {
+        testing::check(&SOURCES, |program| {
+            let mut stdout = Vec::new();
+            let result = vm::run(&BytecodeBackend.process(program), &mut stdout);
+            Some(Execution::finished(stdout, &result))
+        });
+    }
+
+    #[test]
+    fn jvm() {
+        let directory = env::temp_dir().join(format!("instant-order-jvm-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        testing::check(&SOURCES, |program| {
+            let class_file = JasminBackend::new("Order".into())
+                .process(program)
+                .class_file()
+                .unwrap();
+            fs::write(directory.join("Order.class"), class_file).unwrap();
+            let execution =
+                Execution::of(Command::new("java").arg("-cp").arg(&directory).arg("Order"))?;
+            Some(translated(
+                execution,
+                "Exception in thread \"main\" java.lang.ArithmeticException: ",
+                &[("/ by zero", "division by zero")],
+            ))
+        });
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn wasm() {
+        let path = env::temp_dir().join(format!("instant-order-{}.wasm", std::process::id()));
+        let runner = concat!(env!("CARGO_MANIFEST_DIR"), "/run_wasm.js");
+        testing::check(&SOURCES, |program| {
+            fs::write(&path, WasmBackend.process(program).encode()).unwrap();
+            let execution = Execution::of(Command::new("node").arg(runner).arg(&path))?;
+            Some(translated(
+                execution,
+                "ERROR: ",
+                &[
+                    ("divide by zero", "division by zero"),
+                    ("unreachable", "integer overflow"),
+                ],
+            ))
+        });
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    #[test]
+    fn x86_64() {
+        use crate::backend::{elf, x86_64::Register};
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = env::temp_dir().join(format!("instant-order-x86-{}", std::process::id()));
+        // With a single register, the operands are spilled.
+        for registers in [1, Register::ALLOCATABLE.len()] {
+            testing::check(&SOURCES, |program| {
+                let assembly = X86Backend::default()
+                    .with_registers(registers)
+                    .process(program);
+                fs::write(&path, elf::executable(&assembly)).unwrap();
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+                Execution::of(&mut Command::new(&path))
+            });
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
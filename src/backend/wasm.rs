@@ -0,0 +1,556 @@
+use super::{
+    bytecode::write_varint,
+    stack::{Layout, Storage},
+    Backend,
+};
+use crate::{
+    ast::{self, Op},
+    ir::{Instruction as IrInstruction, MachineOp, Operand, Program},
+    Arithmetic,
+};
+use std::fmt::{self, Display, Formatter};
+
+/// Backend for generating a [WebAssembly](https://webassembly.org/) module, shown as WAT text
+/// by [Display] and encoded into the binary format by [Wasm::encode].
+/// The module imports `printInt(i32)` from `env` and exports `main`, which runs the program.
+///
+/// Runtime errors are traps, which abort `main` and are reported by the host:
+/// - division by zero traps in `i32.div_s`,
+/// - in [Arithmetic::Checked] mode, the division of the minimum by -1 traps in `i32.div_s`
+///   and the other overflows execute `unreachable`,
+/// - in [Arithmetic::Wrapping] mode, the division of the minimum by -1 is a negation,
+///   as `i32.div_s` would trap.
+///
+/// Values used many times are kept in locals, like in the other stack machine backends.
+/// [Arithmetic::Unbounded] mode is not supported.
+#[derive(Default)]
+pub struct WasmBackend;
+
+impl Backend for WasmBackend {
+    type Representation = Wasm;
+
+    fn process(&self, program: &Program) -> Wasm {
+        assert!(
+            program.arithmetic != Arithmetic::Unbounded,
+            "unbounded arithmetic is not supported by the WebAssembly backend"
+        );
+
+        let mut builder = WasmBuilder {
+            program,
+            layout: Layout::new(program),
+            instructions: Vec::new(),
+        };
+        for instruction in &program.instructions {
+            builder.add_instruction(instruction);
+        }
+
+        Wasm {
+            locals: builder.layout.locals as u32,
+            instructions: builder.instructions,
+        }
+    }
+}
+
+/// Numeric instruction without immediates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Numeric {
+    I32Eq,
+    I32Add,
+    I32Sub,
+    I32Mul,
+    I32DivS,
+    I32Shl,
+    I32ShrS,
+    I32ShrU,
+    I32WrapI64,
+    I64ExtendI32S,
+    I64Ne,
+    I64Add,
+    I64Sub,
+    I64Mul,
+    I64ShrS,
+}
+
+impl Numeric {
+    fn name(self) -> &'static str {
+        match self {
+            Self::I32Eq => "i32.eq",
+            Self::I32Add => "i32.add",
+            Self::I32Sub => "i32.sub",
+            Self::I32Mul => "i32.mul",
+            Self::I32DivS => "i32.div_s",
+            Self::I32Shl => "i32.shl",
+            Self::I32ShrS => "i32.shr_s",
+            Self::I32ShrU => "i32.shr_u",
+            Self::I32WrapI64 => "i32.wrap_i64",
+            Self::I64ExtendI32S => "i64.extend_i32_s",
+            Self::I64Ne => "i64.ne",
+            Self::I64Add => "i64.add",
+            Self::I64Sub => "i64.sub",
+            Self::I64Mul => "i64.mul",
+            Self::I64ShrS => "i64.shr_s",
+        }
+    }
+
+    fn opcode(self) -> u8 {
+        match self {
+            Self::I32Eq => 0x46,
+            Self::I32Add => 0x6a,
+            Self::I32Sub => 0x6b,
+            Self::I32Mul => 0x6c,
+            Self::I32DivS => 0x6d,
+            Self::I32Shl => 0x74,
+            Self::I32ShrS => 0x75,
+            Self::I32ShrU => 0x76,
+            Self::I32WrapI64 => 0xa7,
+            Self::I64ExtendI32S => 0xac,
+            Self::I64Ne => 0x52,
+            Self::I64Add => 0x7c,
+            Self::I64Sub => 0x7d,
+            Self::I64Mul => 0x7e,
+            Self::I64ShrS => 0x87,
+        }
+    }
+}
+
+/// Instruction of the `main` function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    I32Const(i32),
+    I64Const(i64),
+    Numeric(Numeric),
+    /// Call of the imported `printInt`.
+    Print,
+    Drop,
+    /// Start of a block executed if the popped value is not zero, which leaves an i32
+    /// on the stack if requested.
+    If {
+        result: bool,
+    },
+    Else,
+    End,
+    Unreachable,
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LocalGet(idx) => write!(f, "local.get {}", idx),
+            Self::LocalSet(idx) => write!(f, "local.set {}", idx),
+            Self::LocalTee(idx) => write!(f, "local.tee {}", idx),
+            Self::I32Const(val) => write!(f, "i32.const {}", val),
+            Self::I64Const(val) => write!(f, "i64.const {}", val),
+            Self::Numeric(op) => f.write_str(op.name()),
+            Self::Print => f.write_str("call $printInt"),
+            Self::Drop => f.write_str("drop"),
+            Self::If { result: true } => f.write_str("if (result i32)"),
+            Self::If { result: false } => f.write_str("if"),
+            Self::Else => f.write_str("else"),
+            Self::End => f.write_str("end"),
+            Self::Unreachable => f.write_str("unreachable"),
+        }
+    }
+}
+
+/// Appends the signed LEB128 encoding of the value.
+fn write_signed_varint(bytes: &mut Vec<u8>, mut val: i64) {
+    loop {
+        let byte = val as u8 & 0x7f;
+        val >>= 7;
+        if (val == 0 && byte & 0x40 == 0) || (val == -1 && byte & 0x40 != 0) {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+impl Instruction {
+    fn encode(self, bytes: &mut Vec<u8>) {
+        match self {
+            Self::LocalGet(idx) | Self::LocalSet(idx) | Self::LocalTee(idx) => {
+                bytes.push(match self {
+                    Self::LocalGet(_) => 0x20,
+                    Self::LocalSet(_) => 0x21,
+                    _ => 0x22,
+                });
+                write_varint(bytes, idx.into());
+            }
+            Self::I32Const(val) => {
+                bytes.push(0x41);
+                write_signed_varint(bytes, val.into());
+            }
+            Self::I64Const(val) => {
+                bytes.push(0x42);
+                write_signed_varint(bytes, val);
+            }
+            Self::Numeric(op) => bytes.push(op.opcode()),
+            // `printInt` is the only imported function, with index 0.
+            Self::Print => bytes.extend([0x10, 0x00]),
+            Self::Drop => bytes.push(0x1a),
+            Self::If { result: true } => bytes.extend([0x04, I32]),
+            Self::If { result: false } => bytes.extend([0x04, 0x40]),
+            Self::Else => bytes.push(0x05),
+            Self::End => bytes.push(0x0b),
+            Self::Unreachable => bytes.push(0x00),
+        }
+    }
+}
+
+/// Binary encoding of the i32 value type.
+const I32: u8 = 0x7f;
+
+/// Binary encoding of the i64 value type.
+const I64: u8 = 0x7e;
+
+struct WasmBuilder<'p> {
+    program: &'p Program,
+    layout: Layout,
+    instructions: Vec<Instruction>,
+}
+
+impl WasmBuilder<'_> {
+    /// Returns the index of the i32 local holding an operand of a single operation,
+    /// 0 for the left one and 1 for the right one. They follow the locals of the [Layout].
+    fn scratch(&self, operand: u32) -> u32 {
+        self.layout.locals as u32 + operand
+    }
+
+    /// Returns the index of the i64 local holding the result of a checked operation.
+    fn wide(&self) -> u32 {
+        self.layout.locals as u32 + 2
+    }
+
+    fn process_operand(&mut self, operand: Operand) {
+        let instruction = match operand {
+            Operand::Const(val) => Instruction::I32Const(val),
+            Operand::BigConst(idx) => {
+                Instruction::I32Const(ast::wrapped_literal(&self.program.big_constants[idx]))
+            }
+            Operand::Value(value) => match self.layout.storage[value.0] {
+                Storage::Local(slot) => Instruction::LocalGet(slot as u32),
+                Storage::Inline | Storage::Dup | Storage::Discard => {
+                    let definition = self.layout.definitions[value.0];
+                    return self.process_definition(&self.program.instructions[definition]);
+                }
+            },
+        };
+
+        self.instructions.push(instruction);
+    }
+
+    fn process_definition(&mut self, instruction: &IrInstruction) {
+        let (lhs, rhs) = match instruction {
+            IrInstruction::Bin { lhs, rhs, .. } | IrInstruction::Machine { lhs, rhs, .. } => {
+                (*lhs, *rhs)
+            }
+            IrInstruction::Print(_) => unreachable!("values are never defined by prints"),
+        };
+
+        // There is no `dup`, the value goes through a local.
+        let duplicated = match (lhs, lhs == rhs) {
+            (Operand::Value(value), true) => self.layout.storage[value.0] == Storage::Dup,
+            _ => false,
+        };
+        if duplicated {
+            self.process_operand(lhs);
+            let scratch = self.scratch(0);
+            self.instructions.extend([
+                Instruction::LocalTee(scratch),
+                Instruction::LocalGet(scratch),
+            ]);
+        } else {
+            self.process_operand(lhs);
+            self.process_operand(rhs);
+        }
+
+        match instruction {
+            IrInstruction::Bin { op, rhs, .. } => self.bin(*op, *rhs),
+            IrInstruction::Machine { op, .. } => self.machine(*op),
+            IrInstruction::Print(_) => unreachable!("values are never defined by prints"),
+        }
+    }
+
+    /// Applies the operation to the operands on the top of the stack.
+    fn bin(&mut self, op: Op, rhs: Operand) {
+        let checked = self.program.arithmetic == Arithmetic::Checked;
+        let numeric = |op| Instruction::Numeric(op);
+        match (op, checked) {
+            (Op::Add, false) => self.instructions.push(numeric(Numeric::I32Add)),
+            (Op::Sub, false) => self.instructions.push(numeric(Numeric::I32Sub)),
+            (Op::Mul, false) => self.instructions.push(numeric(Numeric::I32Mul)),
+            (Op::Add, true) => self.checked(Numeric::I64Add),
+            (Op::Sub, true) => self.checked(Numeric::I64Sub),
+            (Op::Mul, true) => self.checked(Numeric::I64Mul),
+            // The trap on the overflow is the expected error in checked arithmetic.
+            (Op::Div, true) => self.instructions.push(numeric(Numeric::I32DivS)),
+            (Op::Div, false) if matches!(rhs, Operand::Const(val) if val != -1) => {
+                self.instructions.push(numeric(Numeric::I32DivS))
+            }
+            (Op::Div, false) => {
+                let (lhs, rhs) = (self.scratch(0), self.scratch(1));
+                self.instructions.extend([
+                    Instruction::LocalSet(rhs),
+                    Instruction::LocalSet(lhs),
+                    Instruction::LocalGet(rhs),
+                    Instruction::I32Const(-1),
+                    numeric(Numeric::I32Eq),
+                    Instruction::If { result: true },
+                    Instruction::I32Const(0),
+                    Instruction::LocalGet(lhs),
+                    numeric(Numeric::I32Sub),
+                    Instruction::Else,
+                    Instruction::LocalGet(lhs),
+                    Instruction::LocalGet(rhs),
+                    numeric(Numeric::I32DivS),
+                    Instruction::End,
+                ]);
+            }
+        }
+    }
+
+    /// Applies the 64-bit operation to the sign-extended operands on the top of the stack,
+    /// trapping if the result does not fit in 32 bits.
+    fn checked(&mut self, op: Numeric) {
+        let (rhs, wide) = (self.scratch(1), self.wide());
+        self.instructions.extend([
+            Instruction::LocalSet(rhs),
+            Instruction::Numeric(Numeric::I64ExtendI32S),
+            Instruction::LocalGet(rhs),
+            Instruction::Numeric(Numeric::I64ExtendI32S),
+            Instruction::Numeric(op),
+            Instruction::LocalTee(wide),
+            Instruction::LocalGet(wide),
+            Instruction::Numeric(Numeric::I32WrapI64),
+            Instruction::Numeric(Numeric::I64ExtendI32S),
+            Instruction::Numeric(Numeric::I64Ne),
+            Instruction::If { result: false },
+            Instruction::Unreachable,
+            Instruction::End,
+            Instruction::LocalGet(wide),
+            Instruction::Numeric(Numeric::I32WrapI64),
+        ]);
+    }
+
+    fn machine(&mut self, op: MachineOp) {
+        // The shift amount is taken modulo 32 by the instructions.
+        let op = match op {
+            MachineOp::Shl => Numeric::I32Shl,
+            MachineOp::Sar => Numeric::I32ShrS,
+            MachineOp::Shr => Numeric::I32ShrU,
+            MachineOp::MulHigh => {
+                let rhs = self.scratch(1);
+                self.instructions.extend([
+                    Instruction::LocalSet(rhs),
+                    Instruction::Numeric(Numeric::I64ExtendI32S),
+                    Instruction::LocalGet(rhs),
+                    Instruction::Numeric(Numeric::I64ExtendI32S),
+                    Instruction::Numeric(Numeric::I64Mul),
+                    Instruction::I64Const(32),
+                    Instruction::Numeric(Numeric::I64ShrS),
+                    Instruction::Numeric(Numeric::I32WrapI64),
+                ]);
+                return;
+            }
+        };
+
+        self.instructions.push(Instruction::Numeric(op));
+    }
+
+    fn add_instruction(&mut self, instruction: &IrInstruction) {
+        match instruction {
+            IrInstruction::Print(operand) => {
+                self.process_operand(*operand);
+                self.instructions.push(Instruction::Print);
+            }
+            IrInstruction::Bin { dst, .. } | IrInstruction::Machine { dst, .. } => {
+                let last = match self.layout.storage[dst.0] {
+                    Storage::Inline | Storage::Dup => return,
+                    Storage::Local(slot) => Instruction::LocalSet(slot as u32),
+                    Storage::Discard => Instruction::Drop,
+                };
+
+                self.process_definition(instruction);
+                self.instructions.push(last);
+            }
+        }
+    }
+}
+
+/// WebAssembly module of an Instant program.
+pub struct Wasm {
+    /// Number of the i32 locals of the [Layout], followed by two i32 and one i64 scratch locals.
+    locals: u32,
+    instructions: Vec<Instruction>,
+}
+
+impl Display for Wasm {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "(module")?;
+        writeln!(
+            f,
+            "  (import \"env\" \"printInt\" (func $printInt (param i32)))"
+        )?;
+        writeln!(f, "  (func (export \"main\")")?;
+        writeln!(
+            f,
+            "    (local{}) (local i64)",
+            " i32".repeat(self.locals as usize + 2)
+        )?;
+
+        let mut depth = 2;
+        for instruction in &self.instructions {
+            if let Instruction::Else | Instruction::End = instruction {
+                depth -= 1;
+            }
+            writeln!(f, "{:width$}{}", "", instruction, width = 2 * depth)?;
+            if let Instruction::If { .. } | Instruction::Else = instruction {
+                depth += 1;
+            }
+        }
+
+        writeln!(f, "  )")?;
+        writeln!(f, ")")
+    }
+}
+
+/// Appends a section with its size.
+fn write_section(bytes: &mut Vec<u8>, id: u8, content: &[u8]) {
+    bytes.push(id);
+    write_varint(bytes, content.len() as u64);
+    bytes.extend(content);
+}
+
+/// Appends a name with its length.
+fn write_name(bytes: &mut Vec<u8>, name: &str) {
+    write_varint(bytes, name.len() as u64);
+    bytes.extend(name.as_bytes());
+}
+
+impl Wasm {
+    /// Encodes the module into the binary format, with the sections:
+    /// - types: `printInt` taking an i32 and `main` without parameters and results,
+    /// - imports: `printInt` from `env`,
+    /// - functions and exports: `main`, with index 1 after the import,
+    /// - code: the body of `main`.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = b"\0asm".to_vec();
+        bytes.extend(1u32.to_le_bytes());
+
+        write_section(&mut bytes, 1, &[2, 0x60, 1, I32, 0, 0x60, 0, 0]);
+
+        let mut imports = vec![1];
+        write_name(&mut imports, "env");
+        write_name(&mut imports, "printInt");
+        imports.extend([0x00, 0]);
+        write_section(&mut bytes, 2, &imports);
+
+        write_section(&mut bytes, 3, &[1, 1]);
+
+        let mut exports = vec![1];
+        write_name(&mut exports, "main");
+        exports.extend([0x00, 1]);
+        write_section(&mut bytes, 7, &exports);
+
+        let mut body = vec![2];
+        write_varint(&mut body, u64::from(self.locals) + 2);
+        body.extend([I32, 1, I64]);
+        for instruction in &self.instructions {
+            instruction.encode(&mut body);
+        }
+        body.push(0x0b);
+
+        let mut code = vec![1];
+        write_varint(&mut code, body.len() as u64);
+        code.extend(body);
+        write_section(&mut bytes, 10, &code);
+
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{self, Execution};
+    use std::{env, fs, process::Command};
+
+    #[test]
+    fn generation() {
+        let wasm = WasmBackend.process(&testing::program(
+            "a = 7 / 2; a * a; a / (a - 3)",
+            Arithmetic::Wrapping,
+        ));
+        assert_eq!(
+            wasm.to_string(),
+            "\
+(module
+  (import \"env\" \"printInt\" (func $printInt (param i32)))
+  (func (export \"main\")
+    (local i32 i32 i32) (local i64)
+    i32.const 7
+    i32.const 2
+    i32.div_s
+    local.set 0
+    local.get 0
+    local.get 0
+    i32.mul
+    call $printInt
+    local.get 0
+    local.get 0
+    i32.const 3
+    i32.sub
+    local.set 2
+    local.set 1
+    local.get 2
+    i32.const -1
+    i32.eq
+    if (result i32)
+      i32.const 0
+      local.get 1
+      i32.sub
+    else
+      local.get 1
+      local.get 2
+      i32.div_s
+    end
+    call $printInt
+  )
+)
+"
+        );
+
+        let mut bytes = Vec::new();
+        write_signed_varint(&mut bytes, -1);
+        write_signed_varint(&mut bytes, 64);
+        write_signed_varint(&mut bytes, i32::MIN.into());
+        assert_eq!(bytes, [0x7f, 0xc0, 0x00, 0x80, 0x80, 0x80, 0x80, 0x78]);
+    }
+
+    #[test]
+    fn traps() {
+        let sources = [
+            "1; 2 / (1 - 1); 3",
+            "a = 2147483647; a - 1; a + 1",
+            "a = 0 - 2147483647 - 1; a / (0 - 1); a * a",
+            "a = 65536; a * (a - 1) * 2",
+        ];
+        let path = env::temp_dir().join(format!("instant-wasm-{}.wasm", std::process::id()));
+        let runner = concat!(env!("CARGO_MANIFEST_DIR"), "/run_wasm.js");
+
+        testing::check(&sources, |program| {
+            fs::write(&path, WasmBackend.process(program).encode()).unwrap();
+            // The traps are reported with the messages of the engine.
+            let execution = Execution::of(Command::new("node").arg(runner).arg(&path))?;
+            Some(Execution {
+                stderr: None,
+                ..execution
+            })
+        });
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,985 @@
+use super::{
+    stack::{Layout, Storage},
+    Backend,
+};
+use crate::{
+    ast::{self, Op},
+    ir::{Instruction as IrInstruction, MachineOp, Operand as IrOperand, Program},
+    Arithmetic,
+};
+use std::fmt::{self, Display, Formatter};
+
+/// Backend for generating GNU assembler in the AT&T syntax for x86-64 Linux, to be assembled
+/// with `as` and linked with `ld` into a static executable without libc.
+/// Printing and exiting use the `write` and `exit` system calls directly.
+///
+/// Expressions are computed in registers in the Sethi–Ullman order, in the same way as operands
+/// are pushed by the [JasminBackend](super::jasmin::JasminBackend). When an expression needs more
+/// registers than are available, the result of one operand is spilled to the stack.
+/// Values used many times are kept in 32-bit slots below the frame pointer.
+///
+/// Division by zero and, in [Arithmetic::Checked] mode, overflows print an error to STDERR
+/// and exit with code 1. [Arithmetic::Unbounded] mode is not supported.
+pub struct X86Backend {
+    registers: usize,
+}
+
+impl Default for X86Backend {
+    fn default() -> Self {
+        Self {
+            registers: Register::ALLOCATABLE.len(),
+        }
+    }
+}
+
+impl X86Backend {
+    /// Limits the number of registers holding intermediate results, at least 1.
+    pub fn with_registers(self, registers: usize) -> Self {
+        assert!(
+            (1..=Register::ALLOCATABLE.len()).contains(&registers),
+            "invalid number of registers"
+        );
+        Self { registers }
+    }
+}
+
+impl Backend for X86Backend {
+    type Representation = Assembly;
+
+    fn process(&self, program: &Program) -> Assembly {
+        assert!(
+            program.arithmetic != Arithmetic::Unbounded,
+            "unbounded arithmetic is not supported by the x86-64 backend"
+        );
+
+        let layout = Layout::new(program);
+        let mut builder = X86Builder {
+            program,
+            needs: vec![0; program.value_count()],
+            fallible: vec![false; program.value_count()],
+            layout,
+            registers: self.registers,
+            labels: 0,
+            divisions: false,
+            instructions: Vec::new(),
+        };
+        builder.count_needs();
+
+        let frame = (builder.layout.locals * 4).next_multiple_of(16) as u32;
+        builder.instructions.extend([
+            Instruction::Label(Label::Named("_start")),
+            Instruction::SetFrame,
+            Instruction::Reserve(frame),
+        ]);
+        for instruction in &program.instructions {
+            builder.add_instruction(instruction);
+        }
+
+        builder.build()
+    }
+}
+
+/// General purpose register, in the order of the machine encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    Rax,
+    Rcx,
+    Rdx,
+    Rbx,
+    Rsp,
+    Rbp,
+    Rsi,
+    Rdi,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+}
+
+impl Register {
+    /// Registers holding intermediate results, in the order of allocation.
+    /// `rax`, `rcx` and `rdx` are left for divisions, shifts and spilled operands.
+    pub const ALLOCATABLE: [Self; 11] = [
+        Self::Rbx,
+        Self::Rsi,
+        Self::Rdi,
+        Self::R8,
+        Self::R9,
+        Self::R10,
+        Self::R11,
+        Self::R12,
+        Self::R13,
+        Self::R14,
+        Self::R15,
+    ];
+
+    /// Returns the number of the register in the machine encoding.
+    pub fn number(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the name of the whole 64-bit register.
+    pub fn name64(self) -> &'static str {
+        [
+            "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11",
+            "r12", "r13", "r14", "r15",
+        ][self as usize]
+    }
+
+    /// Returns the name of the lower 32 bits of the register.
+    pub fn name32(self) -> &'static str {
+        [
+            "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "r8d", "r9d", "r10d", "r11d",
+            "r12d", "r13d", "r14d", "r15d",
+        ][self as usize]
+    }
+}
+
+/// 32-bit operand of an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Register(Register),
+    Immediate(i32),
+    /// 32-bit slot with the given index below the frame pointer `rbp`.
+    Local(usize),
+}
+
+impl Operand {
+    /// Returns the displacement of the slot from `rbp`.
+    pub fn local_offset(slot: usize) -> i32 {
+        -4 * (slot as i32 + 1)
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Register(register) => write!(f, "%{}", register.name32()),
+            Self::Immediate(val) => write!(f, "${}", val),
+            Self::Local(slot) => write!(f, "{}(%rbp)", Self::local_offset(*slot)),
+        }
+    }
+}
+
+/// Target of jumps and calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Label {
+    /// Label of the runtime.
+    Named(&'static str),
+    /// Local label inside the main code.
+    Numbered(usize),
+}
+
+impl Display for Label {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Named(name) => f.write_str(name),
+            Self::Numbered(idx) => write!(f, ".L{}", idx),
+        }
+    }
+}
+
+/// Two-operand arithmetic instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithOp {
+    Add,
+    Sub,
+    Imul,
+}
+
+/// Shift instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftOp {
+    Shl,
+    Sar,
+    Shr,
+}
+
+/// Condition of a jump, after a `cmp` or an arithmetic instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    Equal,
+    NotEqual,
+    GreaterEqual,
+    Overflow,
+}
+
+/// Instruction or directive of the generated assembly.
+/// Arithmetic is 32-bit, only the stack and addresses use whole registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Label(Label),
+    /// Copies the source to the destination, at most one of them in memory.
+    Mov {
+        src: Operand,
+        dst: Operand,
+    },
+    Arith {
+        op: ArithOp,
+        src: Operand,
+        dst: Register,
+    },
+    /// Compares the operand, a register or a slot, with the immediate.
+    Cmp {
+        operand: Operand,
+        val: i32,
+    },
+    Neg(Register),
+    /// Sign-extends `eax` into `edx`.
+    Cdq,
+    /// Signed division of `edx:eax` by the operand, a register or a slot.
+    Idiv(Operand),
+    /// Unsigned division of `edx:eax` by the register.
+    Div(Register),
+    /// Signed multiplication of `eax` by the operand, a register or a slot, into `edx:eax`.
+    WideImul(Operand),
+    /// Shifts the register by the given amount, or by `cl` if it is [None].
+    Shift {
+        op: ShiftOp,
+        amount: Option<u8>,
+        dst: Register,
+    },
+    /// Jumps to the label if the condition holds, or unconditionally.
+    Jump {
+        condition: Option<Condition>,
+        target: Label,
+    },
+    Call(Label),
+    Ret,
+    Syscall,
+    Push(Register),
+    Pop(Register),
+    /// Sets the frame pointer `rbp` to the stack pointer.
+    SetFrame,
+    /// Allocates the given number of bytes on the stack.
+    Reserve(u32),
+    /// Frees the given number of bytes on the stack.
+    Release(u32),
+    /// Stores `dl` at `rsi` bytes above the stack pointer.
+    StoreByte,
+    /// Sets `rsi` to its sum with the stack pointer.
+    AddressOnStack,
+    /// Loads the address of the label into the register.
+    Address {
+        label: Label,
+        dst: Register,
+    },
+    /// Bytes of the string, placed in the code.
+    Ascii(&'static str),
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Label(label) => write!(f, "{}:", label),
+            Self::Mov { src, dst } => write!(f, "\tmovl {}, {}", src, dst),
+            Self::Arith { op, src, dst } => {
+                let name = match op {
+                    ArithOp::Add => "addl",
+                    ArithOp::Sub => "subl",
+                    ArithOp::Imul => "imull",
+                };
+                write!(f, "\t{} {}, %{}", name, src, dst.name32())
+            }
+            Self::Cmp { operand, val } => write!(f, "\tcmpl ${}, {}", val, operand),
+            Self::Neg(register) => write!(f, "\tnegl %{}", register.name32()),
+            Self::Cdq => f.write_str("\tcltd"),
+            Self::Idiv(operand) => write!(f, "\tidivl {}", operand),
+            Self::Div(register) => write!(f, "\tdivl %{}", register.name32()),
+            Self::WideImul(operand) => write!(f, "\timull {}", operand),
+            Self::Shift { op, amount, dst } => {
+                let name = match op {
+                    ShiftOp::Shl => "shll",
+                    ShiftOp::Sar => "sarl",
+                    ShiftOp::Shr => "shrl",
+                };
+                match amount {
+                    Some(amount) => write!(f, "\t{} ${}, %{}", name, amount, dst.name32()),
+                    None => write!(f, "\t{} %cl, %{}", name, dst.name32()),
+                }
+            }
+            Self::Jump { condition, target } => {
+                let name = match condition {
+                    None => "jmp",
+                    Some(Condition::Equal) => "je",
+                    Some(Condition::NotEqual) => "jne",
+                    Some(Condition::GreaterEqual) => "jge",
+                    Some(Condition::Overflow) => "jo",
+                };
+                write!(f, "\t{} {}", name, target)
+            }
+            Self::Call(label) => write!(f, "\tcall {}", label),
+            Self::Ret => f.write_str("\tret"),
+            Self::Syscall => f.write_str("\tsyscall"),
+            Self::Push(register) => write!(f, "\tpushq %{}", register.name64()),
+            Self::Pop(register) => write!(f, "\tpopq %{}", register.name64()),
+            Self::SetFrame => f.write_str("\tmovq %rsp, %rbp"),
+            Self::Reserve(bytes) => write!(f, "\tsubq ${}, %rsp", bytes),
+            Self::Release(bytes) => write!(f, "\taddq ${}, %rsp", bytes),
+            Self::StoreByte => f.write_str("\tmovb %dl, (%rsp,%rsi)"),
+            Self::AddressOnStack => f.write_str("\tleaq (%rsp,%rsi), %rsi"),
+            Self::Address { label, dst } => {
+                write!(f, "\tleaq {}(%rip), %{}", label, dst.name64())
+            }
+            Self::Ascii(text) => write!(f, "\t.ascii \"{}\"", text.escape_default()),
+        }
+    }
+}
+
+const PRINT_INT: Label = Label::Named("printInt");
+const DIVZERO: Label = Label::Named("divzero");
+const OVERFLOW: Label = Label::Named("overflow");
+const FAIL: Label = Label::Named("fail");
+const DIVZERO_MSG: Label = Label::Named("divzero.msg");
+const OVERFLOW_MSG: Label = Label::Named("overflow.msg");
+const PRINT_INT_ABS: Label = Label::Named("printInt.abs");
+const PRINT_INT_DIGIT: Label = Label::Named("printInt.digit");
+const PRINT_INT_WRITE: Label = Label::Named("printInt.write");
+
+/// Returns `printInt`, printing `edi` in a separate line with a single `write` system call.
+/// The digits are written backwards into a buffer on the stack, from the absolute value
+/// treated as unsigned, so that the minimum needs no special case.
+fn print_int() -> Vec<Instruction> {
+    use Instruction::*;
+    use Register::*;
+
+    let mov = |val, register| Mov {
+        src: Operand::Immediate(val),
+        dst: Operand::Register(register),
+    };
+    let decrement = |register| Arith {
+        op: ArithOp::Sub,
+        src: Operand::Immediate(1),
+        dst: register,
+    };
+
+    vec![
+        Label(PRINT_INT),
+        Reserve(16),
+        Mov {
+            src: Operand::Register(Rdi),
+            dst: Operand::Register(Rax),
+        },
+        mov(15, Rsi),
+        mov(b'\n'.into(), Rdx),
+        StoreByte,
+        Cmp {
+            operand: Operand::Register(Rdi),
+            val: 0,
+        },
+        Jump {
+            condition: Some(Condition::GreaterEqual),
+            target: PRINT_INT_ABS,
+        },
+        Neg(Rax),
+        Label(PRINT_INT_ABS),
+        mov(10, Rcx),
+        Label(PRINT_INT_DIGIT),
+        mov(0, Rdx),
+        Div(Rcx),
+        Arith {
+            op: ArithOp::Add,
+            src: Operand::Immediate(b'0'.into()),
+            dst: Rdx,
+        },
+        decrement(Rsi),
+        StoreByte,
+        Cmp {
+            operand: Operand::Register(Rax),
+            val: 0,
+        },
+        Jump {
+            condition: Some(Condition::NotEqual),
+            target: PRINT_INT_DIGIT,
+        },
+        Cmp {
+            operand: Operand::Register(Rdi),
+            val: 0,
+        },
+        Jump {
+            condition: Some(Condition::GreaterEqual),
+            target: PRINT_INT_WRITE,
+        },
+        mov(b'-'.into(), Rdx),
+        decrement(Rsi),
+        StoreByte,
+        Label(PRINT_INT_WRITE),
+        mov(16, Rdx),
+        Arith {
+            op: ArithOp::Sub,
+            src: Operand::Register(Rsi),
+            dst: Rdx,
+        },
+        AddressOnStack,
+        mov(1, Rax),
+        mov(1, Rdi),
+        Syscall,
+        Release(16),
+        Ret,
+    ]
+}
+
+/// Returns the handlers of the runtime errors the program may jump to,
+/// writing the message to STDERR and exiting with code 1.
+fn error_handlers(divisions: bool, checked: bool) -> Vec<Instruction> {
+    use Instruction::*;
+    use Register::*;
+
+    let mov = |val, register| Mov {
+        src: Operand::Immediate(val),
+        dst: Operand::Register(register),
+    };
+
+    let mut instructions = Vec::new();
+    let messages = [
+        (divisions, DIVZERO, DIVZERO_MSG),
+        (checked, OVERFLOW, OVERFLOW_MSG),
+    ];
+    for (used, label, message) in messages {
+        if used {
+            instructions.extend([
+                Label(label),
+                Address {
+                    label: message,
+                    dst: Rsi,
+                },
+                Jump {
+                    condition: None,
+                    target: FAIL,
+                },
+            ]);
+        }
+    }
+    if instructions.is_empty() {
+        return instructions;
+    }
+
+    // Both messages are 17 bytes long.
+    instructions.extend([
+        Label(FAIL),
+        mov(17, Rdx),
+        mov(1, Rax),
+        mov(2, Rdi),
+        Syscall,
+        mov(60, Rax),
+        mov(1, Rdi),
+        Syscall,
+    ]);
+    if divisions {
+        instructions.extend([Label(DIVZERO_MSG), Ascii("division by zero\n")]);
+    }
+    if checked {
+        instructions.extend([Label(OVERFLOW_MSG), Ascii("integer overflow\n")]);
+    }
+
+    instructions
+}
+
+struct X86Builder<'p> {
+    program: &'p Program,
+    layout: Layout,
+    /// Sethi–Ullman number of every value computed in registers: the number of registers
+    /// needed to compute it without spilling.
+    needs: Vec<usize>,
+    /// Whether computing each value in registers may end the program with a runtime error.
+    fallible: Vec<bool>,
+    registers: usize,
+    labels: usize,
+    /// Whether any division jumps to `divzero`.
+    divisions: bool,
+    instructions: Vec<Instruction>,
+}
+
+impl<'p> X86Builder<'p> {
+    /// Returns the operand usable directly as the right operand of an instruction,
+    /// [None] for values computed as a part of their user.
+    fn leaf(&self, operand: IrOperand) -> Option<Operand> {
+        match operand {
+            IrOperand::Const(val) => Some(Operand::Immediate(val)),
+            IrOperand::BigConst(idx) => Some(Operand::Immediate(ast::wrapped_literal(
+                &self.program.big_constants[idx],
+            ))),
+            IrOperand::Value(value) => match self.layout.storage[value.0] {
+                Storage::Local(slot) => Some(Operand::Local(slot)),
+                Storage::Inline | Storage::Dup | Storage::Discard => None,
+            },
+        }
+    }
+
+    /// Returns the number of registers needed to compute the operand, none for right operands
+    /// used directly by the instruction.
+    fn need(&self, operand: IrOperand, right: bool) -> usize {
+        match (self.leaf(operand), operand) {
+            (Some(_), _) => usize::from(!right),
+            (None, IrOperand::Value(value)) => self.needs[value.0],
+            (None, _) => unreachable!("constants are leaves"),
+        }
+    }
+
+    /// Returns whether computing the operand may end the program with a runtime error.
+    fn fallible(&self, operand: IrOperand) -> bool {
+        match (self.leaf(operand), operand) {
+            (None, IrOperand::Value(value)) => self.fallible[value.0],
+            _ => false,
+        }
+    }
+
+    /// Returns whether both operands may fail, so that they are computed from left to right
+    /// for the first error to be reported.
+    fn in_order(&self, lhs: IrOperand, rhs: IrOperand) -> bool {
+        self.fallible(lhs) && self.fallible(rhs)
+    }
+
+    /// Computes the Sethi–Ullman numbers of all values, definitions coming before uses.
+    fn count_needs(&mut self) {
+        for instruction in &self.program.instructions {
+            let (IrInstruction::Bin { dst, lhs, rhs, .. }
+            | IrInstruction::Machine { dst, lhs, rhs, .. }) = instruction
+            else {
+                continue;
+            };
+
+            let lhs_need = self.need(*lhs, false);
+            let rhs_need = self.need(*rhs, true);
+            self.needs[dst.0] = if self.duplicated(*lhs, *rhs) || lhs_need > rhs_need {
+                lhs_need
+            } else if lhs_need < rhs_need && !self.in_order(*lhs, *rhs) {
+                rhs_need
+            } else {
+                rhs_need + 1
+            };
+            self.fallible[dst.0] = instruction.may_fail(self.program.arithmetic)
+                || self.fallible(*lhs)
+                || self.fallible(*rhs);
+        }
+    }
+
+    fn duplicated(&self, lhs: IrOperand, rhs: IrOperand) -> bool {
+        match (lhs, lhs == rhs) {
+            (IrOperand::Value(value), true) => self.layout.storage[value.0] == Storage::Dup,
+            _ => false,
+        }
+    }
+
+    fn label(&mut self) -> Label {
+        self.labels += 1;
+        Label::Numbered(self.labels - 1)
+    }
+
+    fn register(&self, idx: usize) -> Register {
+        Register::ALLOCATABLE[idx]
+    }
+
+    /// Computes the operand into the register with the given index in the allocation order,
+    /// using only the registers after it.
+    fn process_operand(&mut self, operand: IrOperand, base: usize) {
+        let dst = Operand::Register(self.register(base));
+        match (self.leaf(operand), operand) {
+            (Some(src), _) => self.instructions.push(Instruction::Mov { src, dst }),
+            (None, IrOperand::Value(value)) => {
+                let definition = self.layout.definitions[value.0];
+                self.process_definition(&self.program.instructions[definition], base);
+            }
+            (None, _) => unreachable!("constants are leaves"),
+        }
+    }
+
+    fn process_definition(&mut self, instruction: &IrInstruction, base: usize) {
+        match instruction {
+            IrInstruction::Bin { lhs, op, rhs, .. } => {
+                self.process_bin(*lhs, *rhs, BinOp::Bin(*op), base)
+            }
+            IrInstruction::Machine { lhs, op, rhs, .. } => {
+                self.process_bin(*lhs, *rhs, BinOp::Machine(*op), base)
+            }
+            IrInstruction::Print(_) => unreachable!("values are never defined by prints"),
+        }
+    }
+
+    /// Computes both operands in the Sethi–Ullman order, or from left to right if both may fail,
+    /// and applies the operation. Spills the first computed operand when the other one needs
+    /// all the remaining registers.
+    fn process_bin(&mut self, lhs: IrOperand, rhs: IrOperand, op: BinOp, base: usize) {
+        let dst = self.register(base);
+        if self.duplicated(lhs, rhs) {
+            self.process_operand(lhs, base);
+            return self.apply(op, Operand::Register(dst), dst);
+        }
+        if let Some(rhs) = self.leaf(rhs) {
+            self.process_operand(lhs, base);
+            return self.apply(op, rhs, dst);
+        }
+
+        let available = self.registers - base;
+        let lhs_need = self.need(lhs, false);
+        let rhs_need = self.need(rhs, true);
+        let in_order = self.in_order(lhs, rhs);
+        if in_order && rhs_need >= available {
+            self.process_operand(lhs, base);
+            self.instructions.push(Instruction::Push(dst));
+            self.process_operand(rhs, base);
+            self.instructions.extend([
+                Instruction::Mov {
+                    src: Operand::Register(dst),
+                    dst: Operand::Register(Register::Rcx),
+                },
+                Instruction::Pop(dst),
+            ]);
+            self.apply(op, Operand::Register(Register::Rcx), dst);
+        } else if lhs_need >= available && rhs_need >= available {
+            self.process_operand(rhs, base);
+            self.instructions.push(Instruction::Push(dst));
+            self.process_operand(lhs, base);
+            self.instructions.push(Instruction::Pop(Register::Rcx));
+            self.apply(op, Operand::Register(Register::Rcx), dst);
+        } else if in_order || lhs_need >= rhs_need {
+            self.process_operand(lhs, base);
+            self.process_operand(rhs, base + 1);
+            self.apply(op, Operand::Register(self.register(base + 1)), dst);
+        } else {
+            self.process_operand(rhs, base);
+            self.process_operand(lhs, base + 1);
+            let lhs = self.register(base + 1);
+            if op.commutative() {
+                self.apply(op, Operand::Register(lhs), dst);
+            } else {
+                self.apply(op, Operand::Register(dst), lhs);
+                self.instructions.push(Instruction::Mov {
+                    src: Operand::Register(lhs),
+                    dst: Operand::Register(dst),
+                });
+            }
+        }
+    }
+
+    /// Applies the operation to the register and the operand, a register, a slot or an immediate.
+    fn apply(&mut self, op: BinOp, src: Operand, dst: Register) {
+        let checked = self.program.arithmetic == Arithmetic::Checked;
+        let arith = |op| Instruction::Arith { op, src, dst };
+        match op {
+            BinOp::Bin(Op::Div) => self.divide(src, dst),
+            BinOp::Bin(op) => {
+                self.instructions.push(arith(match op {
+                    Op::Add => ArithOp::Add,
+                    Op::Sub => ArithOp::Sub,
+                    Op::Mul => ArithOp::Imul,
+                    Op::Div => unreachable!("division is handled separately"),
+                }));
+                if checked {
+                    self.instructions.push(Instruction::Jump {
+                        condition: Some(Condition::Overflow),
+                        target: OVERFLOW,
+                    });
+                }
+            }
+            BinOp::Machine(MachineOp::MulHigh) => {
+                let src = self.addressable(src);
+                self.instructions.extend([
+                    Instruction::Mov {
+                        src: Operand::Register(dst),
+                        dst: Operand::Register(Register::Rax),
+                    },
+                    Instruction::WideImul(src),
+                    Instruction::Mov {
+                        src: Operand::Register(Register::Rdx),
+                        dst: Operand::Register(dst),
+                    },
+                ]);
+            }
+            BinOp::Machine(op) => {
+                let op = match op {
+                    MachineOp::Shl => ShiftOp::Shl,
+                    MachineOp::Sar => ShiftOp::Sar,
+                    MachineOp::Shr => ShiftOp::Shr,
+                    MachineOp::MulHigh => unreachable!("high multiplication is handled above"),
+                };
+                // The amount is taken modulo 32 by the processor.
+                let amount = match src {
+                    Operand::Immediate(val) => Some((val & 31) as u8),
+                    Operand::Register(Register::Rcx) => None,
+                    src => {
+                        self.instructions.push(Instruction::Mov {
+                            src,
+                            dst: Operand::Register(Register::Rcx),
+                        });
+                        None
+                    }
+                };
+                self.instructions
+                    .push(Instruction::Shift { op, amount, dst });
+            }
+        }
+    }
+
+    /// Moves an immediate operand to `ecx`, for instructions taking only registers and slots.
+    fn addressable(&mut self, operand: Operand) -> Operand {
+        match operand {
+            Operand::Immediate(_) => {
+                self.instructions.push(Instruction::Mov {
+                    src: operand,
+                    dst: Operand::Register(Register::Rcx),
+                });
+                Operand::Register(Register::Rcx)
+            }
+            operand => operand,
+        }
+    }
+
+    /// Divides the register by the operand. Unless the divisor is a constant other than 0 and -1,
+    /// it is checked for zero and the division by -1 is a negation, as `idiv` faults
+    /// on the overflow of the minimum divided by -1.
+    fn divide(&mut self, src: Operand, dst: Register) {
+        let guarded = !matches!(src, Operand::Immediate(val) if val != 0 && val != -1);
+        let src = self.addressable(src);
+        let divide = [
+            Instruction::Mov {
+                src: Operand::Register(dst),
+                dst: Operand::Register(Register::Rax),
+            },
+            Instruction::Cdq,
+            Instruction::Idiv(src),
+            Instruction::Mov {
+                src: Operand::Register(Register::Rax),
+                dst: Operand::Register(dst),
+            },
+        ];
+        if !guarded {
+            return self.instructions.extend(divide);
+        }
+
+        self.divisions = true;
+        let (division, done) = (self.label(), self.label());
+        self.instructions.extend([
+            Instruction::Cmp {
+                operand: src,
+                val: 0,
+            },
+            Instruction::Jump {
+                condition: Some(Condition::Equal),
+                target: DIVZERO,
+            },
+            Instruction::Cmp {
+                operand: src,
+                val: -1,
+            },
+            Instruction::Jump {
+                condition: Some(Condition::NotEqual),
+                target: division,
+            },
+            Instruction::Neg(dst),
+        ]);
+        if self.program.arithmetic == Arithmetic::Checked {
+            self.instructions.push(Instruction::Jump {
+                condition: Some(Condition::Overflow),
+                target: OVERFLOW,
+            });
+        }
+        self.instructions.extend([
+            Instruction::Jump {
+                condition: None,
+                target: done,
+            },
+            Instruction::Label(division),
+        ]);
+        self.instructions.extend(divide);
+        self.instructions.push(Instruction::Label(done));
+    }
+
+    fn add_instruction(&mut self, instruction: &IrInstruction) {
+        let first = Operand::Register(self.register(0));
+        match instruction {
+            IrInstruction::Print(operand) => {
+                let src = match self.leaf(*operand) {
+                    Some(src) => src,
+                    None => {
+                        self.process_operand(*operand, 0);
+                        first
+                    }
+                };
+                self.instructions.extend([
+                    Instruction::Mov {
+                        src,
+                        dst: Operand::Register(Register::Rdi),
+                    },
+                    Instruction::Call(PRINT_INT),
+                ]);
+            }
+            IrInstruction::Bin { dst, .. } | IrInstruction::Machine { dst, .. } => {
+                match self.layout.storage[dst.0] {
+                    Storage::Inline | Storage::Dup => {}
+                    Storage::Local(slot) => {
+                        self.process_definition(instruction, 0);
+                        self.instructions.push(Instruction::Mov {
+                            src: first,
+                            dst: Operand::Local(slot),
+                        });
+                    }
+                    Storage::Discard => self.process_definition(instruction, 0),
+                }
+            }
+        }
+    }
+
+    fn build(mut self) -> Assembly {
+        self.instructions.extend([
+            Instruction::Mov {
+                src: Operand::Immediate(60),
+                dst: Operand::Register(Register::Rax),
+            },
+            Instruction::Mov {
+                src: Operand::Immediate(0),
+                dst: Operand::Register(Register::Rdi),
+            },
+            Instruction::Syscall,
+        ]);
+        self.instructions.extend(print_int());
+        self.instructions.extend(error_handlers(
+            self.divisions,
+            self.program.arithmetic == Arithmetic::Checked,
+        ));
+
+        Assembly {
+            instructions: self.instructions,
+        }
+    }
+}
+
+/// Operation of an [IrInstruction] computed in registers.
+#[derive(Debug, Clone, Copy)]
+enum BinOp {
+    Bin(Op),
+    Machine(MachineOp),
+}
+
+impl BinOp {
+    fn commutative(self) -> bool {
+        match self {
+            Self::Bin(op) => op.commutative(),
+            Self::Machine(op) => op == MachineOp::MulHigh,
+        }
+    }
+}
+
+/// x86-64 assembly of an Instant program, with its entry point `_start` and the runtime.
+pub struct Assembly {
+    pub(crate) instructions: Vec<Instruction>,
+}
+
+impl Display for Assembly {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "\t.text")?;
+        writeln!(f, "\t.globl _start")?;
+        for instruction in &self.instructions {
+            writeln!(f, "{}", instruction)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{self, Execution};
+    use std::{env, fs, process::Command};
+
+    /// Assembles, links and runs the assembly.
+    /// Returns [None] if `as` or `ld` is not available.
+    fn execute(assembly: &Assembly) -> Option<Execution> {
+        let path = env::temp_dir().join(format!("instant-x86-{}", std::process::id()));
+        let (source, object) = (path.with_extension("s"), path.with_extension("o"));
+        fs::write(&source, assembly.to_string()).unwrap();
+
+        let assembled = Command::new("as")
+            .arg("-o")
+            .arg(&object)
+            .arg(&source)
+            .status()
+            .ok()?;
+        assert!(assembled.success());
+        let linked = Command::new("ld")
+            .arg("-o")
+            .arg(&path)
+            .arg(&object)
+            .status()
+            .ok()?;
+        assert!(linked.success());
+        let execution = Execution::of(&mut Command::new(&path)).unwrap();
+
+        for file in [source, object, path] {
+            fs::remove_file(file).unwrap();
+        }
+        Some(execution)
+    }
+
+    #[test]
+    fn generation() {
+        let assembly = X86Backend::default().process(&testing::program(
+            "a = 7 / 2; 7 - a * a; a",
+            Arithmetic::Wrapping,
+        ));
+        assert_eq!(
+            assembly.to_string().lines().take(22).collect::<Vec<_>>(),
+            [
+                "\t.text",
+                "\t.globl _start",
+                "_start:",
+                "\tmovq %rsp, %rbp",
+                "\tsubq $16, %rsp",
+                "\tmovl $7, %ebx",
+                "\tmovl $2, %ecx",
+                "\tmovl %ebx, %eax",
+                "\tcltd",
+                "\tidivl %ecx",
+                "\tmovl %eax, %ebx",
+                "\tmovl %ebx, -4(%rbp)",
+                "\tmovl $7, %ebx",
+                "\tmovl -4(%rbp), %esi",
+                "\timull -4(%rbp), %esi",
+                "\tsubl %esi, %ebx",
+                "\tmovl %ebx, %edi",
+                "\tcall printInt",
+                "\tmovl -4(%rbp), %edi",
+                "\tcall printInt",
+                "\tmovl $60, %eax",
+                "\tmovl $0, %edi",
+            ]
+        );
+        // The division by a constant cannot fail.
+        assert!(!assembly.to_string().contains("divzero"));
+    }
+
+    #[test]
+    fn spilling() {
+        let sources = [
+            "a = 1; b = 2; ((a - b) - (b - a)) - ((a - 9) * (b / a)) - 9",
+            "a = 100; (a * 8 + a * 16) / 5 - (a * 4 - a * 32) / (0 - 7)",
+            "a = 2147483647; 1; (a - 3) * (a / (a - 2147483647))",
+            "a = 0 - 2147483647 - 1; a / (0 - 1); a / (a - a - 1); (0 - 5) / 2",
+        ];
+
+        for registers in [1, 2, Register::ALLOCATABLE.len()] {
+            testing::check(&sources, |program| {
+                let assembly = X86Backend::default()
+                    .with_registers(registers)
+                    .process(program);
+                let spills = assembly
+                    .instructions
+                    .iter()
+                    .filter(|instruction| matches!(instruction, Instruction::Push(_)))
+                    .count();
+                match registers {
+                    1 => assert!(spills > 0),
+                    2 => {}
+                    _ => assert_eq!(spills, 0),
+                }
+
+                execute(&assembly)
+            });
+        }
+    }
+}
@@ -0,0 +1,623 @@
+//! Debug Adapter Protocol server over stdio, running Instant programs with the step debugger.
+//! Instant programs have a single thread with a single stack frame, so all variables are in one
+//! scope.
+
+use instant::{
+    ast::Stmt,
+    debugger::{Debugger, Stop},
+    interpreter::RuntimeError,
+    lines::Lines,
+    parser,
+    transport::{read_message, write_message, ReadError},
+    Arithmetic,
+};
+use serde_json::{json, Value};
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::Path,
+    process::ExitCode,
+};
+
+const THREAD_ID: i64 = 1;
+const FRAME_ID: i64 = 1;
+/// Reference of the scope holding all variables.
+const VARIABLES_REFERENCE: i64 = 1;
+
+/// Output of the server, numbering the sent messages.
+struct Connection<'o> {
+    output: &'o mut dyn Write,
+    seq: i64,
+    /// Numbers of the first line and column used by the client, 0 or 1.
+    line_base: usize,
+    column_base: usize,
+}
+
+impl Connection<'_> {
+    fn send(&mut self, mut message: Value) -> Result<(), String> {
+        message["seq"] = self.seq.into();
+        self.seq += 1;
+        write_message(self.output, &message)
+    }
+
+    fn respond(&mut self, request: &Value, body: Value) -> Result<(), String> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }))
+    }
+
+    fn fail(&mut self, request: &Value, message: &str) -> Result<(), String> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        }))
+    }
+
+    /// Reads the next request, answering the messages failing to parse with an error response.
+    /// Returns [None] at the end of the input.
+    fn receive(&mut self, input: &mut dyn BufRead) -> Result<Option<Value>, String> {
+        loop {
+            match read_message(input) {
+                Ok(request) => return Ok(request),
+                // The sequence number and command of a message that failed to parse are unknown.
+                Err(ReadError::Parse(e)) => self.fail(&Value::Null, &e)?,
+                Err(ReadError::Framing(e)) => return Err(e),
+            }
+        }
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> Result<(), String> {
+        self.send(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }))
+    }
+}
+
+/// Arguments of the launch request.
+struct Launch {
+    path: String,
+    source: String,
+    arithmetic: Arithmetic,
+    stop_on_entry: bool,
+}
+
+impl Launch {
+    fn new(arguments: &Value) -> Result<Self, String> {
+        let path = arguments["program"]
+            .as_str()
+            .ok_or("missing program path")?
+            .to_string();
+        let source =
+            fs::read_to_string(&path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+        let arithmetic = match arguments["arithmetic"].as_str() {
+            None | Some("wrap") => Arithmetic::Wrapping,
+            Some("trap") => Arithmetic::Checked,
+            Some("bigint") => Arithmetic::Unbounded,
+            Some(other) => return Err(format!("invalid arithmetic {}", other)),
+        };
+
+        Ok(Self {
+            path,
+            source,
+            arithmetic,
+            stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+        })
+    }
+}
+
+/// Handles the requests preceding a launch. Returns the launch request with its arguments,
+/// or [None] if the client disconnects before.
+fn configure(
+    input: &mut dyn BufRead,
+    connection: &mut Connection,
+) -> Result<Option<(Value, Launch)>, String> {
+    while let Some(request) = connection.receive(input)? {
+        match request["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                let arguments = &request["arguments"];
+                connection.line_base =
+                    arguments["linesStartAt1"].as_bool().unwrap_or(true) as usize;
+                connection.column_base =
+                    arguments["columnsStartAt1"].as_bool().unwrap_or(true) as usize;
+                connection.respond(
+                    &request,
+                    json!({
+                        "supportsConfigurationDoneRequest": true,
+                        "supportsEvaluateForHovers": true,
+                    }),
+                )?;
+            }
+            "launch" => match Launch::new(&request["arguments"]) {
+                Ok(launch) => return Ok(Some((request, launch))),
+                Err(e) => connection.fail(&request, &e)?,
+            },
+            "disconnect" => {
+                connection.respond(&request, json!({}))?;
+                return Ok(None);
+            }
+            _ => connection.fail(&request, "no program has been launched")?,
+        }
+    }
+
+    Ok(None)
+}
+
+/// Debugging session of a launched program.
+struct Session<'a, 'o> {
+    connection: Connection<'o>,
+    launch: &'a Launch,
+    lines: Lines,
+    debugger: Debugger<'a>,
+}
+
+impl Session<'_, '_> {
+    /// Handles the requests until the client disconnects.
+    fn run(mut self, input: &mut dyn BufRead) -> Result<(), String> {
+        while let Some(request) = self.connection.receive(input)? {
+            let arguments = &request["arguments"];
+            match request["command"].as_str().unwrap_or_default() {
+                "setBreakpoints" => {
+                    let body = self.set_breakpoints(arguments);
+                    self.connection.respond(&request, body)?;
+                }
+                "setExceptionBreakpoints" => {
+                    self.connection
+                        .respond(&request, json!({ "breakpoints": [] }))?;
+                }
+                "configurationDone" => {
+                    self.connection.respond(&request, json!({}))?;
+                    if self.launch.stop_on_entry {
+                        self.stopped("entry", None)?;
+                    } else {
+                        self.execute(true)?;
+                    }
+                }
+                "threads" => {
+                    self.connection.respond(
+                        &request,
+                        json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] }),
+                    )?;
+                }
+                "stackTrace" => {
+                    let body = self.stack_trace();
+                    self.connection.respond(&request, body)?;
+                }
+                "scopes" => {
+                    let scope = json!({
+                        "name": "Variables",
+                        "presentationHint": "locals",
+                        "variablesReference": VARIABLES_REFERENCE,
+                        "expensive": false,
+                    });
+                    self.connection
+                        .respond(&request, json!({ "scopes": [scope] }))?;
+                }
+                "variables" => {
+                    let body = self.variables(arguments);
+                    self.connection.respond(&request, body)?;
+                }
+                "evaluate" => match self.evaluate(arguments) {
+                    Ok(result) => self.connection.respond(
+                        &request,
+                        json!({ "result": result, "variablesReference": 0 }),
+                    )?,
+                    Err(e) => self.connection.fail(&request, &e)?,
+                },
+                "next" | "stepIn" | "stepOut" => {
+                    self.connection.respond(&request, json!({}))?;
+                    self.execute(false)?;
+                }
+                "continue" => {
+                    self.connection
+                        .respond(&request, json!({ "allThreadsContinued": true }))?;
+                    self.execute(true)?;
+                }
+                // The program runs only between the requests, so it is always paused.
+                "pause" => self.connection.respond(&request, json!({}))?,
+                "disconnect" | "terminate" => {
+                    self.connection.respond(&request, json!({}))?;
+                    return Ok(());
+                }
+                command => self
+                    .connection
+                    .fail(&request, &format!("unsupported request {}", command))?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the breakpoints with the requested ones. Breakpoints on lines where no statement
+    /// starts are not verified.
+    fn set_breakpoints(&mut self, arguments: &Value) -> Value {
+        let lines = self.debugger.breakpoints().collect::<Vec<_>>();
+        for line in lines {
+            self.debugger.remove_breakpoint(line);
+        }
+
+        let same_source = arguments["source"]["path"].as_str() == Some(&self.launch.path);
+        let breakpoints = arguments["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|breakpoint| {
+                let line = breakpoint["line"].as_u64().unwrap_or_default() as usize;
+                let verified = same_source
+                    && line + 1 > self.connection.line_base
+                    && self
+                        .debugger
+                        .add_breakpoint(line + 1 - self.connection.line_base);
+                json!({ "verified": verified, "line": line })
+            })
+            .collect::<Vec<_>>();
+
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn stack_trace(&self) -> Value {
+        let Some(location) = self.debugger.current() else {
+            return json!({ "stackFrames": [], "totalFrames": 0 });
+        };
+        let name = Path::new(&self.launch.path)
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let frame = json!({
+            "id": FRAME_ID,
+            "name": location.stmt,
+            "source": { "name": name, "path": self.launch.path },
+            "line": location.position.line() - 1 + self.connection.line_base,
+            "column": location.position.column() - 1 + self.connection.column_base,
+        });
+
+        json!({ "stackFrames": [frame], "totalFrames": 1 })
+    }
+
+    fn variables(&self, arguments: &Value) -> Value {
+        if arguments["variablesReference"] != VARIABLES_REFERENCE {
+            return json!({ "variables": [] });
+        }
+
+        let mut variables = self
+            .debugger
+            .interpreter()
+            .variables()
+            .iter()
+            .collect::<Vec<_>>();
+        variables.sort();
+        let variables = variables
+            .into_iter()
+            .map(|(name, val)| {
+                json!({ "name": name, "value": val.to_string(), "variablesReference": 0 })
+            })
+            .collect::<Vec<_>>();
+
+        json!({ "variables": variables })
+    }
+
+    /// Evaluates the expression with the current values of the variables.
+    fn evaluate(&self, arguments: &Value) -> Result<String, String> {
+        let expression = arguments["expression"].as_str().unwrap_or_default();
+        let lines = Lines::new(expression);
+        let mut stmts = parser::parse(expression, self.launch.arithmetic)
+            .map_err(|e| format!("{} at {}", e, lines.position(e.range().0)))?;
+        let exp = match (stmts.pop(), stmts.is_empty()) {
+            (Some(Stmt::Exp(exp)), true) => exp,
+            _ => return Err(format!("{} is not an expression", expression)),
+        };
+
+        self.debugger
+            .interpreter()
+            .evaluate(&exp)
+            .map(|val| val.to_string())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Executes one statement or resumes the program, reporting where it stops.
+    fn execute(&mut self, resume: bool) -> Result<(), String> {
+        let mut output = Vec::new();
+        let stop = if resume {
+            self.debugger.resume(&mut output)
+        } else {
+            self.debugger.step(&mut output)
+        };
+        if !output.is_empty() {
+            let output = String::from_utf8_lossy(&output);
+            self.connection
+                .event("output", json!({ "category": "stdout", "output": output }))?;
+        }
+
+        match stop {
+            Ok(Stop::Step) => self.stopped("step", None),
+            Ok(Stop::Breakpoint(_)) => self.stopped("breakpoint", None),
+            Ok(Stop::Finished) => {
+                self.connection.event("exited", json!({ "exitCode": 0 }))?;
+                self.connection.event("terminated", json!({}))
+            }
+            Err(e) => {
+                let message = match e {
+                    RuntimeError::UndeclaredVariable(e) => format!(
+                        "undeclared variable {} at {}",
+                        e.name,
+                        self.lines.position(e.byte_offset)
+                    ),
+                    e => e.to_string(),
+                };
+                self.connection.event(
+                    "output",
+                    json!({ "category": "stderr", "output": format!("ERROR: {}\n", message) }),
+                )?;
+                self.stopped("exception", Some(&message))
+            }
+        }
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<&str>) -> Result<(), String> {
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(text) = text {
+            body["text"] = text.into();
+        }
+
+        self.connection.event("stopped", body)
+    }
+}
+
+/// Serves a single debugging session. A launch failing to parse the program can be retried.
+fn serve(input: &mut dyn BufRead, output: &mut dyn Write) -> Result<(), String> {
+    let mut connection = Connection {
+        output,
+        seq: 1,
+        line_base: 1,
+        column_base: 1,
+    };
+
+    while let Some((request, launch)) = configure(input, &mut connection)? {
+        let lines = Lines::new(&launch.source);
+        match parser::parse_spanned(&launch.source, launch.arithmetic) {
+            Ok(stmts) => {
+                connection.respond(&request, json!({}))?;
+                connection.event("initialized", json!({}))?;
+                let session = Session {
+                    connection,
+                    launch: &launch,
+                    lines,
+                    debugger: Debugger::new(&launch.source, stmts, launch.arithmetic),
+                };
+                return session.run(input);
+            }
+            Err(e) => {
+                let message = format!("{} at {}", e, lines.position(e.range().0));
+                connection.fail(&request, &message)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match serve(&mut io::stdin().lock(), &mut io::stdout().lock()) {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Runs the session with the requests, numbered in order, and returns the sent messages.
+    fn session(requests: &[Value]) -> Vec<Value> {
+        let mut input = Vec::new();
+        for (seq, request) in requests.iter().enumerate() {
+            let mut request = request.clone();
+            request["seq"] = (seq + 1).into();
+            request["type"] = "request".into();
+            write_message(&mut input, &request).unwrap();
+        }
+
+        receive(&input)
+    }
+
+    /// Serves the raw input and returns the sent messages.
+    fn receive(input: &[u8]) -> Vec<Value> {
+        let mut output = Vec::new();
+        serve(&mut &input[..], &mut output).unwrap();
+        let mut output = &output[..];
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut output).unwrap() {
+            messages.push(message);
+        }
+        messages
+    }
+
+    /// Returns the program written to a temporary file.
+    fn program(name: &str, source: &str) -> String {
+        let path = std::env::temp_dir().join(format!("instant-dap-{}.ins", name));
+        fs::write(&path, source).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    /// Returns the command of a response, with ! if it failed, or the name of an event.
+    fn summary(message: &Value) -> String {
+        match message["type"].as_str() {
+            Some("response") if message["success"] == true => {
+                message["command"].as_str().unwrap().into()
+            }
+            Some("response") => format!("!{}", message["command"].as_str().unwrap()),
+            _ => {
+                let event = message["event"].as_str().unwrap();
+                match message["body"]["reason"].as_str() {
+                    Some(reason) => format!("{} {}", event, reason),
+                    None => event.into(),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn debugging() {
+        let path = program("debugging", "a = 6;\nb = a * 7;\nb;\nc = b / (a - 6);\n1\n");
+        let messages = session(&[
+            json!({ "command": "initialize", "arguments": { "adapterID": "instant" } }),
+            json!({ "command": "threads" }),
+            json!({ "command": "launch", "arguments": { "program": path, "stopOnEntry": true } }),
+            json!({
+                "command": "setBreakpoints",
+                "arguments": { "source": { "path": path }, "breakpoints": [{ "line": 3 }, { "line": 9 }] },
+            }),
+            json!({ "command": "configurationDone" }),
+            json!({ "command": "next", "arguments": { "threadId": 1 } }),
+            json!({ "command": "stackTrace", "arguments": { "threadId": 1 } }),
+            json!({ "command": "continue", "arguments": { "threadId": 1 } }),
+            json!({ "command": "scopes", "arguments": { "frameId": 1 } }),
+            json!({ "command": "variables", "arguments": { "variablesReference": 1 } }),
+            json!({ "command": "evaluate", "arguments": { "expression": "b - a" } }),
+            json!({ "command": "evaluate", "arguments": { "expression": "b = 1" } }),
+            json!({ "command": "continue", "arguments": { "threadId": 1 } }),
+            json!({ "command": "stackTrace", "arguments": { "threadId": 1 } }),
+            json!({ "command": "disconnect" }),
+        ]);
+
+        assert_eq!(
+            messages.iter().map(summary).collect::<Vec<_>>(),
+            [
+                "initialize",
+                "!threads",
+                "launch",
+                "initialized",
+                "setBreakpoints",
+                "configurationDone",
+                "stopped entry",
+                "next",
+                "stopped step",
+                "stackTrace",
+                "continue",
+                "stopped breakpoint",
+                "scopes",
+                "variables",
+                "evaluate",
+                "!evaluate",
+                "continue",
+                "output",
+                "output",
+                "stopped exception",
+                "stackTrace",
+                "disconnect",
+            ]
+        );
+        assert!(messages
+            .iter()
+            .enumerate()
+            .all(|(idx, message)| message["seq"] == idx + 1));
+
+        assert_eq!(
+            messages[4]["body"]["breakpoints"],
+            json!([{ "verified": true, "line": 3 }, { "verified": false, "line": 9 }])
+        );
+        let frame = &messages[9]["body"]["stackFrames"][0];
+        assert_eq!(
+            (&frame["name"], &frame["line"], &frame["column"]),
+            (&json!("b = a * 7"), &json!(2), &json!(1))
+        );
+        assert_eq!(messages[17]["body"]["output"], "42\n");
+        assert_eq!(
+            messages[13]["body"]["variables"],
+            json!([
+                { "name": "a", "value": "6", "variablesReference": 0 },
+                { "name": "b", "value": "42", "variablesReference": 0 },
+            ])
+        );
+        assert_eq!(messages[14]["body"]["result"], "36");
+        assert_eq!(messages[15]["message"], "b = 1 is not an expression");
+        assert_eq!(messages[18]["body"]["category"], "stderr");
+        assert_eq!(messages[19]["body"]["text"], "division by zero");
+        let frame = &messages[20]["body"]["stackFrames"][0];
+        assert_eq!(frame["name"], "c = b / (a - 6)");
+    }
+
+    #[test]
+    fn launching() {
+        let invalid = program("launching-invalid", "a = 1;\nb = (a");
+        let valid = program("launching-valid", "1;\n4294967296");
+        let messages = session(&[
+            json!({ "command": "initialize", "arguments": { "linesStartAt1": false } }),
+            json!({ "command": "launch", "arguments": { "program": "/nonexistent/file.ins" } }),
+            json!({ "command": "launch", "arguments": { "program": invalid } }),
+            json!({ "command": "launch", "arguments": { "program": valid, "arithmetic": "bigint" } }),
+            json!({
+                "command": "setBreakpoints",
+                "arguments": { "source": { "path": valid }, "breakpoints": [{ "line": 1 }] },
+            }),
+            json!({ "command": "configurationDone" }),
+            json!({ "command": "stackTrace", "arguments": { "threadId": 1 } }),
+            json!({ "command": "continue", "arguments": { "threadId": 1 } }),
+            json!({ "command": "stackTrace", "arguments": { "threadId": 1 } }),
+        ]);
+
+        assert_eq!(
+            messages.iter().map(summary).collect::<Vec<_>>(),
+            [
+                "initialize",
+                "!launch",
+                "!launch",
+                "launch",
+                "initialized",
+                "setBreakpoints",
+                "configurationDone",
+                "output",
+                "stopped breakpoint",
+                "stackTrace",
+                "continue",
+                "output",
+                "exited",
+                "terminated",
+                "stackTrace",
+            ]
+        );
+        assert_eq!(
+            messages[2]["message"],
+            "unexpected EOF at (line 2, column 7)"
+        );
+        assert_eq!(messages[5]["body"]["breakpoints"][0]["verified"], true);
+        assert_eq!(messages[9]["body"]["stackFrames"][0]["line"], 1);
+        assert_eq!(messages[11]["body"]["output"], "4294967296\n");
+        assert_eq!(messages[14]["body"]["totalFrames"], 0);
+    }
+
+    #[test]
+    fn malformed_messages() {
+        let mut input = b"Content-Length: 10\r\n\r\n{\"seq\": 1,".to_vec();
+        let request = json!({ "seq": 2, "type": "request", "command": "initialize" });
+        write_message(&mut input, &request).unwrap();
+
+        let messages = receive(&input);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            (&messages[0]["type"], &messages[0]["success"]),
+            (&json!("response"), &json!(false))
+        );
+        assert!(messages[0]["message"]
+            .as_str()
+            .unwrap()
+            .starts_with("invalid message"));
+        assert_eq!(summary(&messages[1]), "initialize");
+        assert_eq!(messages[1]["request_seq"], 2);
+    }
+}
@@ -0,0 +1,517 @@
+//! Language Server Protocol server over stdio for Instant programs, built on the analysis in
+//! [instant::analysis]. Documents are synchronized in full on every change.
+//! Instant source is ASCII, so the UTF-16 columns of the protocol are byte columns.
+
+use instant::{
+    analysis::Analysis,
+    lines::Lines,
+    transport::{read_message, write_message, ReadError},
+    Arithmetic,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    process::ExitCode,
+};
+
+/// Error codes of JSON-RPC responses.
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// State of the server.
+struct Server<'o> {
+    output: &'o mut dyn Write,
+    arithmetic: Arithmetic,
+    /// Sources of the open documents by their URIs.
+    documents: HashMap<String, String>,
+    shutdown: bool,
+}
+
+impl Server<'_> {
+    fn respond(&mut self, request: &Value, result: Value) -> Result<(), String> {
+        write_message(
+            self.output,
+            &json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+        )
+    }
+
+    fn fail(&mut self, request: &Value, code: i64, message: &str) -> Result<(), String> {
+        write_message(
+            self.output,
+            &json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "error": { "code": code, "message": message },
+            }),
+        )
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> Result<(), String> {
+        write_message(
+            self.output,
+            &json!({ "jsonrpc": "2.0", "method": method, "params": params }),
+        )
+    }
+
+    /// Handles the messages until the `exit` notification. Returns whether `shutdown` preceded it.
+    fn run(&mut self, input: &mut dyn BufRead) -> Result<bool, String> {
+        loop {
+            let message = match read_message(input) {
+                Ok(Some(message)) => message,
+                Ok(None) => break,
+                // The id of a message that failed to parse is unknown.
+                Err(ReadError::Parse(e)) => {
+                    self.fail(&Value::Null, PARSE_ERROR, &e)?;
+                    continue;
+                }
+                Err(ReadError::Framing(e)) => return Err(e),
+            };
+            let params = &message["params"];
+            let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+            match message["method"].as_str().unwrap_or_default() {
+                "initialize" => {
+                    self.arithmetic = match params["initializationOptions"]["arithmetic"].as_str() {
+                        Some("trap") => Arithmetic::Checked,
+                        Some("bigint") => Arithmetic::Unbounded,
+                        _ => Arithmetic::Wrapping,
+                    };
+                    let capabilities = json!({
+                        "textDocumentSync": 1,
+                        "definitionProvider": true,
+                        "referencesProvider": true,
+                        "hoverProvider": true,
+                        "renameProvider": true,
+                        "documentFormattingProvider": true,
+                    });
+                    let server_info = json!({
+                        "name": "instant-lsp",
+                        "version": env!("CARGO_PKG_VERSION"),
+                    });
+                    self.respond(
+                        &message,
+                        json!({ "capabilities": capabilities, "serverInfo": server_info }),
+                    )?;
+                }
+                "textDocument/didOpen" => {
+                    let source = params["textDocument"]["text"].as_str().unwrap_or_default();
+                    self.documents.insert(uri.into(), source.into());
+                    self.publish_diagnostics(uri)?;
+                }
+                "textDocument/didChange" => {
+                    // With full synchronization the last change holds the whole document.
+                    let changes = params["contentChanges"].as_array();
+                    if let Some(source) = changes.and_then(|changes| changes.last()) {
+                        let source = source["text"].as_str().unwrap_or_default();
+                        self.documents.insert(uri.into(), source.into());
+                        self.publish_diagnostics(uri)?;
+                    }
+                }
+                "textDocument/didClose" => {
+                    self.documents.remove(uri);
+                    self.notify(
+                        "textDocument/publishDiagnostics",
+                        json!({ "uri": uri, "diagnostics": [] }),
+                    )?;
+                }
+                "textDocument/definition" => {
+                    let result = self
+                        .query(uri, &params["position"], |analysis, offset, lines| {
+                            let range = analysis.definition(offset)?;
+                            Some(json!({ "uri": uri, "range": range_json(lines, range) }))
+                        })
+                        .unwrap_or_default();
+                    self.respond(&message, result)?;
+                }
+                "textDocument/references" => {
+                    let include_declaration = params["context"]["includeDeclaration"]
+                        .as_bool()
+                        .unwrap_or(true);
+                    let result = self
+                        .query(uri, &params["position"], |analysis, offset, lines| {
+                            let locations = analysis
+                                .references(offset, include_declaration)
+                                .into_iter()
+                                .map(|range| json!({ "uri": uri, "range": range_json(lines, range) }))
+                                .collect::<Vec<_>>();
+                            Some(locations.into())
+                        })
+                        .unwrap_or_default();
+                    self.respond(&message, result)?;
+                }
+                "textDocument/hover" => {
+                    let result = self
+                        .query(uri, &params["position"], |analysis, offset, lines| {
+                            let (range, description) = analysis.hover(offset)?;
+                            Some(json!({
+                                "contents": { "kind": "plaintext", "value": description },
+                                "range": range_json(lines, range),
+                            }))
+                        })
+                        .unwrap_or_default();
+                    self.respond(&message, result)?;
+                }
+                "textDocument/rename" => {
+                    let new_name = params["newName"].as_str().unwrap_or_default();
+                    if !is_identifier(new_name) {
+                        let error = format!("{} is not a valid variable name", new_name);
+                        self.fail(&message, INVALID_PARAMS, &error)?;
+                        continue;
+                    }
+
+                    let result = self
+                        .query(uri, &params["position"], |analysis, offset, lines| {
+                            let edits = analysis
+                                .references(offset, true)
+                                .into_iter()
+                                .map(|range| {
+                                    json!({ "range": range_json(lines, range), "newText": new_name })
+                                })
+                                .collect::<Vec<_>>();
+                            (!edits.is_empty()).then(|| json!({ "changes": { uri: edits } }))
+                        })
+                        .unwrap_or_default();
+                    self.respond(&message, result)?;
+                }
+                "textDocument/formatting" => {
+                    let result = self.documents.get(uri).and_then(|source| {
+                        let formatted = Analysis::new(source, self.arithmetic).format()?;
+                        let lines = Lines::new(source);
+                        let range = range_json(&lines, (0, source.len()));
+                        Some(json!([{ "range": range, "newText": formatted }]))
+                    });
+                    self.respond(&message, result.unwrap_or_default())?;
+                }
+                "shutdown" => {
+                    self.shutdown = true;
+                    self.respond(&message, Value::Null)?;
+                }
+                "exit" => return Ok(self.shutdown),
+                // Notifications without handlers are ignored.
+                method if message.get("id").is_some() => {
+                    let error = format!("unsupported method {}", method);
+                    self.fail(&message, METHOD_NOT_FOUND, &error)?;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(self.shutdown)
+    }
+
+    /// Analyzes the document and calls `query` with the byte offset of the position.
+    /// Returns [None] for unknown documents and positions.
+    fn query(
+        &self,
+        uri: &str,
+        position: &Value,
+        query: impl FnOnce(&Analysis, usize, &Lines) -> Option<Value>,
+    ) -> Option<Value> {
+        let source = self.documents.get(uri)?;
+        let lines = Lines::new(source);
+        let line = position["line"].as_u64()? as usize;
+        let character = position["character"].as_u64()? as usize;
+        let offset = lines.offset(line + 1, character + 1)?;
+
+        query(&Analysis::new(source, self.arithmetic), offset, &lines)
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) -> Result<(), String> {
+        let source = &self.documents[uri];
+        let lines = Lines::new(source);
+        let diagnostics = Analysis::new(source, self.arithmetic)
+            .diagnostics()
+            .into_iter()
+            .map(|diagnostic| {
+                json!({
+                    "range": range_json(&lines, (diagnostic.start, diagnostic.end)),
+                    "severity": 1,
+                    "source": "instant",
+                    "message": diagnostic.message,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        self.notify(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        )
+    }
+}
+
+/// Returns the protocol range of the byte range, with lines and columns starting from 0.
+fn range_json(lines: &Lines, (start, end): (usize, usize)) -> Value {
+    let position = |offset| {
+        let position = lines.position(offset);
+        json!({ "line": position.line() - 1, "character": position.column() - 1 })
+    };
+
+    json!({ "start": position(start), "end": position(end) })
+}
+
+/// Returns whether the name is a valid variable name, following the grammar.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '\'')
+}
+
+fn main() -> ExitCode {
+    let mut server = Server {
+        output: &mut io::stdout().lock(),
+        arithmetic: Arithmetic::default(),
+        documents: HashMap::new(),
+        shutdown: false,
+    };
+
+    match server.run(&mut io::stdin().lock()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("ERROR: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use instant::transport::MAX_LENGTH;
+
+    const URI: &str = "file:///test.ins";
+
+    /// Runs the session with the messages, numbering the requests, and returns the sent messages
+    /// with whether the server shut down cleanly.
+    fn session(messages: &[Value]) -> (Vec<Value>, bool) {
+        let mut input = Vec::new();
+        for (id, message) in messages.iter().enumerate() {
+            let mut message = message.clone();
+            message["jsonrpc"] = "2.0".into();
+            if message["id"] == true {
+                message["id"] = id.into();
+            }
+            write_message(&mut input, &message).unwrap();
+        }
+
+        let (sent, result) = serve(&input);
+        (sent, result.unwrap())
+    }
+
+    /// Runs the server on the raw input and returns the sent messages with its result.
+    fn serve(mut input: &[u8]) -> (Vec<Value>, Result<bool, String>) {
+        let mut output = Vec::new();
+        let mut server = Server {
+            output: &mut output,
+            arithmetic: Arithmetic::default(),
+            documents: HashMap::new(),
+            shutdown: false,
+        };
+        let result = server.run(&mut input);
+
+        let mut output = &output[..];
+        let mut sent = Vec::new();
+        while let Some(message) = read_message(&mut output).unwrap() {
+            sent.push(message);
+        }
+        (sent, result)
+    }
+
+    fn request(method: &str, params: Value) -> Value {
+        json!({ "id": true, "method": method, "params": params })
+    }
+
+    fn notification(method: &str, params: Value) -> Value {
+        json!({ "method": method, "params": params })
+    }
+
+    fn at(line: u64, character: u64) -> Value {
+        json!({ "textDocument": { "uri": URI }, "position": { "line": line, "character": character } })
+    }
+
+    fn range(start: (u64, u64), end: (u64, u64)) -> Value {
+        json!({
+            "start": { "line": start.0, "character": start.1 },
+            "end": { "line": end.0, "character": end.1 },
+        })
+    }
+
+    #[test]
+    fn navigation() {
+        let source = "a = 2;\nb = a * 3;\na = a + (b);\na";
+        let mut rename = at(1, 4);
+        rename["newName"] = "value'".into();
+        let mut invalid_rename = at(1, 4);
+        invalid_rename["newName"] = "1a".into();
+        let mut references = at(3, 0);
+        references["context"] = json!({ "includeDeclaration": false });
+
+        let (messages, shutdown) = session(&[
+            request("initialize", json!({ "capabilities": {} })),
+            notification("initialized", json!({})),
+            notification(
+                "textDocument/didOpen",
+                json!({ "textDocument": { "uri": URI, "languageId": "instant", "version": 1, "text": source } }),
+            ),
+            request("textDocument/definition", at(2, 4)),
+            request("textDocument/definition", at(3, 1)),
+            request("textDocument/definition", at(0, 4)),
+            request("textDocument/references", references),
+            request("textDocument/hover", at(2, 9)),
+            request("textDocument/rename", rename),
+            request("textDocument/rename", invalid_rename),
+            request(
+                "textDocument/formatting",
+                json!({ "textDocument": { "uri": URI }, "options": {} }),
+            ),
+            request(
+                "textDocument/foldingRange",
+                json!({ "textDocument": { "uri": URI } }),
+            ),
+            request("shutdown", Value::Null),
+            notification("exit", Value::Null),
+        ]);
+        assert!(shutdown);
+
+        assert_eq!(messages[0]["id"], 0);
+        assert_eq!(
+            messages[0]["result"]["capabilities"]["renameProvider"],
+            true
+        );
+        assert_eq!(
+            messages[1]["params"],
+            json!({ "uri": URI, "diagnostics": [] })
+        );
+        assert_eq!(
+            messages[2]["result"],
+            json!({ "uri": URI, "range": range((0, 0), (0, 1)) })
+        );
+        assert_eq!(messages[3]["result"]["range"], range((2, 0), (2, 1)));
+        assert_eq!(messages[4]["result"], Value::Null);
+        assert_eq!(
+            messages[5]["result"],
+            json!([
+                { "uri": URI, "range": range((1, 4), (1, 5)) },
+                { "uri": URI, "range": range((2, 4), (2, 5)) },
+                { "uri": URI, "range": range((3, 0), (3, 1)) },
+            ])
+        );
+        assert_eq!(
+            messages[6]["result"],
+            json!({ "contents": { "kind": "plaintext", "value": "b = 6" }, "range": range((2, 9), (2, 10)) })
+        );
+        let edits = messages[7]["result"]["changes"][URI].as_array().unwrap();
+        assert_eq!(edits.len(), 5);
+        assert_eq!(
+            edits[1],
+            json!({ "range": range((1, 4), (1, 5)), "newText": "value'" })
+        );
+        assert_eq!(messages[8]["error"]["code"], INVALID_PARAMS);
+        assert_eq!(
+            messages[9]["result"],
+            json!([{
+                "range": range((0, 0), (3, 1)),
+                "newText": "a = 2;\nb = a * 3;\na = a + b;\na\n",
+            }])
+        );
+        assert_eq!(messages[10]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(
+            messages[11],
+            json!({ "jsonrpc": "2.0", "id": 12, "result": null })
+        );
+        assert_eq!(messages.len(), 12);
+    }
+
+    #[test]
+    fn diagnostics() {
+        let change = |text: &str| {
+            notification(
+                "textDocument/didChange",
+                json!({ "textDocument": { "uri": URI, "version": 2 }, "contentChanges": [{ "text": text }] }),
+            )
+        };
+        let (messages, shutdown) = session(&[
+            request(
+                "initialize",
+                json!({ "capabilities": {}, "initializationOptions": { "arithmetic": "trap" } }),
+            ),
+            notification(
+                "textDocument/didOpen",
+                json!({ "textDocument": { "uri": URI, "languageId": "instant", "version": 1, "text": "a = 1;\nb + a + c" } }),
+            ),
+            change("a = (1"),
+            change("a = 1;\n  2147483648"),
+            request(
+                "textDocument/formatting",
+                json!({ "textDocument": { "uri": URI }, "options": {} }),
+            ),
+            notification(
+                "textDocument/didClose",
+                json!({ "textDocument": { "uri": URI } }),
+            ),
+            request("textDocument/hover", at(0, 0)),
+            notification("exit", Value::Null),
+        ]);
+        assert!(!shutdown);
+
+        let diagnostics = |message: &Value| {
+            message["params"]["diagnostics"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|diagnostic| (diagnostic["range"].clone(), diagnostic["message"].clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            diagnostics(&messages[1]),
+            [
+                (range((1, 0), (1, 1)), json!("undeclared variable b")),
+                (range((1, 8), (1, 9)), json!("undeclared variable c")),
+            ]
+        );
+        assert_eq!(
+            diagnostics(&messages[2]),
+            [(range((0, 6), (0, 6)), json!("unexpected EOF"))]
+        );
+        assert_eq!(
+            diagnostics(&messages[3]),
+            [(
+                range((1, 2), (1, 12)),
+                json!("literal 2147483648 out of bounds")
+            )]
+        );
+        assert_eq!(messages[4]["result"], Value::Null);
+        assert_eq!(diagnostics(&messages[5]), []);
+        assert_eq!(messages[6]["result"], Value::Null);
+    }
+
+    #[test]
+    fn malformed_messages() {
+        let mut input = b"Content-Length: 6\r\n\r\n{\"id\":".to_vec();
+        write_message(
+            &mut input,
+            &json!({ "jsonrpc": "2.0", "id": 1, "method": "shutdown" }),
+        )
+        .unwrap();
+        input.extend(b"Content-Length: 9\r\n\r\n{}");
+
+        let (messages, result) = serve(&input);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0]["id"], Value::Null);
+        assert_eq!(messages[0]["error"]["code"], PARSE_ERROR);
+        assert_eq!(
+            messages[1],
+            json!({ "jsonrpc": "2.0", "id": 1, "result": null })
+        );
+        // The truncated message breaks the framing, which ends the session.
+        assert!(result
+            .unwrap_err()
+            .starts_with("failed to read the message"));
+
+        let oversized = format!("Content-Length: {}\r\n\r\n", MAX_LENGTH + 1);
+        let (messages, result) = serve(oversized.as_bytes());
+        assert!(messages.is_empty());
+        assert!(result.is_err());
+    }
+}
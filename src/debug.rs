@@ -0,0 +1,340 @@
+//! Command loop of the step debugger, see [Debugger].
+
+use crate::{parse, parse_spanned, runtime_error};
+use instant::{
+    ast::{Exp, Stmt},
+    debugger::{Debugger, Stop},
+    lines::Lines,
+    Arithmetic,
+};
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+step, s           execute the next statement
+continue, c       execute statements until a breakpoint or the end of the program
+break, b <line>   stop before the first statement on the line
+delete, d <line>  remove the breakpoint from the line
+watch, w <exp>    show the value of the expression at every stop
+print, p <exp>    show the value of the expression
+trace, t [<exp>]  show the evaluation of the expression operation by operation,
+                  by default of the expression in the next statement
+vars              show the variables
+where             show the next statement, the breakpoints and the watched expressions
+help              show this message
+quit, q           stop debugging";
+
+/// State of the debugging session.
+struct Session<'a> {
+    debugger: Debugger<'a>,
+    /// Lines of the debugged program, for the positions of runtime errors.
+    lines: Lines,
+    arithmetic: Arithmetic,
+    /// Sources of the watched expressions, parsed at every stop.
+    watches: Vec<String>,
+}
+
+impl Session<'_> {
+    /// Executes a command. Returns false after the command ending the session.
+    fn execute(&mut self, line: &str, output: &mut dyn Write) -> Result<bool, String> {
+        let write_error = |e| format!("failed to write to STDOUT: {}", e);
+        let (command, argument) = match line.trim().split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line.trim(), ""),
+        };
+
+        match (command, argument) {
+            ("", _) => {}
+            ("step" | "s", "") => {
+                self.debugger
+                    .step(output)
+                    .map_err(|e| runtime_error(e, Some(&self.lines)))?;
+                self.show(output).map_err(write_error)?;
+            }
+            ("continue" | "c", "") => {
+                let stop = self
+                    .debugger
+                    .resume(output)
+                    .map_err(|e| runtime_error(e, Some(&self.lines)))?;
+                if let Stop::Breakpoint(line) = stop {
+                    writeln!(output, "breakpoint at line {}", line).map_err(write_error)?;
+                }
+                self.show(output).map_err(write_error)?;
+            }
+            ("break" | "b", line) => {
+                let line = parse_line(line)?;
+                if !self.debugger.add_breakpoint(line) {
+                    return Err(format!("no statement starts at line {}", line));
+                }
+            }
+            ("delete" | "d", line) => {
+                let line = parse_line(line)?;
+                if !self.debugger.remove_breakpoint(line) {
+                    return Err(format!("no breakpoint at line {}", line));
+                }
+            }
+            ("watch" | "w", exp) => {
+                parse_exp(exp, self.arithmetic)?;
+                self.watches.push(exp.into());
+                self.show_watch(exp, output).map_err(write_error)?;
+            }
+            ("print" | "p", exp) => {
+                let exp = parse_exp(exp, self.arithmetic)?;
+                let val = self
+                    .debugger
+                    .interpreter()
+                    .evaluate(&exp)
+                    .map_err(|e| runtime_error(e, None))?;
+                writeln!(output, "{}", val).map_err(write_error)?;
+            }
+            ("trace" | "t", "") => {
+                let exp = match self.debugger.current_stmt() {
+                    Some(Stmt::Ass { exp, .. } | Stmt::Exp(exp)) => exp,
+                    None => return Err("the program has finished".into()),
+                };
+                self.trace(exp, output)?;
+            }
+            ("trace" | "t", exp) => {
+                let exp = parse_exp(exp, self.arithmetic)?;
+                self.trace(&exp, output)?;
+            }
+            ("vars", "") => {
+                let mut variables = self
+                    .debugger
+                    .interpreter()
+                    .variables()
+                    .iter()
+                    .collect::<Vec<_>>();
+                variables.sort();
+                variables
+                    .into_iter()
+                    .try_for_each(|(name, val)| writeln!(output, "{} = {}", name, val))
+                    .map_err(write_error)?;
+            }
+            ("where", "") => {
+                self.show(output).map_err(write_error)?;
+                for line in self.debugger.breakpoints() {
+                    writeln!(output, "breakpoint at line {}", line).map_err(write_error)?;
+                }
+            }
+            ("help", "") => writeln!(output, "{}", HELP).map_err(write_error)?,
+            ("quit" | "q", "") => return Ok(false),
+            (command, _) => return Err(format!("invalid command {}, see help", command)),
+        }
+
+        Ok(true)
+    }
+
+    /// Writes the next statement with its source line and the values of the watched expressions.
+    fn show(&self, output: &mut dyn Write) -> io::Result<()> {
+        match self.debugger.current() {
+            Some(location) => {
+                writeln!(output, "next: {} at {}", location.stmt, location.position)?;
+                writeln!(
+                    output,
+                    "{:>5} | {}",
+                    location.position.line(),
+                    location.line
+                )?;
+            }
+            None => writeln!(output, "program finished")?,
+        }
+
+        self.watches
+            .iter()
+            .try_for_each(|watch| self.show_watch(watch, output))
+    }
+
+    fn show_watch(&self, watch: &str, output: &mut dyn Write) -> io::Result<()> {
+        let exp = parse_exp(watch, self.arithmetic).expect("watched expressions are valid");
+        match self.debugger.interpreter().evaluate(&exp) {
+            Ok(val) => writeln!(output, "watch: {} = {}", exp, val),
+            Err(e) => writeln!(output, "watch: {} = <{}>", exp, runtime_error(e, None)),
+        }
+    }
+
+    /// Writes every operation performed while evaluating the expression, in order.
+    fn trace(&self, exp: &Exp, output: &mut dyn Write) -> Result<(), String> {
+        let write_error = |e| format!("failed to write to STDOUT: {}", e);
+        let mut steps = Vec::new();
+        let result = self.debugger.trace(exp, &mut steps);
+
+        for step in &steps {
+            writeln!(
+                output,
+                "{} => {} {} {} = {}",
+                step.exp,
+                step.lhs,
+                step.op.symbol(),
+                step.rhs,
+                step.result
+            )
+            .map_err(write_error)?;
+        }
+        let val = result.map_err(|e| runtime_error(e, None))?;
+        if steps.is_empty() {
+            writeln!(output, "{} = {}", exp, val).map_err(write_error)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_line(line: &str) -> Result<usize, String> {
+    if line.is_empty() {
+        return Err("missing line number".into());
+    }
+    line.parse()
+        .map_err(|_| format!("invalid line number {}", line))
+}
+
+/// Parses a single expression given in a command.
+fn parse_exp(exp: &str, arithmetic: Arithmetic) -> Result<Exp<'_>, String> {
+    if exp.is_empty() {
+        return Err("missing expression".into());
+    }
+    let mut stmts = parse(exp, &Lines::new(exp), arithmetic)?;
+    match (stmts.pop(), stmts.is_empty()) {
+        (Some(Stmt::Exp(exp)), true) => Ok(exp),
+        _ => Err(format!("{} is not an expression", exp)),
+    }
+}
+
+/// Debugs the Instant program with commands read until the end of the input or `quit`.
+/// Errors are written to `output` and end only the command causing them. A prompt is written
+/// before every command, if requested.
+pub fn run(
+    source: &str,
+    arithmetic: Arithmetic,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+    prompt: bool,
+) -> Result<(), String> {
+    let lines = Lines::new(source);
+    let stmts = parse_spanned(source, &lines, arithmetic)?;
+    let mut session = Session {
+        debugger: Debugger::new(source, stmts, arithmetic),
+        lines,
+        arithmetic,
+        watches: Vec::new(),
+    };
+    session
+        .show(output)
+        .map_err(|e| format!("failed to write to STDOUT: {}", e))?;
+
+    let mut line = String::new();
+    loop {
+        if prompt {
+            write!(output, "(debug) ")
+                .and_then(|_| output.flush())
+                .map_err(|e| format!("failed to write to STDOUT: {}", e))?;
+        }
+
+        line.clear();
+        let read = input
+            .read_line(&mut line)
+            .map_err(|e| format!("failed to read STDIN: {}", e))?;
+        if read == 0 {
+            return Ok(());
+        }
+
+        match session.execute(&line, output) {
+            Ok(true) => {}
+            Ok(false) => return Ok(()),
+            Err(e) => writeln!(output, "ERROR: {}", e)
+                .map_err(|e| format!("failed to write to STDOUT: {}", e))?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn session(source: &str, arithmetic: Arithmetic, input: &str) -> String {
+        let mut output = Vec::new();
+        run(
+            source,
+            arithmetic,
+            &mut input.as_bytes(),
+            &mut output,
+            false,
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn stepping() {
+        let source = "a = 1; b = 2;\n1 + a + b;\nc = b * 3;\nc / (a - 1);\n8";
+        let input = "\
+            break 3\n\
+            break 9\n\
+            watch c - b\n\
+            step\n\
+            continue\n\
+            vars\n\
+            frobnicate\n\
+            where\n\
+            continue\n\
+            print a + c\n\
+            step\n\
+            where\n\
+            quit\n\
+            step\n";
+        assert_eq!(
+            session(source, Arithmetic::Wrapping, input),
+            "\
+            next: a = 1 at (line 1, column 1)\n    \
+                1 | a = 1; b = 2;\n\
+            ERROR: no statement starts at line 9\n\
+            watch: c - b = <undeclared variable c>\n\
+            next: b = 2 at (line 1, column 8)\n    \
+                1 | a = 1; b = 2;\n\
+            watch: c - b = <undeclared variable c>\n\
+            4\n\
+            breakpoint at line 3\n\
+            next: c = b * 3 at (line 3, column 1)\n    \
+                3 | c = b * 3;\n\
+            watch: c - b = <undeclared variable c>\n\
+            a = 1\n\
+            b = 2\n\
+            ERROR: invalid command frobnicate, see help\n\
+            next: c = b * 3 at (line 3, column 1)\n    \
+                3 | c = b * 3;\n\
+            watch: c - b = <undeclared variable c>\n\
+            breakpoint at line 3\n\
+            ERROR: division by zero\n\
+            7\n\
+            ERROR: division by zero\n\
+            next: c / (a - 1) at (line 4, column 1)\n    \
+                4 | c / (a - 1);\n\
+            watch: c - b = 4\n\
+            breakpoint at line 3\n"
+        );
+    }
+
+    #[test]
+    fn trace() {
+        let source = "a = 2;\nb = 1 + a + 3 * a;\nb / (a - 2)";
+        let input = "step\ntrace\ntrace a\ntrace 10 - 2 - 3\nstep\ntrace\ntrace (\n";
+        assert_eq!(
+            session(source, Arithmetic::Wrapping, input),
+            "\
+            next: a = 2 at (line 1, column 1)\n    \
+                1 | a = 2;\n\
+            next: b = 1 + a + 3 * a at (line 2, column 1)\n    \
+                2 | b = 1 + a + 3 * a;\n\
+            3 * a => 3 * 2 = 6\n\
+            a + 3 * a => 2 + 6 = 8\n\
+            1 + a + 3 * a => 1 + 8 = 9\n\
+            a = 2\n\
+            10 - 2 => 10 - 2 = 8\n\
+            10 - 2 - 3 => 8 - 3 = 5\n\
+            next: b / (a - 2) at (line 3, column 1)\n    \
+                3 | b / (a - 2)\n\
+            a - 2 => 2 - 2 = 0\n\
+            ERROR: division by zero\n\
+            ERROR: unexpected EOF at (line 1, column 2)\n"
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! Step debugger executing an Instant program statement by statement with the reference interpreter.
+
+use crate::{
+    ast::{Exp, Op, Stmt},
+    interpreter::{self, Interpreter, RuntimeError},
+    lines::{Lines, Position},
+    Arithmetic,
+};
+use num_bigint::BigInt;
+use std::{collections::BTreeSet, io::Write};
+
+/// Statement of the debugged program with the byte range of its source.
+struct Statement<'a> {
+    stmt: Stmt<'a>,
+    start: usize,
+    end: usize,
+    line: usize,
+}
+
+/// Statement about to be executed.
+pub struct Location<'a> {
+    /// Index of the statement in the program.
+    pub index: usize,
+    /// Position of the first character of the statement.
+    pub position: Position,
+    /// Source of the statement.
+    pub stmt: &'a str,
+    /// Whole source line containing the start of the statement.
+    pub line: &'a str,
+}
+
+/// Reason for which the execution stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// A single statement has been executed.
+    Step,
+    /// The next statement starts the line with the breakpoint.
+    Breakpoint(usize),
+    /// All statements have been executed.
+    Finished,
+}
+
+/// Binary operation performed while evaluating an expression, see [Debugger::trace].
+#[derive(Debug)]
+pub struct TraceStep<'e, 'a> {
+    /// Evaluated [Exp::Bi] node.
+    pub exp: &'e Exp<'a>,
+    pub lhs: BigInt,
+    pub op: Op,
+    pub rhs: BigInt,
+    pub result: BigInt,
+}
+
+/// Debugger of a parsed Instant program, keeping the interpreter state between the stops.
+pub struct Debugger<'a> {
+    source: &'a str,
+    lines: Lines,
+    stmts: Vec<Statement<'a>>,
+    interpreter: Interpreter,
+    /// Index of the statement executed next.
+    next: usize,
+    breakpoints: BTreeSet<usize>,
+}
+
+impl<'a> Debugger<'a> {
+    /// Creates a debugger stopped before the first statement.
+    /// The statements come with the byte ranges of their source in `source`.
+    pub fn new(
+        source: &'a str,
+        stmts: Vec<(usize, Stmt<'a>, usize)>,
+        arithmetic: Arithmetic,
+    ) -> Self {
+        let lines = Lines::new(source);
+        let stmts = stmts
+            .into_iter()
+            .map(|(start, stmt, end)| Statement {
+                stmt,
+                start,
+                end,
+                line: lines.position(start).line(),
+            })
+            .collect();
+
+        Self {
+            source,
+            lines,
+            stmts,
+            interpreter: Interpreter::new(arithmetic),
+            next: 0,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    /// Returns the interpreter holding the variables assigned so far.
+    pub fn interpreter(&self) -> &Interpreter {
+        &self.interpreter
+    }
+
+    /// Returns the statement executed next, [None] after the whole program.
+    pub fn current(&self) -> Option<Location<'a>> {
+        let stmt = self.stmts.get(self.next)?;
+        let position = self.lines.position(stmt.start);
+        let line = self
+            .source
+            .lines()
+            .nth(position.line() - 1)
+            .unwrap_or_default();
+
+        Some(Location {
+            index: self.next,
+            position,
+            stmt: &self.source[stmt.start..stmt.end],
+            line,
+        })
+    }
+
+    /// Returns the statement executed next, [None] after the whole program.
+    pub fn current_stmt(&self) -> Option<&Stmt<'a>> {
+        self.stmts.get(self.next).map(|stmt| &stmt.stmt)
+    }
+
+    /// Executes the next statement, writing the printed value to `output`.
+    /// A failing statement stays the next one, so the state before it can be inspected.
+    pub fn step(&mut self, output: &mut dyn Write) -> Result<Stop, RuntimeError<'a>> {
+        let Some(stmt) = self.stmts.get(self.next) else {
+            return Ok(Stop::Finished);
+        };
+        self.interpreter.execute(&stmt.stmt, output)?;
+        self.next += 1;
+
+        Ok(match self.stmts.get(self.next) {
+            Some(_) => Stop::Step,
+            None => Stop::Finished,
+        })
+    }
+
+    /// Executes statements until the first one on a line with a breakpoint, or until the end.
+    /// At least one statement is executed, so the execution can continue from a breakpoint.
+    pub fn resume(&mut self, output: &mut dyn Write) -> Result<Stop, RuntimeError<'a>> {
+        loop {
+            let line = self.stmts.get(self.next).map(|stmt| stmt.line);
+            if self.step(output)? == Stop::Finished {
+                return Ok(Stop::Finished);
+            }
+
+            let next = self.stmts[self.next].line;
+            if Some(next) != line && self.breakpoints.contains(&next) {
+                return Ok(Stop::Breakpoint(next));
+            }
+        }
+    }
+
+    /// Sets a breakpoint on the line. Returns false, without setting it, if no statement starts
+    /// on the line.
+    pub fn add_breakpoint(&mut self, line: usize) -> bool {
+        let found = self.stmts.iter().any(|stmt| stmt.line == line);
+        if found {
+            self.breakpoints.insert(line);
+        }
+        found
+    }
+
+    /// Removes the breakpoint from the line. Returns whether there was one.
+    pub fn remove_breakpoint(&mut self, line: usize) -> bool {
+        self.breakpoints.remove(&line)
+    }
+
+    /// Returns the lines with breakpoints in increasing order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Evaluates the expression with the current values of the variables, recording every binary
+    /// operation in `steps` in the order of evaluation: the left operand, the right operand,
+    /// and then the node itself.
+    /// On errors `steps` ends with the last successful operation.
+    pub fn trace<'e, 'x>(
+        &self,
+        exp: &'e Exp<'x>,
+        steps: &mut Vec<TraceStep<'e, 'x>>,
+    ) -> Result<BigInt, RuntimeError<'x>> {
+        match exp {
+            Exp::Bi { lhs, op, rhs } => {
+                let lhs = self.trace(lhs, steps)?;
+                let rhs = self.trace(rhs, steps)?;
+                let result = interpreter::evaluate(self.interpreter.arithmetic(), *op, &lhs, &rhs)?;
+                steps.push(TraceStep {
+                    exp,
+                    lhs,
+                    op: *op,
+                    rhs,
+                    result: result.clone(),
+                });
+                Ok(result)
+            }
+            leaf => self.interpreter.evaluate(leaf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser;
+
+    const SOURCE: &str = "a = 1; b = 2;\na + b;\nc = a / (b - 2);\n7";
+
+    fn debugger() -> Debugger<'static> {
+        let stmts = parser::parse_spanned(SOURCE, Arithmetic::Wrapping).unwrap();
+        Debugger::new(SOURCE, stmts, Arithmetic::Wrapping)
+    }
+
+    #[test]
+    fn stepping() {
+        let mut debugger = debugger();
+        let mut output = Vec::new();
+
+        let location = debugger.current().unwrap();
+        assert_eq!((location.index, location.stmt), (0, "a = 1"));
+        assert_eq!(debugger.step(&mut output).unwrap(), Stop::Step);
+        let location = debugger.current().unwrap();
+        assert_eq!(location.position.to_string(), "(line 1, column 8)");
+        assert_eq!((location.stmt, location.line), ("b = 2", "a = 1; b = 2;"));
+
+        assert!(debugger.add_breakpoint(3));
+        assert!(!debugger.add_breakpoint(5));
+        assert_eq!(debugger.resume(&mut output).unwrap(), Stop::Breakpoint(3));
+        assert_eq!(output, b"3\n");
+        assert_eq!(debugger.current().unwrap().stmt, "c = a / (b - 2)");
+
+        assert!(matches!(
+            debugger.resume(&mut output),
+            Err(RuntimeError::DivisionByZero)
+        ));
+        assert_eq!(debugger.current().unwrap().index, 3);
+        assert_eq!(debugger.interpreter().variables()["b"], BigInt::from(2));
+
+        assert!(debugger.remove_breakpoint(3));
+        assert_eq!(debugger.breakpoints().count(), 0);
+    }
+
+    #[test]
+    fn trace() {
+        let mut debugger = debugger();
+        debugger.resume(&mut Vec::new()).unwrap_err();
+
+        // Addition is right-associative, so 1 + 2 + 3 adds 2 + 3 first.
+        let stmts = parser::parse("1 + b + a * 3", Arithmetic::Wrapping).unwrap();
+        let [Stmt::Exp(exp)] = &stmts[..] else {
+            panic!("the source is a single expression");
+        };
+        let mut steps = Vec::new();
+        assert_eq!(debugger.trace(exp, &mut steps).unwrap(), BigInt::from(6));
+        let steps = steps
+            .iter()
+            .map(|step| {
+                format!(
+                    "{}: {} {} {} = {}",
+                    step.exp,
+                    step.lhs,
+                    step.op.symbol(),
+                    step.rhs,
+                    step.result
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            steps,
+            [
+                "a * 3: 1 * 3 = 3",
+                "b + a * 3: 2 + 3 = 5",
+                "1 + b + a * 3: 1 + 5 = 6"
+            ]
+        );
+
+        let Some(Stmt::Ass { exp, .. }) = debugger.current_stmt() else {
+            panic!("the failing assignment is the next statement");
+        };
+        let mut steps = Vec::new();
+        assert!(matches!(
+            debugger.trace(exp, &mut steps),
+            Err(RuntimeError::DivisionByZero)
+        ));
+        assert_eq!(steps.len(), 1);
+        assert_eq!(steps[0].result, BigInt::from(0));
+    }
+}
@@ -0,0 +1,246 @@
+//! Reference interpreter executing the [Stmt]s directly, the specification of the language semantics.
+
+use crate::{
+    ast::{self, Exp, Op, Stmt},
+    Arithmetic, UndeclaredVariableError,
+};
+use num_bigint::BigInt;
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    io::Write,
+};
+
+/// Error aborting the execution of an Instant program.
+#[derive(Debug)]
+pub enum RuntimeError<'a> {
+    /// Access to a variable that has not been assigned yet.
+    UndeclaredVariable(UndeclaredVariableError<'a>),
+    /// Division with a zero divisor.
+    DivisionByZero,
+    /// Result not fitting in 32 bits in [Arithmetic::Checked] mode.
+    Overflow,
+    /// Failure to write the printed value.
+    Output(std::io::Error),
+}
+
+/// Describes the error without the position of undeclared variables.
+impl Display for RuntimeError<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UndeclaredVariable(e) => write!(f, "undeclared variable {}", e.name),
+            Self::DivisionByZero => f.write_str("division by zero"),
+            Self::Overflow => f.write_str("integer overflow"),
+            Self::Output(e) => write!(f, "failed to write the output: {}", e),
+        }
+    }
+}
+
+/// Returns the result of the operation in the given arithmetic mode.
+/// In bounded modes both operands have to fit in 32 bits.
+/// Division rounds towards zero.
+pub fn evaluate<'a>(
+    arithmetic: Arithmetic,
+    op: Op,
+    lhs: &BigInt,
+    rhs: &BigInt,
+) -> Result<BigInt, RuntimeError<'a>> {
+    match arithmetic {
+        Arithmetic::Wrapping | Arithmetic::Checked => {
+            evaluate_bounded(arithmetic, op, small(lhs), small(rhs)).map(BigInt::from)
+        }
+        Arithmetic::Unbounded => match op {
+            Op::Add => Ok(lhs + rhs),
+            Op::Sub => Ok(lhs - rhs),
+            Op::Mul => Ok(lhs * rhs),
+            Op::Div if *rhs == BigInt::default() => Err(RuntimeError::DivisionByZero),
+            Op::Div => Ok(lhs / rhs),
+        },
+    }
+}
+
+/// Returns the result of the operation on 32-bit values, see [evaluate].
+/// Panics in [Arithmetic::Unbounded] mode.
+pub fn evaluate_bounded<'a>(
+    arithmetic: Arithmetic,
+    op: Op,
+    lhs: i32,
+    rhs: i32,
+) -> Result<i32, RuntimeError<'a>> {
+    match (arithmetic, op) {
+        (_, Op::Div) if rhs == 0 => Err(RuntimeError::DivisionByZero),
+        (Arithmetic::Wrapping, op) => Ok(match op {
+            Op::Add => lhs.wrapping_add(rhs),
+            Op::Sub => lhs.wrapping_sub(rhs),
+            Op::Mul => lhs.wrapping_mul(rhs),
+            Op::Div => lhs.wrapping_div(rhs),
+        }),
+        (Arithmetic::Checked, op) => match op {
+            Op::Add => lhs.checked_add(rhs),
+            Op::Sub => lhs.checked_sub(rhs),
+            Op::Mul => lhs.checked_mul(rhs),
+            Op::Div => lhs.checked_div(rhs),
+        }
+        .ok_or(RuntimeError::Overflow),
+        (Arithmetic::Unbounded, _) => unreachable!("unbounded values do not fit in 32 bits"),
+    }
+}
+
+/// Converts a value computed in a bounded arithmetic mode.
+pub(crate) fn small(value: &BigInt) -> i32 {
+    i32::try_from(value).expect("bounded values fit in 32 bits")
+}
+
+/// Tree-walking interpreter, keeping the variables between executed statements.
+#[derive(Debug, Clone, Default)]
+pub struct Interpreter {
+    arithmetic: Arithmetic,
+    variables: HashMap<String, BigInt>,
+}
+
+impl Interpreter {
+    /// Creates an interpreter without any variables.
+    pub fn new(arithmetic: Arithmetic) -> Self {
+        Self {
+            arithmetic,
+            variables: Default::default(),
+        }
+    }
+
+    /// Returns the arithmetic mode of the interpreter.
+    pub fn arithmetic(&self) -> Arithmetic {
+        self.arithmetic
+    }
+
+    /// Returns the assigned variables with their values.
+    pub fn variables(&self) -> &HashMap<String, BigInt> {
+        &self.variables
+    }
+
+    /// Executes the statements in order, writing the printed values to `output`.
+    /// Stops at the first error, keeping the effects of the preceding statements.
+    pub fn run<'a>(
+        &mut self,
+        stmts: &[Stmt<'a>],
+        output: &mut dyn Write,
+    ) -> Result<(), RuntimeError<'a>> {
+        for stmt in stmts {
+            self.execute(stmt, output)?;
+        }
+
+        Ok(())
+    }
+
+    /// Executes a single statement, writing the printed value to `output`.
+    pub fn execute<'a>(
+        &mut self,
+        stmt: &Stmt<'a>,
+        output: &mut dyn Write,
+    ) -> Result<(), RuntimeError<'a>> {
+        match stmt {
+            Stmt::Ass { var, exp } => {
+                let val = self.evaluate(exp)?;
+                self.variables.insert(var.to_string(), val);
+            }
+            Stmt::Exp(exp) => {
+                let val = self.evaluate(exp)?;
+                writeln!(output, "{}", val).map_err(RuntimeError::Output)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the value of the expression, evaluating the left operands first.
+    pub fn evaluate<'a>(&self, exp: &Exp<'a>) -> Result<BigInt, RuntimeError<'a>> {
+        match exp {
+            Exp::Lit(val) => Ok((*val).into()),
+            Exp::BigLit(digits) if self.arithmetic == Arithmetic::Unbounded => {
+                Ok(digits.parse().expect("big literals are valid integers"))
+            }
+            Exp::BigLit(digits) => Ok(ast::wrapped_literal(digits).into()),
+            Exp::Var { name, position } => {
+                self.variables
+                    .get(*name)
+                    .cloned()
+                    .ok_or(RuntimeError::UndeclaredVariable(UndeclaredVariableError {
+                        name,
+                        byte_offset: *position,
+                    }))
+            }
+            Exp::Bi { lhs, op, rhs } => {
+                let lhs = self.evaluate(lhs)?;
+                let rhs = self.evaluate(rhs)?;
+                evaluate(self.arithmetic, *op, &lhs, &rhs)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser;
+
+    fn output(arithmetic: Arithmetic, stmts: &[Stmt]) -> (String, Option<String>) {
+        let mut output = Vec::new();
+        let result = Interpreter::new(arithmetic).run(stmts, &mut output);
+        (
+            String::from_utf8(output).unwrap(),
+            result.err().map(|e| format!("{:?}", e)),
+        )
+    }
+
+    #[test]
+    fn wrapping() {
+        let stmts = |arithmetic| {
+            let source = "a = 2147483647 + 1; a; a / (0 - 1); (0 - 7) / 2; 65536 * 65537";
+            let mut stmts = parser::parse(source, arithmetic).unwrap();
+            // The parser rejects big literals in bounded modes, but they are wrapped around.
+            stmts.push(Stmt::Exp(Exp::BigLit("4294967338")));
+            stmts
+        };
+        assert_eq!(
+            output(Arithmetic::Wrapping, &stmts(Arithmetic::Wrapping)),
+            ("-2147483648\n-2147483648\n-3\n65536\n42\n".into(), None)
+        );
+        assert_eq!(
+            output(Arithmetic::Checked, &stmts(Arithmetic::Checked)),
+            (String::new(), Some("Overflow".into()))
+        );
+        assert_eq!(
+            output(Arithmetic::Unbounded, &stmts(Arithmetic::Unbounded)),
+            (
+                "2147483648\n-2147483648\n-3\n4295032832\n4294967338\n".into(),
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn runtime_errors() {
+        for arithmetic in [
+            Arithmetic::Wrapping,
+            Arithmetic::Checked,
+            Arithmetic::Unbounded,
+        ] {
+            let stmts = parser::parse("5; a = 1 / (2 - 2); 7", arithmetic).unwrap();
+            assert_eq!(
+                output(arithmetic, &stmts),
+                ("5\n".into(), Some("DivisionByZero".into()))
+            );
+        }
+
+        let mut interpreter = Interpreter::default();
+        let mut output = Vec::new();
+        let stmts = parser::parse("a = 1; a + b", Arithmetic::Wrapping).unwrap();
+        match interpreter.run(&stmts, &mut output) {
+            Err(RuntimeError::UndeclaredVariable(e)) => {
+                assert_eq!((e.name, e.byte_offset), ("b", 11))
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(output.is_empty());
+        assert_eq!(interpreter.variables()["a"], BigInt::from(1));
+    }
+}
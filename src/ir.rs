@@ -0,0 +1,386 @@
+//! Backend-independent intermediate representation of Instant programs.
+//!
+//! A [Program] is a flat list of three-address [Instruction]s in SSA form:
+//! every [Value] is defined by exactly one [Instruction::Bin] before any of its uses.
+//! Variables do not exist in the IR, assignments only bind names to [Operand]s during lowering.
+//! The only observable effects are [Instruction::Print] and runtime errors
+//! (division by zero and, in [Arithmetic::Checked] mode, overflows).
+//! Besides the operations of Instant, optimizations may introduce [Instruction::Machine]
+//! operations, which never fail and are present only in bounded [Arithmetic] modes.
+//!
+//! The text format produced by the [Display] implementation looks like this:
+//!
+//! ```text
+//! ; arithmetic: wrapping
+//! %0 = mul 6, 7
+//! %1 = add %0, %0
+//! print %1
+//! ```
+
+use crate::{
+    ast::{self, Exp, Op, Stmt},
+    Arithmetic, UndeclaredVariableError,
+};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+};
+
+/// Value computed by an [Instruction::Bin].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub usize);
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+/// Operand of an [Instruction].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Operand {
+    /// Value computed by an earlier instruction.
+    Value(Value),
+    /// Constant in the i32 range.
+    Const(i32),
+    /// Constant out of the i32 range, index into [Program::big_constants].
+    /// Present only in the [Arithmetic::Unbounded] mode.
+    BigConst(usize),
+}
+
+impl From<Value> for Operand {
+    fn from(value: Value) -> Self {
+        Self::Value(value)
+    }
+}
+
+/// Low-level operation on i32 values, without a counterpart in Instant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MachineOp {
+    /// Left shift, by the right operand modulo 32.
+    Shl,
+    /// Arithmetic right shift, by the right operand modulo 32.
+    Sar,
+    /// Logical right shift, by the right operand modulo 32.
+    Shr,
+    /// High 32 bits of the 64-bit signed product.
+    MulHigh,
+}
+
+impl MachineOp {
+    /// Returns the result of this operation.
+    pub fn apply(self, lhs: i32, rhs: i32) -> i32 {
+        match self {
+            Self::Shl => lhs.wrapping_shl(rhs as u32),
+            Self::Sar => lhs.wrapping_shr(rhs as u32),
+            Self::Shr => (lhs as u32).wrapping_shr(rhs as u32) as i32,
+            Self::MulHigh => ((i64::from(lhs) * i64::from(rhs)) >> 32) as i32,
+        }
+    }
+}
+
+impl Display for MachineOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Shl => "shl",
+            Self::Sar => "sar",
+            Self::Shr => "shr",
+            Self::MulHigh => "mulhi",
+        })
+    }
+}
+
+/// Single operation of the [Program].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Instruction {
+    /// Binary operation defining a new value.
+    Bin {
+        dst: Value,
+        op: Op,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    /// Low-level operation defining a new value.
+    Machine {
+        dst: Value,
+        op: MachineOp,
+        lhs: Operand,
+        rhs: Operand,
+    },
+    /// Printing of an operand, followed by a newline.
+    Print(Operand),
+}
+
+impl Instruction {
+    /// Returns operands read by this instruction.
+    pub fn operands(&self) -> Vec<Operand> {
+        match self {
+            Self::Bin { lhs, rhs, .. } | Self::Machine { lhs, rhs, .. } => vec![*lhs, *rhs],
+            Self::Print(operand) => vec![*operand],
+        }
+    }
+
+    /// Returns the value defined by this instruction.
+    pub fn dst(&self) -> Option<Value> {
+        match self {
+            Self::Bin { dst, .. } | Self::Machine { dst, .. } => Some(*dst),
+            Self::Print(_) => None,
+        }
+    }
+
+    /// Returns whether this instruction may end the program with a runtime error.
+    /// Divisions are considered failing unless the divisor is a constant other than 0,
+    /// in [Arithmetic::Checked] mode every operation is considered failing.
+    pub fn may_fail(&self, arithmetic: Arithmetic) -> bool {
+        match self {
+            Self::Bin { .. } if arithmetic == Arithmetic::Checked => true,
+            Self::Bin {
+                op: Op::Div, rhs, ..
+            } => !matches!(rhs, Operand::Const(val) if *val != 0),
+            Self::Bin { .. } | Self::Machine { .. } | Self::Print(_) => false,
+        }
+    }
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Add => "add",
+            Self::Sub => "sub",
+            Self::Mul => "mul",
+            Self::Div => "div",
+        })
+    }
+}
+
+impl Display for Arithmetic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Wrapping => "wrapping",
+            Self::Checked => "checked",
+            Self::Unbounded => "unbounded",
+        })
+    }
+}
+
+/// Instant program in the intermediate representation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    /// Semantics of the arithmetic operations.
+    pub arithmetic: Arithmetic,
+    /// Instructions, executed in order.
+    pub instructions: Vec<Instruction>,
+    /// Decimal representations of the constants referenced with [Operand::BigConst].
+    pub big_constants: Vec<String>,
+    next_value: usize,
+}
+
+impl Program {
+    /// Creates an empty program.
+    pub fn new(arithmetic: Arithmetic) -> Self {
+        Self {
+            arithmetic,
+            instructions: Default::default(),
+            big_constants: Default::default(),
+            next_value: 0,
+        }
+    }
+
+    /// Lowers the given Instant program into the intermediate representation.
+    /// In bounded [Arithmetic] modes, literals out of the i32 range are wrapped around.
+    pub fn lower<'a>(
+        program: &[Stmt<'a>],
+        arithmetic: Arithmetic,
+    ) -> Result<Self, UndeclaredVariableError<'a>> {
+        let mut builder = ProgramBuilder {
+            variables: Default::default(),
+            program: Self::new(arithmetic),
+        };
+
+        for stmt in program {
+            builder.add_stmt(stmt)?;
+        }
+
+        Ok(builder.program)
+    }
+
+    /// Returns a fresh value, not defined by any instruction yet.
+    pub fn new_value(&mut self) -> Value {
+        let value = Value(self.next_value);
+        self.next_value += 1;
+        value
+    }
+
+    /// Returns an upper bound on the numbers of values used in this program.
+    pub fn value_count(&self) -> usize {
+        self.next_value
+    }
+
+    /// Returns the number of uses of every value in this program, indexed by the value number.
+    pub fn use_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.value_count()];
+        for instruction in &self.instructions {
+            for operand in instruction.operands() {
+                if let Operand::Value(value) = operand {
+                    counts[value.0] += 1;
+                }
+            }
+        }
+
+        counts
+    }
+
+    fn fmt_operand(&self, f: &mut Formatter<'_>, operand: Operand) -> fmt::Result {
+        match operand {
+            Operand::Value(value) => write!(f, "{}", value),
+            Operand::Const(val) => write!(f, "{}", val),
+            Operand::BigConst(idx) => f.write_str(&self.big_constants[idx]),
+        }
+    }
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "; arithmetic: {}", self.arithmetic)?;
+        for instruction in &self.instructions {
+            match instruction {
+                Instruction::Bin { dst, op, lhs, rhs } => {
+                    write!(f, "{} = {} ", dst, op)?;
+                    self.fmt_operand(f, *lhs)?;
+                    f.write_str(", ")?;
+                    self.fmt_operand(f, *rhs)?;
+                }
+                Instruction::Machine { dst, op, lhs, rhs } => {
+                    write!(f, "{} = {} ", dst, op)?;
+                    self.fmt_operand(f, *lhs)?;
+                    f.write_str(", ")?;
+                    self.fmt_operand(f, *rhs)?;
+                }
+                Instruction::Print(operand) => {
+                    f.write_str("print ")?;
+                    self.fmt_operand(f, *operand)?;
+                }
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+struct ProgramBuilder<'a> {
+    variables: HashMap<&'a str, Operand>,
+    program: Program,
+}
+
+impl<'a> ProgramBuilder<'a> {
+    fn add_exp(&mut self, exp: &Exp<'a>) -> Result<Operand, UndeclaredVariableError<'a>> {
+        match exp {
+            Exp::Lit(val) => Ok(Operand::Const(*val)),
+            Exp::BigLit(digits) if self.program.arithmetic == Arithmetic::Unbounded => {
+                self.program.big_constants.push(digits.to_string());
+                Ok(Operand::BigConst(self.program.big_constants.len() - 1))
+            }
+            Exp::BigLit(digits) => Ok(Operand::Const(ast::wrapped_literal(digits))),
+            Exp::Var { name, position } => {
+                self.variables
+                    .get(name)
+                    .copied()
+                    .ok_or(UndeclaredVariableError {
+                        name,
+                        byte_offset: *position,
+                    })
+            }
+            Exp::Bi { lhs, op, rhs } => {
+                let lhs = self.add_exp(lhs)?;
+                let rhs = self.add_exp(rhs)?;
+                let dst = self.program.new_value();
+
+                self.program.instructions.push(Instruction::Bin {
+                    dst,
+                    op: *op,
+                    lhs,
+                    rhs,
+                });
+
+                Ok(dst.into())
+            }
+        }
+    }
+
+    fn add_stmt(&mut self, stmt: &Stmt<'a>) -> Result<(), UndeclaredVariableError<'a>> {
+        match stmt {
+            Stmt::Exp(exp) => {
+                let operand = self.add_exp(exp)?;
+                self.program.instructions.push(Instruction::Print(operand));
+            }
+            Stmt::Ass { var, exp } => {
+                let operand = self.add_exp(exp)?;
+                self.variables.insert(*var, operand);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn lowering() {
+        let mut stmts =
+            parser::parse("a = 2; b = a * 3; a = b; a - b", Arithmetic::Wrapping).unwrap();
+        // The parser rejects big literals in bounded modes, but the lowering wraps them around.
+        stmts.push(Stmt::Exp(Exp::BigLit("4294967338")));
+        let program = Program::lower(&stmts, Arithmetic::Wrapping).unwrap();
+
+        assert_eq!(
+            program.instructions,
+            [
+                Instruction::Bin {
+                    dst: Value(0),
+                    op: Op::Mul,
+                    lhs: Operand::Const(2),
+                    rhs: Operand::Const(3),
+                },
+                Instruction::Bin {
+                    dst: Value(1),
+                    op: Op::Sub,
+                    lhs: Value(0).into(),
+                    rhs: Value(0).into(),
+                },
+                Instruction::Print(Value(1).into()),
+                Instruction::Print(Operand::Const(42)),
+            ]
+        );
+        assert_eq!(program.use_counts(), [2, 1]);
+        assert_eq!(
+            program.to_string(),
+            "; arithmetic: wrapping\n%0 = mul 2, 3\n%1 = sub %0, %0\nprint %1\nprint 42\n"
+        );
+    }
+
+    #[test]
+    fn big_constants() {
+        let stmts = parser::parse("4294967338 + 1", Arithmetic::Unbounded).unwrap();
+        let program = Program::lower(&stmts, Arithmetic::Unbounded).unwrap();
+
+        assert_eq!(program.big_constants, ["4294967338"]);
+        assert_eq!(
+            program.to_string(),
+            "; arithmetic: unbounded\n%0 = add 4294967338, 1\nprint %0\n"
+        );
+    }
+
+    #[test]
+    fn undeclared_variable() {
+        let stmts = parser::parse("a = 1; a + name", Arithmetic::Wrapping).unwrap();
+        let error = Program::lower(&stmts, Arithmetic::Wrapping)
+            .expect_err("undeclared variable access should result in an error");
+        assert_eq!(error.name, "name");
+        assert_eq!(error.byte_offset, 11);
+    }
+}
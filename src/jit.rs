@@ -0,0 +1,390 @@
+//! Just-in-time compiler executing programs as native code generated by
+//! [Cranelift](https://cranelift.dev/).
+//!
+//! The program is lowered by the [LLVMBackend], whose registers and immediates map directly
+//! to Cranelift values, so both share the handling of divisions and overflows.
+//! Prints call back into Rust in place of `@printInt`, and the runtime errors return
+//! from the generated function instead of exiting the process.
+
+use crate::{
+    ast::Op,
+    backend::{
+        llvm::{Instruction, LLVMBackend, Location},
+        Backend,
+    },
+    interpreter::RuntimeError,
+    ir::{MachineOp, Program},
+    Arithmetic,
+};
+use cranelift_codegen::{
+    ir::{condcodes::IntCC, types, AbiParam, Block, FuncRef, InstBuilder, Value},
+    settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, FuncId, Linkage, Module, ModuleError};
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{self, Write},
+    mem,
+};
+
+/// Failure to generate the native code.
+#[derive(Debug)]
+pub enum CompileError {
+    /// The host is not supported by Cranelift.
+    UnsupportedHost(String),
+    Module(Box<ModuleError>),
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedHost(e) => write!(f, "unsupported host: {}", e),
+            Self::Module(e) => write!(f, "code generation failed: {}", e),
+        }
+    }
+}
+
+impl From<ModuleError> for CompileError {
+    fn from(error: ModuleError) -> Self {
+        Self::Module(Box::new(error))
+    }
+}
+
+/// Values returned by the generated function.
+const SUCCESS: i64 = 0;
+const DIVISION_BY_ZERO: i64 = 1;
+const OVERFLOW: i64 = 2;
+
+/// State shared with [print_int] during the execution.
+struct Context<'o> {
+    output: &'o mut dyn Write,
+    /// First failure to write the output, after which nothing more is written.
+    error: Option<io::Error>,
+}
+
+/// Replaces `@printInt`, writing the value in a separate line.
+extern "C" fn print_int(context: &mut Context, value: i32) {
+    if context.error.is_none() {
+        if let Err(e) = writeln!(context.output, "{}", value) {
+            context.error = Some(e);
+        }
+    }
+}
+
+/// Native code of an Instant program, freed when dropped.
+pub struct Jit {
+    module: Option<JITModule>,
+    main: FuncId,
+}
+
+impl Jit {
+    /// Generates the native code of the program for the host.
+    /// [Arithmetic::Unbounded] mode is not supported.
+    pub fn new(program: &Program) -> Result<Self, CompileError> {
+        assert!(
+            program.arithmetic != Arithmetic::Unbounded,
+            "unbounded arithmetic is not supported by the JIT"
+        );
+
+        let mut flags = settings::builder();
+        flags
+            .set("opt_level", "speed")
+            .expect("opt_level is a valid setting");
+        let isa = cranelift_native::builder()
+            .map_err(|e| CompileError::UnsupportedHost(e.to_string()))?
+            .finish(settings::Flags::new(flags))
+            .map_err(|e| CompileError::UnsupportedHost(e.to_string()))?;
+        let mut builder = JITBuilder::with_isa(isa, default_libcall_names());
+        builder.symbol("printInt", print_int as *const u8);
+        let mut module = JITModule::new(builder);
+
+        let pointer = module.target_config().pointer_type();
+        let mut print_signature = module.make_signature();
+        print_signature
+            .params
+            .extend([AbiParam::new(pointer), AbiParam::new(types::I32)]);
+        let print = module.declare_function("printInt", Linkage::Import, &print_signature)?;
+
+        let mut signature = module.make_signature();
+        signature.params.push(AbiParam::new(pointer));
+        signature.returns.push(AbiParam::new(types::I32));
+        let main = module.declare_function("main", Linkage::Export, &signature)?;
+
+        let mut context = module.make_context();
+        context.func.signature = signature;
+        let mut builder_context = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+        let print = module.declare_func_in_func(print, builder.func);
+
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        let mut translator = Translator {
+            state: builder.block_params(entry)[0],
+            builder,
+            registers: vec![None; program.value_count()],
+            divzero: None,
+            overflow: None,
+        };
+        for instruction in &LLVMBackend.process(program).instructions {
+            translator.add_instruction(instruction, print);
+        }
+        translator.finish();
+
+        module.define_function(main, &mut context)?;
+        module.clear_context(&mut context);
+        module.finalize_definitions()?;
+
+        Ok(Self {
+            module: Some(module),
+            main,
+        })
+    }
+
+    /// Executes the program, writing the printed values to `output`.
+    pub fn run(&self, output: &mut dyn Write) -> Result<(), RuntimeError<'static>> {
+        let module = self
+            .module
+            .as_ref()
+            .expect("the module is freed only when dropped");
+        let code = module.get_finalized_function(self.main);
+        // SAFETY: The function was defined with this signature, and the context outlives the call.
+        let main = unsafe { mem::transmute::<*const u8, extern "C" fn(&mut Context) -> i32>(code) };
+
+        let mut context = Context {
+            output,
+            error: None,
+        };
+        let status = main(&mut context);
+        if let Some(e) = context.error {
+            return Err(RuntimeError::Output(e));
+        }
+
+        match i64::from(status) {
+            SUCCESS => Ok(()),
+            DIVISION_BY_ZERO => Err(RuntimeError::DivisionByZero),
+            OVERFLOW => Err(RuntimeError::Overflow),
+            status => unreachable!("unexpected status {}", status),
+        }
+    }
+}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            // SAFETY: The code is not running and no pointers to it are left.
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+struct Translator<'f> {
+    builder: FunctionBuilder<'f>,
+    /// Pointer to the [Context].
+    state: Value,
+    /// Cranelift value of every register, defined in the order of the instructions.
+    registers: Vec<Option<Value>>,
+    /// Blocks returning the status of the runtime errors, created when first needed.
+    divzero: Option<Block>,
+    overflow: Option<Block>,
+}
+
+impl Translator<'_> {
+    fn value(&mut self, location: Location) -> Value {
+        match location {
+            Location::Register(num) => {
+                self.registers[num].expect("registers are defined before use")
+            }
+            // The immediate is given with the upper 32 bits cleared.
+            Location::Immediate(val) => {
+                self.builder.ins().iconst(types::I32, i64::from(val as u32))
+            }
+            Location::Literal { .. } => {
+                unreachable!("arbitrary-precision literals are used only in unbounded arithmetic")
+            }
+        }
+    }
+
+    fn define(&mut self, location: Location, value: Value) {
+        match location {
+            Location::Register(num) => self.registers[num] = Some(value),
+            _ => unreachable!("results are always stored in registers"),
+        }
+    }
+
+    /// Returns the block returning the status, creating it if needed.
+    fn error_block(&mut self, status: i64) -> Block {
+        let block = match status {
+            DIVISION_BY_ZERO => &mut self.divzero,
+            _ => &mut self.overflow,
+        };
+        *block.get_or_insert_with(|| self.builder.create_block())
+    }
+
+    /// Branches to the error block if the condition is set, continuing in a new block.
+    fn fail_if(&mut self, condition: Value, status: i64) {
+        let error = self.error_block(status);
+        let next = self.builder.create_block();
+        self.builder.ins().brif(condition, error, &[], next, &[]);
+        self.builder.seal_block(next);
+        self.builder.switch_to_block(next);
+    }
+
+    fn add_instruction(&mut self, instruction: &Instruction, print: FuncRef) {
+        match *instruction {
+            Instruction::Print(location) => {
+                let value = self.value(location);
+                self.builder.ins().call(print, &[self.state, value]);
+            }
+            Instruction::Bin { lhs, op, rhs, dst } => {
+                let (lhs, rhs) = (self.value(lhs), self.value(rhs));
+                let ins = self.builder.ins();
+                let result = match op {
+                    Op::Add => ins.iadd(lhs, rhs),
+                    Op::Sub => ins.isub(lhs, rhs),
+                    Op::Mul => ins.imul(lhs, rhs),
+                    // The divisor is a constant other than 0 and -1, so it never traps.
+                    Op::Div => ins.sdiv(lhs, rhs),
+                };
+                self.define(dst, result);
+            }
+            Instruction::CheckedBin { lhs, op, rhs, dst } => {
+                let (lhs, rhs) = (self.value(lhs), self.value(rhs));
+                let ins = self.builder.ins();
+                let (result, overflow) = match op {
+                    Op::Add => ins.sadd_overflow(lhs, rhs),
+                    Op::Sub => ins.ssub_overflow(lhs, rhs),
+                    Op::Mul => ins.smul_overflow(lhs, rhs),
+                    Op::Div => unreachable!("division is handled separately"),
+                };
+                self.fail_if(overflow, OVERFLOW);
+                self.define(Location::Register(dst), result);
+            }
+            Instruction::BigBin { .. } | Instruction::BigPrint(_) => {
+                unreachable!("unbounded arithmetic is not supported")
+            }
+            Instruction::Div {
+                lhs,
+                rhs,
+                dst,
+                checked,
+            } => {
+                let (lhs, rhs) = (self.value(lhs), self.value(rhs));
+                let zero = self.builder.ins().icmp_imm(IntCC::Equal, rhs, 0);
+                self.fail_if(zero, DIVISION_BY_ZERO);
+
+                // sdiv traps on the minimum divided by -1, like in LLVM it is undefined.
+                let minus_one = self.builder.ins().icmp_imm(IntCC::Equal, rhs, -1);
+                let result = if checked {
+                    let minimum =
+                        self.builder
+                            .ins()
+                            .icmp_imm(IntCC::Equal, lhs, i64::from(i32::MIN as u32));
+                    let overflow = self.builder.ins().band(minimum, minus_one);
+                    self.fail_if(overflow, OVERFLOW);
+                    self.builder.ins().sdiv(lhs, rhs)
+                } else {
+                    let one = self.builder.ins().iconst(types::I32, 1);
+                    let divisor = self.builder.ins().select(minus_one, one, rhs);
+                    let quotient = self.builder.ins().sdiv(lhs, divisor);
+                    let negation = self.builder.ins().ineg(lhs);
+                    self.builder.ins().select(minus_one, negation, quotient)
+                };
+                self.define(Location::Register(dst), result);
+            }
+            Instruction::Machine { lhs, op, rhs, dst } => {
+                let (lhs, rhs) = (self.value(lhs), self.value(rhs));
+                let ins = self.builder.ins();
+                // The shift amount is taken modulo 32 by the instructions.
+                let result = match op {
+                    MachineOp::Shl => ins.ishl(lhs, rhs),
+                    MachineOp::Sar => ins.sshr(lhs, rhs),
+                    MachineOp::Shr => ins.ushr(lhs, rhs),
+                    MachineOp::MulHigh => ins.smulhi(lhs, rhs),
+                };
+                self.define(Location::Register(dst), result);
+            }
+        }
+    }
+
+    /// Returns success at the end of the program and fills the error blocks.
+    fn finish(mut self) {
+        let success = self.builder.ins().iconst(types::I32, SUCCESS);
+        self.builder.ins().return_(&[success]);
+
+        for (block, status) in [(self.divzero, DIVISION_BY_ZERO), (self.overflow, OVERFLOW)] {
+            if let Some(block) = block {
+                self.builder.switch_to_block(block);
+                self.builder.seal_block(block);
+                let status = self.builder.ins().iconst(types::I32, status);
+                self.builder.ins().return_(&[status]);
+            }
+        }
+
+        self.builder.seal_all_blocks();
+        self.builder.finalize();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        opt::{OptLevel, PassManager},
+        testing::{self, Execution},
+    };
+    use std::fs;
+
+    #[test]
+    fn examples() {
+        let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/examples");
+        let mut sources = fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "ins"))
+            .collect::<Vec<_>>();
+        sources.sort();
+        assert!(!sources.is_empty());
+
+        for path in sources {
+            let source = fs::read_to_string(&path).unwrap();
+            let expected = fs::read(path.with_extension("output")).unwrap();
+            for arithmetic in [Arithmetic::Wrapping, Arithmetic::Checked] {
+                let mut program = testing::program(&source, arithmetic);
+                PassManager::new(OptLevel::O0, true)
+                    .run(&mut program, &mut io::sink())
+                    .unwrap();
+                let mut output = Vec::new();
+                let jit = Jit::new(&program).unwrap();
+                jit.run(&mut output).unwrap();
+                assert_eq!(output, expected, "{}", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let sources = [
+            "a = 5; a; a / (a - 5); 1",
+            "a = 2147483647; a - 1; a + 1",
+            "a = 0 - 2147483647 - 1; a / 3; a - 1; 2",
+            "a = 0 - 2147483647 - 1; a; a / (0 - 1); a * a",
+            "a = 65536; b = a - 1; a * b; a * a",
+            "a = 100; a / 7; a / (0 - 7); (0 - a) / 7; a / 0",
+        ];
+        testing::check(&sources, |program| {
+            let jit = Jit::new(program).unwrap();
+            let mut stdout = Vec::new();
+            let result = jit.run(&mut stdout);
+
+            // The code can be executed again.
+            let mut again = Vec::new();
+            let _ = jit.run(&mut again);
+            assert_eq!(again, stdout);
+
+            Some(Execution::finished(stdout, &result))
+        });
+    }
+}
@@ -1,6 +1,18 @@
+pub mod analysis;
 pub mod ast;
 pub mod backend;
+pub mod debugger;
+pub mod interpreter;
+pub mod ir;
+pub mod jit;
 pub mod lines;
+pub mod opt;
+pub mod parser;
+pub mod simulator;
+#[cfg(test)]
+mod testing;
+pub mod transport;
+pub mod vm;
 
 /// Undeclared variable access error.
 #[derive(Debug)]
@@ -10,3 +22,16 @@ pub struct UndeclaredVariableError<'a> {
     /// Byte offset into the input Instant program.
     pub byte_offset: usize,
 }
+
+/// Semantics of arithmetic operations on i32 values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Arithmetic {
+    /// Results that do not fit in 32 bits silently wrap around.
+    #[default]
+    Wrapping,
+    /// Results that do not fit in 32 bits abort the program.
+    Checked,
+    /// Integers have arbitrary precision and never overflow.
+    /// Literals are not limited to the i32 range.
+    Unbounded,
+}
@@ -7,6 +7,18 @@ pub struct Position {
     column: usize,
 }
 
+impl Position {
+    /// Returns the line number, starting from 1.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Returns the column number, starting from 1.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+}
+
 impl Display for Position {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "(line {}, column {})", self.line, self.column)
@@ -48,6 +60,18 @@ impl Lines {
             },
         }
     }
+
+    /// Returns the byte offset of the given line and column, both starting from 1, the inverse
+    /// of [Lines::position]. Returns [None] for lines after the end of the input.
+    pub fn offset(&self, line: usize, column: usize) -> Option<usize> {
+        let line_start = match line {
+            0 => return None,
+            1 => 0,
+            line => self.breaks.get(line - 2)? + 1,
+        };
+
+        Some(line_start + column.saturating_sub(1))
+    }
 }
 
 #[cfg(test)]
@@ -62,5 +86,11 @@ mod test {
         assert_eq!(lines.position(4), Position { line: 2, column: 1 });
         assert_eq!(lines.position(7), Position { line: 4, column: 1 });
         assert_eq!(lines.position(10), Position { line: 4, column: 4 });
+
+        assert_eq!(lines.offset(1, 2), Some(1));
+        assert_eq!(lines.offset(4, 4), Some(10));
+        assert_eq!(lines.offset(5, 1), Some(12));
+        assert_eq!(lines.offset(6, 1), None);
+        assert_eq!(lines.offset(0, 1), None);
     }
 }
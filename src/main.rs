@@ -1,88 +1,400 @@
-#[macro_use]
-extern crate lalrpop_util;
+mod debug;
+mod repl;
 
-lalrpop_mod!(#[allow(clippy::all)] pub grammar);
-
-use grammar::ProgParser;
 use instant::{
-    backend::{jasmin::JasminBackend, llvm::LLVMBackend, Backend},
+    ast::Stmt,
+    backend::{
+        bytecode::{self, Bytecode, BytecodeBackend},
+        c::CBackend,
+        elf,
+        jasmin::{JasminBackend, Peephole},
+        llvm::LLVMBackend,
+        riscv::RiscVBackend,
+        wasm::WasmBackend,
+        x86_64::X86Backend,
+        Backend,
+    },
+    interpreter::{Interpreter, RuntimeError},
+    ir::Program,
+    jit::Jit,
     lines::Lines,
+    opt::{self, OptLevel, Pass, PassManager},
+    parser::{self, SyntaxError},
+    simulator, vm, Arithmetic,
 };
-use lalrpop_util::ParseError;
 use std::{
-    env,
-    io::{self, Read},
+    env, fs,
+    io::{self, BufWriter, IsTerminal, Read, Write},
     process::ExitCode,
 };
 
-fn run(class_name: Option<String>) -> Result<(), String> {
+/// Output of the compiler.
+enum Target {
+    Jasmin(String),
+    /// JVM class file with the given class name.
+    Class(String),
+    Llvm,
+    Ir,
+    Bytecode,
+    Asm,
+    Executable,
+    /// WebAssembly module in the text format.
+    Wat,
+    /// WebAssembly module in the binary format.
+    Wasm,
+    /// C99 source.
+    C,
+    /// RV32IM assembly.
+    RiscV,
+    /// RV32IM assembly executed by the simulator, whose output is the output of the program.
+    Simulator,
+    /// Native code generated by Cranelift and executed in memory.
+    Jit,
+}
+
+/// Compilation options, independent of the [Target].
+struct Options {
+    arithmetic: Arithmetic,
+    level: OptLevel,
+    /// Passes overriding the ones selected by the optimization level.
+    passes: Option<Vec<Pass>>,
+    print_after: Option<Pass>,
+    time_passes: bool,
+    /// Whether the whole program is evaluated after the other passes.
+    precompute: bool,
+}
+
+/// Parses the Instant program, reporting errors at their positions.
+fn parse<'i>(
+    input: &'i str,
+    lines: &Lines,
+    arithmetic: Arithmetic,
+) -> Result<Vec<Stmt<'i>>, String> {
+    parser::parse(input, arithmetic).map_err(|e| syntax_error(e, lines))
+}
+
+/// Parses the Instant program keeping the byte range of every statement, see [parse].
+fn parse_spanned<'i>(
+    input: &'i str,
+    lines: &Lines,
+    arithmetic: Arithmetic,
+) -> Result<Vec<(usize, Stmt<'i>, usize)>, String> {
+    parser::parse_spanned(input, arithmetic).map_err(|e| syntax_error(e, lines))
+}
+
+/// Returns the message of the syntax error with its position.
+fn syntax_error(error: SyntaxError, lines: &Lines) -> String {
+    format!("{} at {}", error, lines.position(error.range().0))
+}
+
+/// Parses and lowers the Instant program, reporting errors at their positions.
+fn lower(input: &str, arithmetic: Arithmetic) -> Result<Program, String> {
+    let lines = Lines::new(input);
+    let stmts = parse(input, &lines, arithmetic)?;
+
+    Program::lower(&stmts, arithmetic).map_err(|e| {
+        format!(
+            "undeclared variable {} at {}",
+            e.name,
+            lines.position(e.byte_offset)
+        )
+    })
+}
+
+fn run(target: Target, options: Options) -> Result<(), String> {
     let mut input = String::new();
     io::stdin()
         .read_to_string(&mut input)
         .map_err(|e| format!("failed to read STDIN: {}", e))?;
 
-    let lines = Lines::new(&input);
+    let program = lower(&input, options.arithmetic)?;
+
+    compile(program, target, &options, &mut io::stdout().lock())
+}
 
-    let stmts = ProgParser::new().parse(&input).map_err(|e| match e {
-        ParseError::ExtraToken { token } | ParseError::UnrecognizedToken { token, .. } => {
-            format!("unexpected token at {}", lines.position(token.0))
+/// Optimizes the program and writes the code generated for the target to `output`.
+fn compile(
+    mut program: Program,
+    target: Target,
+    options: &Options,
+    output: &mut dyn Write,
+) -> Result<(), String> {
+    let mut passes = options
+        .passes
+        .clone()
+        .unwrap_or_else(|| options.level.passes());
+    if options.precompute {
+        passes.push(Pass::Precompute);
+    }
+    if let Some(pass) = options.print_after.filter(|pass| !passes.contains(pass)) {
+        return Err(format!("--print-after={} names a pass that is not run", pass));
+    }
+    if program.arithmetic == Arithmetic::Unbounded {
+        match target {
+            Target::Asm | Target::Executable => {
+                return Err("--bigint is not supported by the x86-64 backend".into())
+            }
+            Target::Wat | Target::Wasm => {
+                return Err("--bigint is not supported by the WebAssembly backend".into())
+            }
+            Target::C => return Err("--bigint is not supported by the C backend".into()),
+            Target::RiscV | Target::Simulator => {
+                return Err("--bigint is not supported by the RISC-V backend".into())
+            }
+            Target::Jit => return Err("--bigint is not supported by the JIT".into()),
+            _ => {}
         }
-        ParseError::InvalidToken { location } => {
-            format!("invalid token at {}", lines.position(location))
+    }
+    let passes = PassManager {
+        passes,
+        native: matches!(
+            target,
+            Target::Llvm
+                | Target::Asm
+                | Target::Executable
+                | Target::Wat
+                | Target::Wasm
+                | Target::RiscV
+                | Target::Simulator
+                | Target::Jit
+        ),
+        print_after: options.print_after,
+        time_passes: options.time_passes,
+    };
+    passes
+        .run(&mut program, &mut io::stderr())
+        .map_err(|e| format!("failed to write to STDERR: {}", e))?;
+
+    match target {
+        Target::Jasmin(class_name) => {
+            writeln!(
+                output,
+                "{}",
+                jasmin_backend(class_name, options).process(&program)
+            )
         }
-        ParseError::UnrecognizedEOF { location, .. } => {
-            format!("unexpected EOF at {}", lines.position(location))
+        Target::Class(class_name) => {
+            let class_file = jasmin_backend(class_name, options)
+                .process(&program)
+                .class_file()
+                .map_err(|e| e.to_string())?;
+            output.write_all(&class_file)
         }
-        ParseError::User { error } => format!(
-            "literal {} out of bounds at {}",
-            error.literal,
-            lines.position(error.position)
-        ),
-    })?;
-
-    match class_name {
-        Some(class_name) => {
-            let backend = JasminBackend::new(class_name);
-            let representation = backend.process(&stmts).map_err(|e| {
-                format!(
-                    "undeclared variable {} at {}",
-                    e.name,
-                    lines.position(e.byte_offset)
-                )
-            })?;
-            println!("{}", representation);
+        Target::Llvm => writeln!(output, "{}", LLVMBackend.process(&program)),
+        Target::Ir => write!(output, "{}", program),
+        Target::Bytecode => output.write_all(&BytecodeBackend.process(&program).encode()),
+        Target::Asm => write!(output, "{}", X86Backend::default().process(&program)),
+        Target::Executable => {
+            output.write_all(&elf::executable(&X86Backend::default().process(&program)))
         }
-        None => {
-            let backend = LLVMBackend::default();
-            let representation = backend.process(&stmts).map_err(|e| {
-                format!(
-                    "undeclared variable {} at {}",
-                    e.name,
-                    lines.position(e.byte_offset)
-                )
-            })?;
-            println!("{}", representation);
+        Target::Wat => write!(output, "{}", WasmBackend.process(&program)),
+        Target::Wasm => output.write_all(&WasmBackend.process(&program).encode()),
+        Target::C => write!(output, "{}", CBackend.process(&program)),
+        Target::RiscV => write!(output, "{}", RiscVBackend::default().process(&program)),
+        Target::Simulator => {
+            // The messages of runtime errors are reported like by the interpreter.
+            let mut errors = Vec::new();
+            let assembly = RiscVBackend::default().process(&program);
+            return match simulator::run(&assembly, output, &mut errors) {
+                Ok(0) => Ok(()),
+                Ok(_) => Err(String::from_utf8_lossy(&errors).trim_end().to_string()),
+                Err(e) => Err(format!("simulation failed: {}", e)),
+            };
+        }
+        Target::Jit => {
+            let jit = Jit::new(&program).map_err(|e| e.to_string())?;
+            return jit.run(output).map_err(|e| runtime_error(e, None));
         }
     }
+    .map_err(|e| format!("failed to write the output: {}", e))
+}
 
-    Ok(())
+fn jasmin_backend(class_name: String, options: &Options) -> JasminBackend {
+    let peephole = match options.level {
+        OptLevel::O0 => Peephole::NONE,
+        _ => Peephole::ALL,
+    };
+    JasminBackend::new(class_name).with_peephole(peephole)
+}
+
+/// Executes the program in the given file, either Instant source with the reference interpreter
+/// or bytecode with the virtual machine. Bytecode specifies its own arithmetic mode.
+fn interpret(path: &str, arithmetic: Arithmetic) -> Result<(), String> {
+    let input = fs::read(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+    let mut output = BufWriter::new(io::stdout().lock());
+
+    let result = if input.starts_with(&bytecode::MAGIC) {
+        let bytecode = Bytecode::decode(&input).map_err(|e| format!("{} in {}", e, path))?;
+        vm::run(&bytecode, &mut output).map_err(|e| runtime_error(e, None))
+    } else {
+        let input = String::from_utf8(input).map_err(|_| format!("{} is not UTF-8", path))?;
+        let lines = Lines::new(&input);
+        let stmts = parse(&input, &lines, arithmetic)?;
+        Interpreter::new(arithmetic)
+            .run(&stmts, &mut output)
+            .map_err(|e| runtime_error(e, Some(&lines)))
+    };
+
+    output
+        .flush()
+        .map_err(|e| format!("failed to write to STDOUT: {}", e))?;
+    result
+}
+
+/// Compiles the program in the given file for the target executing it, which is
+/// either the RISC-V simulator or the JIT.
+fn execute(path: &str, target: Target, options: Options) -> Result<(), String> {
+    let input = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+    let program = lower(&input, options.arithmetic)?;
+    let mut output = BufWriter::new(io::stdout().lock());
+
+    let result = compile(program, target, &options, &mut output);
+    output
+        .flush()
+        .map_err(|e| format!("failed to write to STDOUT: {}", e))?;
+    result
+}
+
+/// Returns the message of the runtime error. Positions are known only in Instant source.
+fn runtime_error(error: RuntimeError, lines: Option<&Lines>) -> String {
+    match (error, lines) {
+        (RuntimeError::UndeclaredVariable(e), Some(lines)) => format!(
+            "undeclared variable {} at {}",
+            e.name,
+            lines.position(e.byte_offset)
+        ),
+        (RuntimeError::Output(e), _) => format!("failed to write to STDOUT: {}", e),
+        (error, _) => error.to_string(),
+    }
+}
+
+/// Parses a pass name given in the command line option.
+fn parse_pass(name: &str) -> Result<Pass, String> {
+    name.parse()
+        .map_err(|e: opt::UnknownPassError| format!("unknown pass {}", e.name))
 }
 
 fn main() -> ExitCode {
-    let args = env::args().collect::<Vec<_>>();
+    let mut overflow = None;
+    let mut bigint = false;
+    let mut level = OptLevel::default();
+    let mut passes = None;
+    let mut print_after = None;
+    let mut time_passes = false;
+    let mut precompute = false;
+    let mut args = Vec::new();
+    for arg in env::args() {
+        let parsed = if let Some(mode) = arg.strip_prefix("--overflow=") {
+            match mode {
+                "wrap" => Ok(Arithmetic::Wrapping),
+                "trap" => Ok(Arithmetic::Checked),
+                other => Err(format!("invalid overflow mode {}", other)),
+            }
+            .map(|arithmetic| overflow = Some(arithmetic))
+        } else if let Some(names) = arg.strip_prefix("--passes=") {
+            names
+                .split(',')
+                .filter(|name| !name.is_empty())
+                .map(parse_pass)
+                .collect::<Result<_, _>>()
+                .map(|list| passes = Some(list))
+        } else if let Some(name) = arg.strip_prefix("--print-after=") {
+            parse_pass(name).map(|pass| print_after = Some(pass))
+        } else {
+            match arg.as_str() {
+                "--bigint" => bigint = true,
+                "-O0" => level = OptLevel::O0,
+                "-O1" => level = OptLevel::O1,
+                "-O2" => level = OptLevel::O2,
+                "--time-passes" => time_passes = true,
+                "--precompute" => precompute = true,
+                _ => args.push(arg),
+            }
+            Ok(())
+        };
+
+        if let Err(e) = parsed {
+            eprintln!("ERROR: {}", e);
+            return ExitCode::FAILURE;
+        }
+    }
 
-    let class_name = match &args[..] {
-        [_, mode, class] if mode == "--jasmin" => Some(class.to_string()),
-        [_, mode] if mode == "--llvm" => None,
+    let arithmetic = match (overflow, bigint) {
+        (Some(_), true) => {
+            eprintln!("ERROR: --overflow cannot be used with --bigint");
+            return ExitCode::FAILURE;
+        }
+        (None, true) => Arithmetic::Unbounded,
+        (overflow, false) => overflow.unwrap_or_default(),
+    };
+
+    let options = Options {
+        arithmetic,
+        level,
+        passes,
+        print_after,
+        time_passes,
+        precompute,
+    };
+
+    let target = match &args[..] {
+        [_, mode, class] if mode == "--jasmin" => Target::Jasmin(class.to_string()),
+        [_, mode] if mode == "--llvm" => Target::Llvm,
+        [_, mode] if mode == "--ir" => Target::Ir,
+        [_, mode, kind] if mode == "--emit" && kind == "bytecode" => Target::Bytecode,
+        [_, mode, kind, class] if mode == "--emit" && kind == "class" => {
+            Target::Class(class.to_string())
+        }
+        [_, mode, kind] if mode == "--emit" && kind == "asm" => Target::Asm,
+        [_, mode, kind] if mode == "--emit" && kind == "exe" => Target::Executable,
+        [_, mode, kind] if mode == "--emit" && kind == "wat" => Target::Wat,
+        [_, mode, kind] if mode == "--emit" && kind == "wasm" => Target::Wasm,
+        [_, mode, kind] if mode == "--emit" && kind == "c" => Target::C,
+        [_, mode, kind] if mode == "--emit" && kind == "riscv" => Target::RiscV,
+        [_, command, mode, path] if command == "run" && mode == "--riscv" => {
+            return report(execute(path, Target::Simulator, options))
+        }
+        [_, command, mode, path] if command == "run" && mode == "--jit" => {
+            return report(execute(path, Target::Jit, options))
+        }
+        [_, command, path] if command == "run" => return report(interpret(path, arithmetic)),
+        [_, command, path] if command == "debug" => {
+            let prompt = io::stdin().is_terminal();
+            let result = fs::read_to_string(path)
+                .map_err(|e| format!("failed to read {}: {}", path, e))
+                .and_then(|source| {
+                    debug::run(
+                        &source,
+                        arithmetic,
+                        &mut io::stdin().lock(),
+                        &mut io::stdout().lock(),
+                        prompt,
+                    )
+                });
+            return report(result);
+        }
+        [_, command] if command == "repl" => {
+            let prompt = io::stdin().is_terminal();
+            return report(repl::run(
+                &options,
+                &mut io::stdin().lock(),
+                &mut io::stdout().lock(),
+                prompt,
+            ));
+        }
         other => {
             let prog = other
                 .first()
                 .map(String::as_ref)
                 .unwrap_or("<program name>");
             eprintln!(
-                "USAGE:\n\t{} --llvm\n\t{} --jasmin <class name>\n\t{} --help",
-                prog, prog, prog
+                "USAGE:\n\t{} [options] --llvm\n\t{} [options] --jasmin <class name>\n\t{} [options] --ir\n\t{} [options] --emit bytecode|asm|exe|wat|wasm|c|riscv\n\t{} [options] --emit class <class name>\n\t{} [options] run [--riscv|--jit] <file>\n\t{} [options] repl\n\t{} [options] debug <file>\n\t{} --help",
+                prog, prog, prog, prog, prog, prog, prog, prog, prog
             );
+            eprintln!(
+                "OPTIONS:\n\t--overflow=wrap|trap\n\t--bigint\n\t-O0 | -O1 | -O2\n\t--passes=<pass>,...\n\t--print-after=<pass>\n\t--time-passes\n\t--precompute"
+            );
+            eprintln!("PASSES: {}", Pass::ALL.map(Pass::name).join(", "));
 
             return if other.len() == 2 && other[1] == "--help" {
                 ExitCode::SUCCESS
@@ -92,7 +404,12 @@ fn main() -> ExitCode {
         }
     };
 
-    match run(class_name) {
+    report(run(target, options))
+}
+
+/// Reports the error, if any, and returns the corresponding exit code.
+fn report(result: Result<(), String>) -> ExitCode {
+    match result {
         Ok(_) => ExitCode::SUCCESS,
         Err(e) => {
             eprintln!("ERROR: {}", e);
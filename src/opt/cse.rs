@@ -0,0 +1,104 @@
+//! Common subexpression elimination pass, based on value numbering.
+
+use crate::{
+    ast::Op,
+    ir::{Instruction, MachineOp, Operand, Program},
+};
+use std::collections::{hash_map::Entry, HashMap};
+
+/// Operation identifying a computed value.
+#[derive(PartialEq, Eq, Hash)]
+enum Key {
+    Bin(Op, Operand, Operand),
+    Machine(MachineOp, Operand, Operand),
+}
+
+/// Eliminates common subexpressions with value numbering.
+/// Every operation that was already computed is replaced with the earlier result,
+/// operands of commutative operations are compared regardless of their order.
+pub fn cse(program: &mut Program) {
+    let mut replacements = vec![None; program.value_count()];
+    let mut computed = HashMap::new();
+    let substitute = |replacements: &[Option<Operand>], operand: &mut Operand| {
+        if let Operand::Value(value) = operand {
+            if let Some(replacement) = replacements[value.0] {
+                *operand = replacement;
+            }
+        }
+    };
+
+    program.instructions.retain_mut(|instruction| {
+        let (dst, key) = match instruction {
+            Instruction::Bin { dst, op, lhs, rhs } => {
+                substitute(&replacements, lhs);
+                substitute(&replacements, rhs);
+
+                if op.commutative() && rhs < lhs {
+                    (*dst, Key::Bin(*op, *rhs, *lhs))
+                } else {
+                    (*dst, Key::Bin(*op, *lhs, *rhs))
+                }
+            }
+            Instruction::Machine { dst, op, lhs, rhs } => {
+                substitute(&replacements, lhs);
+                substitute(&replacements, rhs);
+
+                (*dst, Key::Machine(*op, *lhs, *rhs))
+            }
+            Instruction::Print(operand) => {
+                substitute(&replacements, operand);
+                return true;
+            }
+        };
+
+        match computed.entry(key) {
+            Entry::Occupied(e) => {
+                replacements[dst.0] = Some(Operand::Value(*e.get()));
+                false
+            }
+            Entry::Vacant(e) => {
+                e.insert(dst);
+                true
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{parser, Arithmetic};
+
+    /// Defines variables `a`, `b` and `c` with unknown values.
+    const VARIABLES: &str = "a = 0 / 0; b = 1 / 0; c = 2 / 0; ";
+
+    fn eliminated(source: &str) -> String {
+        let stmts = parser::parse(source, Arithmetic::Wrapping).unwrap();
+        let mut program = Program::lower(&stmts, Arithmetic::Wrapping).unwrap();
+        cse(&mut program);
+        program.to_string()
+    }
+
+    #[test]
+    fn repeated_subexpressions() {
+        assert_eq!(
+            eliminated(&format!("{}(a * b + c) * (a * b + c)", VARIABLES)),
+            "; arithmetic: wrapping\n%0 = div 0, 0\n%1 = div 1, 0\n%2 = div 2, 0\n\
+            %3 = mul %0, %1\n%4 = add %3, %2\n%7 = mul %4, %4\nprint %7\n"
+        );
+    }
+
+    #[test]
+    fn commutative_operations() {
+        assert_eq!(
+            eliminated(&format!(
+                "{}a * b; b * a; a - b; b - a; 1 + c; c + 1",
+                VARIABLES
+            )),
+            "; arithmetic: wrapping\n%0 = div 0, 0\n%1 = div 1, 0\n%2 = div 2, 0\n\
+            %3 = mul %0, %1\nprint %3\nprint %3\n\
+            %5 = sub %0, %1\nprint %5\n%6 = sub %1, %0\nprint %6\n\
+            %7 = add 1, %2\nprint %7\nprint %7\n"
+        );
+    }
+}
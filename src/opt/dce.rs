@@ -0,0 +1,91 @@
+//! Dead code elimination pass, based on the liveness of values.
+
+use crate::ir::{Operand, Program};
+
+/// Removes operations whose results never reach a print.
+/// Operations that may fail at runtime are kept, so that the error stays observable.
+pub fn dce(program: &mut Program) {
+    let mut live = vec![false; program.value_count()];
+    let mut keep = vec![false; program.instructions.len()];
+
+    for (i, instruction) in program.instructions.iter().enumerate().rev() {
+        keep[i] = match instruction.dst() {
+            Some(dst) => live[dst.0] || instruction.may_fail(program.arithmetic),
+            None => true,
+        };
+
+        if keep[i] {
+            for operand in instruction.operands() {
+                if let Operand::Value(value) = operand {
+                    live[value.0] = true;
+                }
+            }
+        }
+    }
+
+    let mut keep = keep.into_iter();
+    program
+        .instructions
+        .retain(|_| keep.next().unwrap_or_default());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        ast::{Exp, Op, Stmt},
+        parser, Arithmetic,
+    };
+
+    fn eliminated(stmts: &[Stmt], arithmetic: Arithmetic) -> String {
+        let mut program = Program::lower(stmts, arithmetic).unwrap();
+        dce(&mut program);
+        program.to_string()
+    }
+
+    #[test]
+    fn unused_assignments() {
+        let source = "a = 1 / 2; b = a * 3 + 4; c = a - 5; b = c * c; c";
+
+        assert_eq!(
+            eliminated(
+                &parser::parse(source, Arithmetic::Wrapping).unwrap(),
+                Arithmetic::Wrapping
+            ),
+            "; arithmetic: wrapping\n%0 = div 1, 2\n%3 = sub %0, 5\nprint %3\n"
+        );
+        assert_eq!(
+            eliminated(
+                &parser::parse(source, Arithmetic::Checked).unwrap(),
+                Arithmetic::Checked
+            ),
+            "; arithmetic: checked\n%0 = div 1, 2\n%1 = mul %0, 3\n%2 = add %1, 4\n\
+            %3 = sub %0, 5\n%4 = mul %3, %3\nprint %3\n"
+        );
+    }
+
+    #[test]
+    fn failing_divisions() {
+        let source = "a = 7 * 6; b = 1 / (a - 42); c = a / 0; c = a / 2";
+        let mut stmts = parser::parse(source, Arithmetic::Unbounded).unwrap();
+        // The parser has no negative literals. Without overflow checks, dividing by -1 cannot fail.
+        stmts.push(Stmt::Ass {
+            var: "c",
+            exp: Exp::Bi {
+                lhs: Exp::Var {
+                    name: "a",
+                    position: 0,
+                }
+                .into(),
+                op: Op::Div,
+                rhs: Exp::Lit(-1).into(),
+            },
+        });
+
+        assert_eq!(
+            eliminated(&stmts, Arithmetic::Unbounded),
+            "; arithmetic: unbounded\n%0 = mul 7, 6\n%1 = sub %0, 42\n%2 = div 1, %1\n\
+            %3 = div %0, 0\n"
+        );
+    }
+}
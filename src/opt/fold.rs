@@ -0,0 +1,165 @@
+//! Constant folding and propagation pass.
+
+use crate::{
+    ast::Op,
+    ir::{Instruction, Operand, Program},
+    Arithmetic,
+};
+
+/// Evaluates operations on constants at compile time and propagates the results into their uses.
+/// Operations that would fail at runtime (division by zero or overflow in
+/// [Arithmetic::Checked] mode) are left intact, so that the error stays observable.
+pub fn fold(program: &mut Program) {
+    let mut constants = vec![None; program.value_count()];
+    let substitute = |constants: &[Option<Operand>], operand: &mut Operand| {
+        if let Operand::Value(value) = operand {
+            if let Some(constant) = constants[value.0] {
+                *operand = constant;
+            }
+        }
+    };
+
+    let mut instructions = Vec::with_capacity(program.instructions.len());
+    for mut instruction in std::mem::take(&mut program.instructions) {
+        match &mut instruction {
+            Instruction::Bin { dst, op, lhs, rhs } => {
+                substitute(&constants, lhs);
+                substitute(&constants, rhs);
+
+                if let Some(result) = evaluate(program, *op, *lhs, *rhs) {
+                    constants[dst.0] = Some(result);
+                    continue;
+                }
+            }
+            Instruction::Machine { dst, op, lhs, rhs } => {
+                substitute(&constants, lhs);
+                substitute(&constants, rhs);
+
+                if let (Operand::Const(lhs), Operand::Const(rhs)) = (lhs, rhs) {
+                    constants[dst.0] = Some(Operand::Const(op.apply(*lhs, *rhs)));
+                    continue;
+                }
+            }
+            Instruction::Print(operand) => substitute(&constants, operand),
+        }
+
+        instructions.push(instruction);
+    }
+
+    program.instructions = instructions;
+}
+
+/// Returns the result of the operation on constant operands.
+/// Returns [None] if any of the operands is not a constant or the operation fails at runtime.
+fn evaluate(program: &mut Program, op: Op, lhs: Operand, rhs: Operand) -> Option<Operand> {
+    match program.arithmetic {
+        Arithmetic::Wrapping | Arithmetic::Checked => {
+            let (Operand::Const(lhs), Operand::Const(rhs)) = (lhs, rhs) else {
+                return None;
+            };
+
+            let result = if program.arithmetic == Arithmetic::Wrapping && rhs != 0 {
+                Some(match op {
+                    Op::Add => lhs.wrapping_add(rhs),
+                    Op::Sub => lhs.wrapping_sub(rhs),
+                    Op::Mul => lhs.wrapping_mul(rhs),
+                    Op::Div => lhs.wrapping_div(rhs),
+                })
+            } else {
+                match op {
+                    Op::Add => lhs.checked_add(rhs),
+                    Op::Sub => lhs.checked_sub(rhs),
+                    Op::Mul => lhs.checked_mul(rhs),
+                    Op::Div => lhs.checked_div(rhs),
+                }
+            };
+
+            result.map(Operand::Const)
+        }
+        Arithmetic::Unbounded => {
+            let value = |operand| match operand {
+                Operand::Const(val) => Some(i128::from(val)),
+                Operand::BigConst(idx) => program.big_constants[idx].parse::<i128>().ok(),
+                Operand::Value(_) => None,
+            };
+            let (lhs, rhs) = (value(lhs)?, value(rhs)?);
+
+            // Values that do not fit in i128 are left for the runtime.
+            let result = match op {
+                Op::Add => lhs.checked_add(rhs),
+                Op::Sub => lhs.checked_sub(rhs),
+                Op::Mul => lhs.checked_mul(rhs),
+                Op::Div => lhs.checked_div(rhs),
+            }?;
+
+            match i32::try_from(result) {
+                Ok(result) => Some(Operand::Const(result)),
+                Err(_) => {
+                    program.big_constants.push(result.to_string());
+                    Some(Operand::BigConst(program.big_constants.len() - 1))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser;
+
+    fn folded(source: &str, arithmetic: Arithmetic) -> String {
+        let stmts = parser::parse(source, arithmetic).unwrap();
+        let mut program = Program::lower(&stmts, arithmetic).unwrap();
+        fold(&mut program);
+        program.to_string()
+    }
+
+    #[test]
+    fn chains() {
+        let sum = format!("{}0", "1 + ".repeat(42));
+        assert_eq!(
+            folded(&sum, Arithmetic::Wrapping),
+            "; arithmetic: wrapping\nprint 42\n"
+        );
+
+        assert_eq!(
+            folded("a = 6 * 7; a - 7 / 2", Arithmetic::Wrapping),
+            "; arithmetic: wrapping\nprint 39\n"
+        );
+    }
+
+    #[test]
+    fn wrapping() {
+        let source = "2147483647 + 1; 65536 * 65536; (0 - 2147483647 - 1) / (0 - 1)";
+        assert_eq!(
+            folded(source, Arithmetic::Wrapping),
+            "; arithmetic: wrapping\nprint -2147483648\nprint 0\nprint -2147483648\n"
+        );
+    }
+
+    #[test]
+    fn runtime_errors() {
+        let source = "(1 + 2) / (2 - 2); 2147483647 + 1";
+        assert_eq!(
+            folded(source, Arithmetic::Wrapping),
+            "; arithmetic: wrapping\n%2 = div 3, 0\nprint %2\nprint -2147483648\n"
+        );
+        assert_eq!(
+            folded(source, Arithmetic::Checked),
+            "; arithmetic: checked\n%2 = div 3, 0\nprint %2\n%3 = add 2147483647, 1\nprint %3\n"
+        );
+    }
+
+    #[test]
+    fn unbounded() {
+        let source =
+            "2147483647 + 1; 65536 * 65536 / 4; (0 - 2147483649) * 100000000000000000000; \
+            100000000000000000000000000000000000000000 / 0";
+        assert_eq!(
+            folded(source, Arithmetic::Unbounded),
+            "; arithmetic: unbounded\nprint 2147483648\nprint 1073741824\nprint -214748364900000000000000000000\n\
+            %5 = div 100000000000000000000000000000000000000000, 0\nprint %5\n"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Optimization passes on the [Program] and the [PassManager] running them.
+
+pub mod cse;
+pub mod dce;
+pub mod fold;
+pub mod precompute;
+pub mod simplify;
+
+use crate::ir::Program;
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{self, Write},
+    str::FromStr,
+    time::Instant,
+};
+
+/// Optimization pass on the [Program].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    /// Constant folding, see [fold::fold].
+    Fold,
+    /// Algebraic simplification and strength reduction, see [simplify::simplify].
+    Simplify,
+    /// Common subexpression elimination, see [cse::cse].
+    Cse,
+    /// Dead code elimination, see [dce::dce].
+    Dce,
+    /// Evaluation of the whole program, see [precompute::precompute].
+    Precompute,
+}
+
+impl Pass {
+    /// All passes, in the order of their usual application.
+    pub const ALL: [Self; 5] = [
+        Self::Fold,
+        Self::Simplify,
+        Self::Cse,
+        Self::Dce,
+        Self::Precompute,
+    ];
+
+    /// Returns the name of this pass, as accepted by the [FromStr] implementation.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Fold => "fold",
+            Self::Simplify => "simplify",
+            Self::Cse => "cse",
+            Self::Dce => "dce",
+            Self::Precompute => "precompute",
+        }
+    }
+
+    /// Runs this pass on the program.
+    /// See [simplify::simplify] for the meaning of `native`.
+    pub fn run(self, program: &mut Program, native: bool) {
+        match self {
+            Self::Fold => fold::fold(program),
+            Self::Simplify => simplify::simplify(program, native),
+            Self::Cse => cse::cse(program),
+            Self::Dce => dce::dce(program),
+            Self::Precompute => precompute::precompute(program),
+        }
+    }
+}
+
+impl Display for Pass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Unknown pass name error.
+#[derive(Debug)]
+pub struct UnknownPassError {
+    /// The unknown name.
+    pub name: String,
+}
+
+impl FromStr for Pass {
+    type Err = UnknownPassError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|pass| pass.name() == s)
+            .ok_or_else(|| UnknownPassError { name: s.into() })
+    }
+}
+
+/// Optimization level, selecting the default passes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OptLevel {
+    /// No optimizations.
+    O0,
+    /// Folding and elimination of redundant and dead computations.
+    O1,
+    /// All optimizations.
+    #[default]
+    O2,
+}
+
+impl OptLevel {
+    /// Returns the passes run at this level, in order.
+    pub fn passes(self) -> Vec<Pass> {
+        match self {
+            Self::O0 => vec![],
+            Self::O1 => vec![Pass::Fold, Pass::Cse, Pass::Dce],
+            // Simplifications expose new constants to fold.
+            Self::O2 => vec![Pass::Fold, Pass::Simplify, Pass::Fold, Pass::Cse, Pass::Dce],
+        }
+    }
+}
+
+/// Runs the configured passes on a [Program].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassManager {
+    /// Passes to run, in order. A pass may appear many times.
+    pub passes: Vec<Pass>,
+    /// Whether the program is compiled for a native target, see [simplify::simplify].
+    pub native: bool,
+    /// Pass after which the program is dumped, every time it runs.
+    pub print_after: Option<Pass>,
+    /// Whether the time taken by every pass is reported.
+    pub time_passes: bool,
+}
+
+impl PassManager {
+    /// Creates a manager running the passes of the given level, without any reporting.
+    pub fn new(level: OptLevel, native: bool) -> Self {
+        Self {
+            passes: level.passes(),
+            native,
+            print_after: None,
+            time_passes: false,
+        }
+    }
+
+    /// Runs the passes on the program, writing the requested dumps and timings to `log`.
+    pub fn run(&self, program: &mut Program, log: &mut dyn Write) -> io::Result<()> {
+        for &pass in &self.passes {
+            let start = Instant::now();
+            pass.run(program, self.native);
+            let elapsed = start.elapsed();
+
+            if self.time_passes {
+                writeln!(log, "; {} took {:?}", pass, elapsed)?;
+            }
+            if self.print_after == Some(pass) {
+                writeln!(log, "; after {}", pass)?;
+                write!(log, "{}", program)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{parser, Arithmetic};
+
+    fn program() -> Program {
+        let stmts = parser::parse("a = 6 * 7; a + 1", Arithmetic::Wrapping).unwrap();
+        Program::lower(&stmts, Arithmetic::Wrapping).unwrap()
+    }
+
+    #[test]
+    fn pass_names() {
+        for pass in Pass::ALL {
+            assert_eq!(pass.name().parse::<Pass>().unwrap(), pass);
+        }
+        assert_eq!("gvn".parse::<Pass>().unwrap_err().name, "gvn");
+    }
+
+    #[test]
+    fn levels() {
+        let mut program = program();
+        let mut log = Vec::new();
+        PassManager::new(OptLevel::O0, false)
+            .run(&mut program, &mut log)
+            .unwrap();
+        assert_eq!(program, self::program());
+        assert!(log.is_empty());
+
+        PassManager::new(OptLevel::O2, false)
+            .run(&mut program, &mut log)
+            .unwrap();
+        assert_eq!(program.to_string(), "; arithmetic: wrapping\nprint 43\n");
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn reporting() {
+        let mut program = program();
+        let mut log = Vec::new();
+        let manager = PassManager {
+            passes: vec![Pass::Cse, Pass::Fold, Pass::Cse],
+            native: false,
+            print_after: Some(Pass::Cse),
+            time_passes: true,
+        };
+        manager.run(&mut program, &mut log).unwrap();
+
+        let log = String::from_utf8(log).unwrap();
+        let lines = log.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 11);
+        assert!(lines[0].starts_with("; cse took "));
+        assert_eq!(
+            lines[1..6],
+            [
+                "; after cse",
+                "; arithmetic: wrapping",
+                "%0 = mul 6, 7",
+                "%1 = add %0, 1",
+                "print %1",
+            ]
+        );
+        assert!(lines[6].starts_with("; fold took "));
+        assert!(lines[7].starts_with("; cse took "));
+        assert_eq!(
+            lines[8..],
+            ["; after cse", "; arithmetic: wrapping", "print 43"]
+        );
+    }
+}
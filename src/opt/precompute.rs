@@ -0,0 +1,128 @@
+//! Whole-program evaluation pass, used by `--precompute`.
+
+use crate::{
+    interpreter::{self, small},
+    ir::{Instruction, Operand, Program},
+};
+use num_bigint::BigInt;
+
+/// Evaluates the whole program at compile time, leaving only prints of the results.
+/// If the program fails at runtime (division by zero or overflow in [Arithmetic::Checked] mode),
+/// the failing operation is kept with constant operands and everything after it is removed,
+/// so that the program still fails after the same prints.
+pub fn precompute(program: &mut Program) {
+    let big_constants = std::mem::take(&mut program.big_constants);
+    let mut values = vec![BigInt::default(); program.value_count()];
+    let value = |values: &[BigInt], operand| match operand {
+        Operand::Value(value) => values[value.0].clone(),
+        Operand::Const(val) => BigInt::from(val),
+        Operand::BigConst(idx) => big_constants[idx]
+            .parse()
+            .expect("big constants are valid integers"),
+    };
+
+    let mut instructions = Vec::new();
+    for instruction in std::mem::take(&mut program.instructions) {
+        match instruction {
+            Instruction::Bin { dst, op, lhs, rhs } => {
+                let (lhs, rhs) = (value(&values, lhs), value(&values, rhs));
+                match interpreter::evaluate(program.arithmetic, op, &lhs, &rhs) {
+                    Ok(result) => values[dst.0] = result,
+                    Err(_) => {
+                        let lhs = constant(program, lhs);
+                        let rhs = constant(program, rhs);
+                        instructions.push(Instruction::Bin { dst, op, lhs, rhs });
+                        break;
+                    }
+                }
+            }
+            Instruction::Machine { dst, op, lhs, rhs } => {
+                let (lhs, rhs) = (value(&values, lhs), value(&values, rhs));
+                values[dst.0] = op.apply(small(&lhs), small(&rhs)).into();
+            }
+            Instruction::Print(operand) => {
+                let operand = constant(program, value(&values, operand));
+                instructions.push(Instruction::Print(operand));
+            }
+        }
+    }
+
+    program.instructions = instructions;
+}
+
+/// Returns the operand holding the value, adding a big constant if it does not fit in 32 bits.
+fn constant(program: &mut Program, value: BigInt) -> Operand {
+    match i32::try_from(&value) {
+        Ok(val) => Operand::Const(val),
+        Err(_) => {
+            program.big_constants.push(value.to_string());
+            Operand::BigConst(program.big_constants.len() - 1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{parser, Arithmetic};
+
+    fn lowered(source: &str, arithmetic: Arithmetic) -> Program {
+        let stmts = parser::parse(source, arithmetic).unwrap();
+        Program::lower(&stmts, arithmetic).unwrap()
+    }
+
+    fn precomputed(source: &str, arithmetic: Arithmetic) -> String {
+        let mut program = lowered(source, arithmetic);
+        precompute(&mut program);
+        program.to_string()
+    }
+
+    #[test]
+    fn constant_output() {
+        let source = "a = 2147483647 + 1; a; a / (0 - 1); (7 - 9) / 2";
+        assert_eq!(
+            precomputed(source, Arithmetic::Wrapping),
+            "; arithmetic: wrapping\nprint -2147483648\nprint -2147483648\nprint -1\n"
+        );
+        assert_eq!(
+            precomputed(source, Arithmetic::Unbounded),
+            "; arithmetic: unbounded\nprint 2147483648\nprint -2147483648\nprint -1\n"
+        );
+    }
+
+    #[test]
+    fn runtime_errors() {
+        let source = "5; a = 2 - 2; 2147483647 + 1; b = (1 + 2) / a; 7";
+        assert_eq!(
+            precomputed(source, Arithmetic::Wrapping),
+            "; arithmetic: wrapping\nprint 5\nprint -2147483648\n%3 = div 3, 0\n"
+        );
+        assert_eq!(
+            precomputed(source, Arithmetic::Checked),
+            "; arithmetic: checked\nprint 5\n%1 = add 2147483647, 1\n"
+        );
+    }
+
+    #[test]
+    fn big_values() {
+        let source = "a = 100000000000000000000 * 100000000000000000000; a; a / (a - a)";
+        assert_eq!(
+            precomputed(source, Arithmetic::Unbounded),
+            "; arithmetic: unbounded\nprint 10000000000000000000000000000000000000000\n\
+             %2 = div 10000000000000000000000000000000000000000, 0\n"
+        );
+    }
+
+    #[test]
+    fn machine_operations() {
+        let mut program = lowered("(0 - 7) / 2", Arithmetic::Wrapping);
+        crate::opt::simplify::simplify(&mut program, true);
+        assert!(program
+            .instructions
+            .iter()
+            .any(|instruction| matches!(instruction, Instruction::Machine { .. })));
+
+        precompute(&mut program);
+        assert_eq!(program.to_string(), "; arithmetic: wrapping\nprint -3\n");
+    }
+}
@@ -0,0 +1,436 @@
+//! Algebraic simplification and strength reduction pass.
+
+use crate::{
+    ast::Op,
+    ir::{Instruction, MachineOp, Operand, Program},
+    Arithmetic,
+};
+
+/// Applies algebraic identities and strength reduction.
+///
+/// The identities `x + 0`, `x - 0`, `x * 1`, `x * 0`, `x - x` and `x / 1` (and their mirrored
+/// forms) are applied in every [Arithmetic] mode, as none of them can fail at runtime.
+/// The computation of `x` itself is left for the dead code elimination, which keeps it if it may fail.
+///
+/// In [Arithmetic::Wrapping] mode, multiplications by powers of two become left shifts.
+/// If `native` is set, divisions by constants become high multiplications by magic numbers,
+/// which the JVM gains nothing from.
+pub fn simplify(program: &mut Program, native: bool) {
+    let mut replacements = vec![None; program.value_count()];
+    let substitute = |replacements: &[Option<Operand>], operand: &mut Operand| {
+        if let Operand::Value(value) = operand {
+            if let Some(replacement) = replacements[value.0] {
+                *operand = replacement;
+            }
+        }
+    };
+
+    let wrapping = program.arithmetic == Arithmetic::Wrapping;
+    let old_instructions = std::mem::take(&mut program.instructions);
+    let mut emitter = Emitter {
+        instructions: Vec::with_capacity(old_instructions.len()),
+        program,
+    };
+
+    for mut instruction in old_instructions {
+        match &mut instruction {
+            Instruction::Bin { dst, op, lhs, rhs } => {
+                substitute(&replacements, lhs);
+                substitute(&replacements, rhs);
+
+                if let Some(result) = identity(*op, *lhs, *rhs) {
+                    replacements[dst.0] = Some(result);
+                    continue;
+                }
+
+                match (*op, *lhs, *rhs) {
+                    (Op::Mul, x, Operand::Const(c)) | (Op::Mul, Operand::Const(c), x)
+                        if wrapping && (c as u32).is_power_of_two() =>
+                    {
+                        instruction = Instruction::Machine {
+                            dst: *dst,
+                            op: MachineOp::Shl,
+                            lhs: x,
+                            rhs: Operand::Const(c.trailing_zeros() as i32),
+                        };
+                    }
+                    (Op::Div, x, Operand::Const(d))
+                        if wrapping && native && !matches!(d, -1..=1 | i32::MIN) =>
+                    {
+                        replacements[dst.0] = Some(emitter.divide(x, d));
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            Instruction::Machine { lhs, rhs, .. } => {
+                substitute(&replacements, lhs);
+                substitute(&replacements, rhs);
+            }
+            Instruction::Print(operand) => substitute(&replacements, operand),
+        }
+
+        emitter.instructions.push(instruction);
+    }
+
+    emitter.program.instructions = emitter.instructions;
+}
+
+/// Returns the result of the operation if it follows from an identity.
+fn identity(op: Op, lhs: Operand, rhs: Operand) -> Option<Operand> {
+    match (op, lhs, rhs) {
+        (Op::Add, x, Operand::Const(0))
+        | (Op::Add, Operand::Const(0), x)
+        | (Op::Sub, x, Operand::Const(0))
+        | (Op::Mul, x, Operand::Const(1))
+        | (Op::Mul, Operand::Const(1), x)
+        | (Op::Div, x, Operand::Const(1)) => Some(x),
+        (Op::Mul, _, Operand::Const(0)) | (Op::Mul, Operand::Const(0), _) => {
+            Some(Operand::Const(0))
+        }
+        (Op::Sub, lhs, rhs) if lhs == rhs => Some(Operand::Const(0)),
+        _ => None,
+    }
+}
+
+/// Returns the magic number and the shift amount for the division by the given constant,
+/// as described in chapter 10 of Hacker's Delight.
+/// The divisor must not be -1, 0, 1 or [i32::MIN].
+fn magic(divisor: i32) -> (i32, u32) {
+    const TWO_31: u32 = 1 << 31;
+
+    let abs = divisor.unsigned_abs();
+    let t = TWO_31 + (divisor as u32 >> 31);
+    let abs_nc = t - 1 - t % abs;
+    let mut p = 31;
+    let (mut q1, mut r1) = (TWO_31 / abs_nc, TWO_31 % abs_nc);
+    let (mut q2, mut r2) = (TWO_31 / abs, TWO_31 % abs);
+
+    loop {
+        p += 1;
+        q1 = q1.wrapping_mul(2);
+        r1 *= 2;
+        if r1 >= abs_nc {
+            q1 = q1.wrapping_add(1);
+            r1 -= abs_nc;
+        }
+        q2 = q2.wrapping_mul(2);
+        r2 *= 2;
+        if r2 >= abs {
+            q2 = q2.wrapping_add(1);
+            r2 -= abs;
+        }
+
+        let delta = abs - r2;
+        if q1 > delta || (q1 == delta && r1 != 0) {
+            break;
+        }
+    }
+
+    let magic = q2.wrapping_add(1) as i32;
+    if divisor < 0 {
+        (magic.wrapping_neg(), p - 32)
+    } else {
+        (magic, p - 32)
+    }
+}
+
+struct Emitter<'p> {
+    program: &'p mut Program,
+    instructions: Vec<Instruction>,
+}
+
+impl Emitter<'_> {
+    fn bin(&mut self, op: Op, lhs: Operand, rhs: Operand) -> Operand {
+        let dst = self.program.new_value();
+        self.instructions
+            .push(Instruction::Bin { dst, op, lhs, rhs });
+        dst.into()
+    }
+
+    fn machine(&mut self, op: MachineOp, lhs: Operand, rhs: Operand) -> Operand {
+        let dst = self.program.new_value();
+        self.instructions
+            .push(Instruction::Machine { dst, op, lhs, rhs });
+        dst.into()
+    }
+
+    /// Emits the division by a constant with a high multiplication, returns the quotient.
+    fn divide(&mut self, dividend: Operand, divisor: i32) -> Operand {
+        let (magic, shift) = magic(divisor);
+
+        let mut quotient = self.machine(MachineOp::MulHigh, dividend, Operand::Const(magic));
+        if divisor > 0 && magic < 0 {
+            quotient = self.bin(Op::Add, quotient, dividend);
+        } else if divisor < 0 && magic > 0 {
+            quotient = self.bin(Op::Sub, quotient, dividend);
+        }
+        if shift > 0 {
+            quotient = self.machine(MachineOp::Sar, quotient, Operand::Const(shift as i32));
+        }
+
+        // Rounds negative quotients towards zero.
+        let sign = self.machine(MachineOp::Shr, quotient, Operand::Const(31));
+        self.bin(Op::Add, quotient, sign)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ir::Value;
+
+    /// Value unknown at compile time.
+    const X: Operand = Operand::Value(Value(0));
+
+    fn simplified(
+        arithmetic: Arithmetic,
+        native: bool,
+        op: Op,
+        lhs: Operand,
+        rhs: Operand,
+    ) -> Program {
+        let mut program = Program::new(arithmetic);
+        program.new_value();
+        let dst = program.new_value();
+        program
+            .instructions
+            .push(Instruction::Bin { dst, op, lhs, rhs });
+        program.instructions.push(Instruction::Print(dst.into()));
+
+        simplify(&mut program, native);
+        program
+    }
+
+    /// Returns the printed value, for the given value of [X].
+    fn run(program: &Program, x: i32) -> i32 {
+        let mut values = vec![x; program.value_count()];
+        let get = |values: &[i32], operand| match operand {
+            Operand::Value(value) => values[value.0],
+            Operand::Const(val) => val,
+            Operand::BigConst(_) => unreachable!("big constants are not used in the tests"),
+        };
+
+        for instruction in &program.instructions {
+            match *instruction {
+                Instruction::Bin { dst, op, lhs, rhs } => {
+                    let (lhs, rhs) = (get(&values, lhs), get(&values, rhs));
+                    values[dst.0] = match op {
+                        Op::Add => lhs.wrapping_add(rhs),
+                        Op::Sub => lhs.wrapping_sub(rhs),
+                        Op::Mul => lhs.wrapping_mul(rhs),
+                        Op::Div => lhs.wrapping_div(rhs),
+                    };
+                }
+                Instruction::Machine { dst, op, lhs, rhs } => {
+                    values[dst.0] = op.apply(get(&values, lhs), get(&values, rhs));
+                }
+                Instruction::Print(operand) => return get(&values, operand),
+            }
+        }
+
+        unreachable!("every test program prints its result")
+    }
+
+    #[test]
+    fn additive_identities() {
+        for arithmetic in [
+            Arithmetic::Wrapping,
+            Arithmetic::Checked,
+            Arithmetic::Unbounded,
+        ] {
+            for (op, lhs, rhs) in [
+                (Op::Add, X, Operand::Const(0)),
+                (Op::Add, Operand::Const(0), X),
+                (Op::Sub, X, Operand::Const(0)),
+            ] {
+                assert_eq!(
+                    simplified(arithmetic, true, op, lhs, rhs).to_string(),
+                    format!("; arithmetic: {}\nprint %0\n", arithmetic)
+                );
+            }
+        }
+
+        assert_eq!(
+            simplified(Arithmetic::Wrapping, true, Op::Sub, Operand::Const(0), X).to_string(),
+            "; arithmetic: wrapping\n%1 = sub 0, %0\nprint %1\n"
+        );
+    }
+
+    #[test]
+    fn multiplicative_identities() {
+        for arithmetic in [
+            Arithmetic::Wrapping,
+            Arithmetic::Checked,
+            Arithmetic::Unbounded,
+        ] {
+            for (op, lhs, rhs) in [
+                (Op::Mul, X, Operand::Const(1)),
+                (Op::Mul, Operand::Const(1), X),
+                (Op::Div, X, Operand::Const(1)),
+            ] {
+                assert_eq!(
+                    simplified(arithmetic, true, op, lhs, rhs).to_string(),
+                    format!("; arithmetic: {}\nprint %0\n", arithmetic)
+                );
+            }
+        }
+
+        assert_eq!(
+            simplified(Arithmetic::Wrapping, true, Op::Div, Operand::Const(1), X).to_string(),
+            "; arithmetic: wrapping\n%1 = div 1, %0\nprint %1\n"
+        );
+    }
+
+    #[test]
+    fn multiplication_by_zero() {
+        for arithmetic in [
+            Arithmetic::Wrapping,
+            Arithmetic::Checked,
+            Arithmetic::Unbounded,
+        ] {
+            for (lhs, rhs) in [(X, Operand::Const(0)), (Operand::Const(0), X)] {
+                assert_eq!(
+                    simplified(arithmetic, true, Op::Mul, lhs, rhs).to_string(),
+                    format!("; arithmetic: {}\nprint 0\n", arithmetic)
+                );
+            }
+        }
+
+        // Division by zero still has to fail.
+        assert_eq!(
+            simplified(Arithmetic::Wrapping, true, Op::Div, Operand::Const(0), X).to_string(),
+            "; arithmetic: wrapping\n%1 = div 0, %0\nprint %1\n"
+        );
+    }
+
+    #[test]
+    fn self_subtraction() {
+        for arithmetic in [
+            Arithmetic::Wrapping,
+            Arithmetic::Checked,
+            Arithmetic::Unbounded,
+        ] {
+            assert_eq!(
+                simplified(arithmetic, true, Op::Sub, X, X).to_string(),
+                format!("; arithmetic: {}\nprint 0\n", arithmetic)
+            );
+        }
+
+        assert_eq!(
+            simplified(Arithmetic::Wrapping, true, Op::Div, X, X).to_string(),
+            "; arithmetic: wrapping\n%1 = div %0, %0\nprint %1\n"
+        );
+    }
+
+    #[test]
+    fn shifts() {
+        for (lhs, rhs) in [(X, Operand::Const(8)), (Operand::Const(8), X)] {
+            assert_eq!(
+                simplified(Arithmetic::Wrapping, false, Op::Mul, lhs, rhs).to_string(),
+                "; arithmetic: wrapping\n%1 = shl %0, 3\nprint %1\n"
+            );
+        }
+
+        let program = simplified(
+            Arithmetic::Wrapping,
+            false,
+            Op::Mul,
+            X,
+            Operand::Const(i32::MIN),
+        );
+        assert_eq!(
+            program.to_string(),
+            "; arithmetic: wrapping\n%1 = shl %0, 31\nprint %1\n"
+        );
+        assert_eq!(run(&program, 3), 3i32.wrapping_mul(i32::MIN));
+
+        for c in [6, -8] {
+            let program = simplified(Arithmetic::Wrapping, false, Op::Mul, X, Operand::Const(c));
+            assert_eq!(
+                program.to_string(),
+                format!("; arithmetic: wrapping\n%1 = mul %0, {}\nprint %1\n", c)
+            );
+        }
+
+        // Shifts never overflow.
+        assert_eq!(
+            simplified(Arithmetic::Checked, false, Op::Mul, X, Operand::Const(8)).to_string(),
+            "; arithmetic: checked\n%1 = mul %0, 8\nprint %1\n"
+        );
+    }
+
+    #[test]
+    fn division_by_constants() {
+        assert_eq!(
+            simplified(Arithmetic::Wrapping, true, Op::Div, X, Operand::Const(3)).to_string(),
+            "; arithmetic: wrapping\n%2 = mulhi %0, 1431655766\n%3 = shr %2, 31\n\
+            %4 = add %2, %3\nprint %4\n"
+        );
+        assert_eq!(
+            simplified(Arithmetic::Wrapping, true, Op::Div, X, Operand::Const(7)).to_string(),
+            "; arithmetic: wrapping\n%2 = mulhi %0, -1840700269\n%3 = add %2, %0\n\
+            %4 = sar %3, 2\n%5 = shr %4, 31\n%6 = add %4, %5\nprint %6\n"
+        );
+
+        for (arithmetic, native, divisor) in [
+            (Arithmetic::Wrapping, false, 3),
+            (Arithmetic::Checked, true, 3),
+            (Arithmetic::Wrapping, true, -1),
+            (Arithmetic::Wrapping, true, 0),
+            (Arithmetic::Wrapping, true, i32::MIN),
+        ] {
+            assert_eq!(
+                simplified(arithmetic, native, Op::Div, X, Operand::Const(divisor)).to_string(),
+                format!(
+                    "; arithmetic: {}\n%1 = div %0, {}\nprint %1\n",
+                    arithmetic, divisor
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn division_sequences() {
+        let divisors = (2..=64).flat_map(|d| [d, -d]).chain([
+            641,
+            1 << 30,
+            -(1 << 30),
+            1_000_000_007,
+            i32::MAX,
+            i32::MIN + 1,
+        ]);
+        let dividends = [
+            i32::MIN,
+            i32::MIN + 1,
+            -7,
+            -1,
+            0,
+            1,
+            7,
+            i32::MAX - 1,
+            i32::MAX,
+        ]
+        .into_iter()
+        .chain((i32::MIN..=i32::MAX).step_by(999_983));
+
+        for divisor in divisors {
+            let program = simplified(
+                Arithmetic::Wrapping,
+                true,
+                Op::Div,
+                X,
+                Operand::Const(divisor),
+            );
+            for dividend in dividends.clone() {
+                assert_eq!(
+                    run(&program, dividend),
+                    dividend.wrapping_div(divisor),
+                    "{} / {}",
+                    dividend,
+                    divisor
+                );
+            }
+        }
+    }
+}
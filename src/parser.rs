@@ -0,0 +1,119 @@
+//! Parser of Instant programs, generated by LALRPOP from `grammar.lalrpop`.
+
+use crate::{
+    ast::{LiteralOutOfBoundsError, Stmt},
+    Arithmetic,
+};
+use lalrpop_util::{lalrpop_mod, ParseError};
+use std::fmt::{self, Display, Formatter};
+
+lalrpop_mod!(
+    #[allow(clippy::all)]
+    grammar
+);
+
+/// Error preventing the program from being parsed.
+#[derive(Debug)]
+pub enum SyntaxError<'a> {
+    /// Token not allowed at its position.
+    UnexpectedToken { start: usize, end: usize },
+    /// Input not forming any token.
+    InvalidToken { position: usize },
+    /// Input ending in the middle of a statement.
+    UnexpectedEof { position: usize },
+    /// Literal out of the i32 range in bounded arithmetic modes.
+    LiteralOutOfBounds(LiteralOutOfBoundsError<'a>),
+}
+
+impl SyntaxError<'_> {
+    /// Returns the byte range of the input causing the error.
+    pub fn range(&self) -> (usize, usize) {
+        match self {
+            Self::UnexpectedToken { start, end } => (*start, *end),
+            Self::InvalidToken { position } => (*position, *position + 1),
+            Self::UnexpectedEof { position } => (*position, *position),
+            Self::LiteralOutOfBounds(e) => (e.position, e.position + e.literal.len()),
+        }
+    }
+}
+
+impl<'a, T> From<ParseError<usize, T, LiteralOutOfBoundsError<'a>>> for SyntaxError<'a> {
+    fn from(error: ParseError<usize, T, LiteralOutOfBoundsError<'a>>) -> Self {
+        match error {
+            ParseError::ExtraToken { token } | ParseError::UnrecognizedToken { token, .. } => {
+                Self::UnexpectedToken {
+                    start: token.0,
+                    end: token.2,
+                }
+            }
+            ParseError::InvalidToken { location } => Self::InvalidToken { position: location },
+            ParseError::UnrecognizedEOF { location, .. } => {
+                Self::UnexpectedEof { position: location }
+            }
+            ParseError::User { error } => Self::LiteralOutOfBounds(error),
+        }
+    }
+}
+
+/// Describes the error without its position, see [SyntaxError::range].
+impl Display for SyntaxError<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedToken { .. } => f.write_str("unexpected token"),
+            Self::InvalidToken { .. } => f.write_str("invalid token"),
+            Self::UnexpectedEof { .. } => f.write_str("unexpected EOF"),
+            Self::LiteralOutOfBounds(e) => write!(f, "literal {} out of bounds", e.literal),
+        }
+    }
+}
+
+/// Parses the Instant program.
+pub fn parse(input: &str, arithmetic: Arithmetic) -> Result<Vec<Stmt<'_>>, SyntaxError<'_>> {
+    Ok(grammar::ProgParser::new().parse(arithmetic, input)?)
+}
+
+/// Parses the Instant program, keeping the byte range of every statement.
+pub fn parse_spanned(
+    input: &str,
+    arithmetic: Arithmetic,
+) -> Result<Vec<(usize, Stmt<'_>, usize)>, SyntaxError<'_>> {
+    Ok(grammar::SpannedProgParser::new().parse(arithmetic, input)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn error(input: &str, arithmetic: Arithmetic) -> (String, (usize, usize)) {
+        let error = parse(input, arithmetic).unwrap_err();
+        (error.to_string(), error.range())
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            error("a = 1;\nb = = 2", Arithmetic::Wrapping),
+            ("unexpected token".into(), (11, 12))
+        );
+        assert_eq!(
+            error("a = 1 $ 2", Arithmetic::Wrapping),
+            ("invalid token".into(), (6, 7))
+        );
+        assert_eq!(
+            error("(1 + 2", Arithmetic::Wrapping),
+            ("unexpected EOF".into(), (6, 6))
+        );
+        assert_eq!(
+            error("1 + 4294967296", Arithmetic::Checked),
+            ("literal 4294967296 out of bounds".into(), (4, 14))
+        );
+        assert!(parse("1 + 4294967296", Arithmetic::Unbounded).is_ok());
+
+        let stmts = parse_spanned("a = 1 ;\n a", Arithmetic::Wrapping).unwrap();
+        let ranges = stmts
+            .iter()
+            .map(|(start, _, end)| (*start, *end))
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, [(0, 5), (9, 10)]);
+    }
+}
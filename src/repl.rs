@@ -0,0 +1,201 @@
+//! Interactive session executing Instant statements line by line with the reference interpreter.
+
+use crate::{compile, parse, runtime_error, Options, Target};
+use instant::{interpreter::Interpreter, ir::Program, lines::Lines};
+use std::io::{BufRead, Write};
+
+const HELP: &str = "\
+<statements>  execute the statements, printing the values of expressions
+:vars         show the variables
+:reset        forget all variables and statements
+:jasmin       show the Jasmin code generated for the session
+:llvm         show the LLVM IR generated for the session
+:help         show this message";
+
+/// State of the REPL.
+struct Session<'o> {
+    options: &'o Options,
+    interpreter: Interpreter,
+    /// Lines executed without errors, without trailing semicolons.
+    lines: Vec<String>,
+}
+
+impl<'o> Session<'o> {
+    fn new(options: &'o Options) -> Self {
+        Self {
+            options,
+            interpreter: Interpreter::new(options.arithmetic),
+            lines: Vec::new(),
+        }
+    }
+
+    /// Executes a command or a line of statements.
+    /// A line failing to parse or to execute does not change the session, only its output remains.
+    fn execute(&mut self, line: &str, output: &mut dyn Write) -> Result<(), String> {
+        let write_error = |e| format!("failed to write to STDOUT: {}", e);
+
+        match line.trim() {
+            "" => Ok(()),
+            ":vars" => {
+                let mut variables = self.interpreter.variables().iter().collect::<Vec<_>>();
+                variables.sort();
+                variables
+                    .into_iter()
+                    .try_for_each(|(name, val)| writeln!(output, "{} = {}", name, val))
+                    .map_err(write_error)
+            }
+            ":reset" => {
+                *self = Self::new(self.options);
+                Ok(())
+            }
+            ":jasmin" => self.compile(Target::Jasmin("Repl".into()), output),
+            ":llvm" => self.compile(Target::Llvm, output),
+            ":help" => writeln!(output, "{}", HELP).map_err(write_error),
+            command if command.starts_with(':') => {
+                Err(format!("unknown command {}, see :help", command))
+            }
+            line => {
+                let line = line.strip_suffix(';').unwrap_or(line);
+                let lines = Lines::new(line);
+                let stmts = parse(line, &lines, self.options.arithmetic)?;
+
+                let mut interpreter = self.interpreter.clone();
+                interpreter
+                    .run(&stmts, output)
+                    .map_err(|e| runtime_error(e, Some(&lines)))?;
+                self.interpreter = interpreter;
+                self.lines.push(line.into());
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Compiles all lines executed so far as a single program.
+    fn compile(&self, target: Target, output: &mut dyn Write) -> Result<(), String> {
+        let source = self.lines.join(";\n");
+        let lines = Lines::new(&source);
+        let stmts = parse(&source, &lines, self.options.arithmetic)?;
+        let program = Program::lower(&stmts, self.options.arithmetic)
+            .expect("executed statements use only assigned variables");
+
+        compile(program, target, self.options, output)
+    }
+}
+
+/// Runs the REPL until the end of the input. Errors are written to `output` and end only the line
+/// causing them. A prompt is written before every line, if requested.
+pub fn run(
+    options: &Options,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+    prompt: bool,
+) -> Result<(), String> {
+    let mut session = Session::new(options);
+    let mut line = String::new();
+    loop {
+        if prompt {
+            write!(output, "> ")
+                .and_then(|_| output.flush())
+                .map_err(|e| format!("failed to write to STDOUT: {}", e))?;
+        }
+
+        line.clear();
+        let read = input
+            .read_line(&mut line)
+            .map_err(|e| format!("failed to read STDIN: {}", e))?;
+        if read == 0 {
+            return Ok(());
+        }
+
+        if let Err(e) = session.execute(&line, output) {
+            writeln!(output, "ERROR: {}", e)
+                .map_err(|e| format!("failed to write to STDOUT: {}", e))?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use instant::{
+        opt::{OptLevel, Pass},
+        Arithmetic,
+    };
+
+    fn options(arithmetic: Arithmetic) -> Options {
+        Options {
+            arithmetic,
+            level: OptLevel::O0,
+            passes: None,
+            print_after: None,
+            time_passes: false,
+            precompute: false,
+        }
+    }
+
+    fn session(options: &Options, input: &str) -> String {
+        let mut output = Vec::new();
+        run(options, &mut input.as_bytes(), &mut output, false).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn persistent_variables() {
+        let input = "\
+            a = 2; b = a * 3\n\
+            a + b;\n\
+            c\n\
+            \n\
+            a = 1; 7; a = 1 / 0\n\
+            a\n\
+            b = 2147483647 + 1\n\
+            :vars\n\
+            :reset\n\
+            :vars\n\
+            a\n";
+        assert_eq!(
+            session(&options(Arithmetic::Wrapping), input),
+            "\
+            8\n\
+            ERROR: undeclared variable c at (line 1, column 1)\n\
+            7\n\
+            ERROR: division by zero\n\
+            2\n\
+            a = 2\n\
+            b = -2147483648\n\
+            ERROR: undeclared variable a at (line 1, column 1)\n"
+        );
+
+        assert_eq!(
+            session(&options(Arithmetic::Checked), "b = 2147483647 + 1\n:vars\n"),
+            "ERROR: integer overflow\n"
+        );
+    }
+
+    #[test]
+    fn commands() {
+        let output = session(
+            &options(Arithmetic::Wrapping),
+            "a = 6 * 7;\na + 1\n:jasmin\n:llvm\n:frobnicate\n(\n",
+        );
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "43");
+        assert!(lines.contains(&".class public Repl"));
+        assert!(lines.contains(&"\t%t0 = mul i32 6, 7"));
+        assert_eq!(
+            lines[lines.len() - 2..],
+            [
+                "ERROR: unknown command :frobnicate, see :help",
+                "ERROR: unexpected EOF at (line 1, column 2)",
+            ]
+        );
+
+        let options = Options {
+            passes: Some(vec![Pass::Fold]),
+            ..options(Arithmetic::Wrapping)
+        };
+        let output = session(&options, "a = 6 * 7;\na + 1\n:llvm\n");
+        assert!(output.contains("call void @printInt(i32 43)"));
+    }
+}
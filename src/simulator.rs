@@ -0,0 +1,509 @@
+//! RV32IM simulator executing the [Assembly] generated by the
+//! [RiscVBackend](crate::backend::riscv::RiscVBackend).
+//!
+//! The code is placed at [TEXT] and the data after it at [DATA]. Memory spans from [DATA]
+//! to [STACK_TOP], where the stack pointer starts. Only the `write` and `exit` system calls
+//! of Linux are supported.
+
+use crate::backend::riscv::{
+    Assembly, Condition, ImmOp, Instruction, Label, RegOp, Register, Width,
+};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    io::{self, Write},
+};
+
+/// Address of the first instruction.
+pub const TEXT: u32 = 0x10000;
+
+/// Address of the first byte of the data.
+pub const DATA: u32 = 0x20000;
+
+/// Address right after the end of the memory, where the stack pointer starts.
+pub const STACK_TOP: u32 = 0x80000;
+
+/// Error stopping the simulation.
+#[derive(Debug)]
+pub enum Fault {
+    /// Jump to an address not holding an instruction, including falling through the last one.
+    InstructionAddress(u32),
+    /// Access to an address outside of the memory or not aligned to the width.
+    MemoryAddress(u32),
+    /// Instruction at the address whose immediate, or offset to its label, does not fit
+    /// in its field.
+    Immediate(u32),
+    /// System call with an unsupported number.
+    SystemCall(u32),
+    /// Failure to write the output of the program.
+    Output(io::Error),
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InstructionAddress(address) => {
+                write!(f, "invalid instruction address {:#x}", address)
+            }
+            Self::MemoryAddress(address) => write!(f, "invalid memory address {:#x}", address),
+            Self::Immediate(address) => {
+                write!(
+                    f,
+                    "immediate out of range in the instruction at {:#x}",
+                    address
+                )
+            }
+            Self::SystemCall(number) => write!(f, "unsupported system call {}", number),
+            Self::Output(e) => write!(f, "failed to write the output: {}", e),
+        }
+    }
+}
+
+struct Machine<'a> {
+    /// Instructions without the labels.
+    code: Vec<Instruction>,
+    /// Address of every label, in the code or in the data.
+    addresses: HashMap<Label, u32>,
+    registers: [u32; 32],
+    memory: Vec<u8>,
+    stdout: &'a mut dyn Write,
+    stderr: &'a mut dyn Write,
+}
+
+/// Executes the assembly from its first instruction until the `exit` system call,
+/// returning the exit code. Writes to the file descriptors 1 and 2 go to `stdout` and `stderr`.
+pub fn run(
+    assembly: &Assembly,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> Result<i32, Fault> {
+    let mut code = Vec::new();
+    let mut addresses = HashMap::new();
+    for instruction in &assembly.instructions {
+        match instruction {
+            Instruction::Label(label) => {
+                addresses.insert(*label, TEXT + 4 * code.len() as u32);
+            }
+            instruction => code.push(*instruction),
+        }
+    }
+
+    let mut memory = vec![0; (STACK_TOP - DATA) as usize];
+    let mut end = 0;
+    for (label, text) in &assembly.data {
+        addresses.insert(*label, DATA + end as u32);
+        memory[end..end + text.len()].copy_from_slice(text.as_bytes());
+        end += text.len();
+    }
+
+    for (idx, instruction) in code.iter().enumerate() {
+        let address = TEXT + 4 * idx as u32;
+        if !encodable(instruction, address, &addresses) {
+            return Err(Fault::Immediate(address));
+        }
+    }
+
+    let mut registers = [0; 32];
+    registers[Register::Sp.number()] = STACK_TOP;
+
+    Machine {
+        code,
+        addresses,
+        registers,
+        memory,
+        stdout,
+        stderr,
+    }
+    .execute()
+}
+
+impl Machine<'_> {
+    fn get(&self, register: Register) -> u32 {
+        self.registers[register.number()]
+    }
+
+    fn set(&mut self, register: Register, val: u32) {
+        if register != Register::Zero {
+            self.registers[register.number()] = val;
+        }
+    }
+
+    /// Returns the index into the memory of the access, checking its bounds and alignment.
+    fn address(&self, address: u32, size: u32) -> Result<usize, Fault> {
+        if address < DATA || address > STACK_TOP - size || !address.is_multiple_of(size) {
+            return Err(Fault::MemoryAddress(address));
+        }
+        Ok((address - DATA) as usize)
+    }
+
+    fn load(&self, width: Width, address: u32) -> Result<u32, Fault> {
+        let size = match width {
+            Width::Byte | Width::ByteUnsigned => 1,
+            Width::Half | Width::HalfUnsigned => 2,
+            Width::Word => 4,
+        };
+        let idx = self.address(address, size)?;
+        let mut bytes = [0; 4];
+        bytes[..size as usize].copy_from_slice(&self.memory[idx..idx + size as usize]);
+        let val = u32::from_le_bytes(bytes);
+
+        Ok(match width {
+            Width::Byte => val as u8 as i8 as u32,
+            Width::Half => val as u16 as i16 as u32,
+            Width::ByteUnsigned | Width::HalfUnsigned | Width::Word => val,
+        })
+    }
+
+    fn store(&mut self, width: Width, address: u32, val: u32) -> Result<(), Fault> {
+        let size = match width {
+            Width::Byte | Width::ByteUnsigned => 1,
+            Width::Half | Width::HalfUnsigned => 2,
+            Width::Word => 4,
+        };
+        let idx = self.address(address, size)?;
+        self.memory[idx..idx + size as usize].copy_from_slice(&val.to_le_bytes()[..size as usize]);
+        Ok(())
+    }
+
+    /// Executes the system call selected by `a7`, returning the exit code for `exit`.
+    fn ecall(&mut self) -> Result<Option<i32>, Fault> {
+        match self.get(Register::A7) {
+            64 => {
+                let (buffer, length) = (self.get(Register::A1), self.get(Register::A2));
+                let bytes = if length == 0 {
+                    &[][..]
+                } else {
+                    let start = self.address(buffer, 1)?;
+                    let end = self.address(buffer.wrapping_add(length - 1), 1)?;
+                    if end < start {
+                        return Err(Fault::MemoryAddress(buffer));
+                    }
+                    &self.memory[start..=end]
+                };
+                let written = match self.get(Register::A0) {
+                    1 => self.stdout.write_all(bytes).map(|_| length),
+                    2 => self.stderr.write_all(bytes).map(|_| length),
+                    // EBADF
+                    _ => Ok(-9i32 as u32),
+                };
+                self.set(Register::A0, written.map_err(Fault::Output)?);
+                Ok(None)
+            }
+            93 => Ok(Some(self.get(Register::A0) as i32)),
+            number => Err(Fault::SystemCall(number)),
+        }
+    }
+
+    fn execute(mut self) -> Result<i32, Fault> {
+        let mut pc = TEXT;
+        loop {
+            let idx = (pc.wrapping_sub(TEXT) / 4) as usize;
+            if pc < TEXT || !pc.is_multiple_of(4) || idx >= self.code.len() {
+                return Err(Fault::InstructionAddress(pc));
+            }
+            let mut next = pc + 4;
+
+            match self.code[idx] {
+                Instruction::Label(_) => unreachable!("labels are removed from the code"),
+                Instruction::Op { op, dst, lhs, rhs } => {
+                    let val = reg_op(op, self.get(lhs), self.get(rhs));
+                    self.set(dst, val);
+                }
+                Instruction::OpImm { op, dst, src, imm } => {
+                    let val = imm_op(op, self.get(src), imm);
+                    self.set(dst, val);
+                }
+                Instruction::Lui { dst, imm } => self.set(dst, imm << 12),
+                Instruction::Load {
+                    width,
+                    dst,
+                    base,
+                    offset,
+                } => {
+                    let val = self.load(width, self.get(base).wrapping_add_signed(offset))?;
+                    self.set(dst, val);
+                }
+                Instruction::Store {
+                    width,
+                    src,
+                    base,
+                    offset,
+                } => self.store(
+                    width,
+                    self.get(base).wrapping_add_signed(offset),
+                    self.get(src),
+                )?,
+                Instruction::Branch {
+                    condition,
+                    lhs,
+                    rhs,
+                    target,
+                } => {
+                    let (lhs, rhs) = (self.get(lhs), self.get(rhs));
+                    let taken = match condition {
+                        Condition::Equal => lhs == rhs,
+                        Condition::NotEqual => lhs != rhs,
+                        Condition::Less => (lhs as i32) < rhs as i32,
+                        Condition::GreaterEqual => lhs as i32 >= rhs as i32,
+                        Condition::LessUnsigned => lhs < rhs,
+                        Condition::GreaterEqualUnsigned => lhs >= rhs,
+                    };
+                    if taken {
+                        next = self.addresses[&target];
+                    }
+                }
+                Instruction::Jal { dst, target } => {
+                    self.set(dst, next);
+                    next = self.addresses[&target];
+                }
+                Instruction::Jalr { dst, base, offset } => {
+                    let target = self.get(base).wrapping_add_signed(offset) & !1;
+                    self.set(dst, next);
+                    next = target;
+                }
+                Instruction::LoadAddress { dst, label } => {
+                    let address = self.addresses[&label];
+                    self.set(dst, address);
+                }
+                Instruction::Call { target } => {
+                    self.set(Register::Ra, next);
+                    next = self.addresses[&target];
+                }
+                Instruction::Jump { target, scratch } => {
+                    // `auipc` leaves the upper part of the address in the register.
+                    let address = self.addresses[&target];
+                    let low = ((address.wrapping_sub(pc) as i32) << 20) >> 20;
+                    self.set(scratch, address.wrapping_sub_signed(low));
+                    next = address;
+                }
+                Instruction::Ecall => {
+                    if let Some(code) = self.ecall()? {
+                        return Ok(code);
+                    }
+                }
+            }
+
+            pc = next;
+        }
+    }
+}
+
+/// Returns whether the immediate of the instruction at the address, or the offset to its label,
+/// fits in its field of the machine encoding. `la`, `call` and `jump` expand to `auipc`
+/// and another instruction, reaching the whole address space.
+fn encodable(instruction: &Instruction, address: u32, addresses: &HashMap<Label, u32>) -> bool {
+    let signed = |val: i32, bits: u32| (-(1 << (bits - 1))..1 << (bits - 1)).contains(&val);
+    let offset = |target: &Label| addresses[target].wrapping_sub(address) as i32;
+    match instruction {
+        Instruction::OpImm {
+            op: ImmOp::Slli | ImmOp::Srli | ImmOp::Srai,
+            imm,
+            ..
+        } => (0..32).contains(imm),
+        Instruction::OpImm { imm, .. } => signed(*imm, 12),
+        Instruction::Lui { imm, .. } => *imm < 1 << 20,
+        Instruction::Load { offset, .. }
+        | Instruction::Store { offset, .. }
+        | Instruction::Jalr { offset, .. } => signed(*offset, 12),
+        // The offsets of branches and jumps are even, so their lowest bit is not encoded.
+        Instruction::Branch { target, .. } => signed(offset(target), 13),
+        Instruction::Jal { target, .. } => signed(offset(target), 21),
+        Instruction::Label(_)
+        | Instruction::Op { .. }
+        | Instruction::LoadAddress { .. }
+        | Instruction::Call { .. }
+        | Instruction::Jump { .. }
+        | Instruction::Ecall => true,
+    }
+}
+
+fn reg_op(op: RegOp, lhs: u32, rhs: u32) -> u32 {
+    let (signed_lhs, signed_rhs) = (lhs as i32, rhs as i32);
+    match op {
+        RegOp::Add => lhs.wrapping_add(rhs),
+        RegOp::Sub => lhs.wrapping_sub(rhs),
+        RegOp::Sll => lhs << (rhs & 31),
+        RegOp::Slt => (signed_lhs < signed_rhs).into(),
+        RegOp::Sltu => (lhs < rhs).into(),
+        RegOp::Xor => lhs ^ rhs,
+        RegOp::Srl => lhs >> (rhs & 31),
+        RegOp::Sra => (signed_lhs >> (rhs & 31)) as u32,
+        RegOp::Or => lhs | rhs,
+        RegOp::And => lhs & rhs,
+        RegOp::Mul => lhs.wrapping_mul(rhs),
+        RegOp::Mulh => ((i64::from(signed_lhs) * i64::from(signed_rhs)) >> 32) as u32,
+        RegOp::Mulhsu => ((i64::from(signed_lhs) * i64::from(rhs)) >> 32) as u32,
+        RegOp::Mulhu => ((u64::from(lhs) * u64::from(rhs)) >> 32) as u32,
+        // Divisions never trap: by zero the quotient has all bits set and the remainder
+        // is the dividend, and the overflowing division wraps around.
+        RegOp::Div if rhs == 0 => u32::MAX,
+        RegOp::Div => signed_lhs.wrapping_div(signed_rhs) as u32,
+        RegOp::Divu => lhs.checked_div(rhs).unwrap_or(u32::MAX),
+        RegOp::Rem if rhs == 0 => lhs,
+        RegOp::Rem => signed_lhs.wrapping_rem(signed_rhs) as u32,
+        RegOp::Remu => lhs.checked_rem(rhs).unwrap_or(lhs),
+    }
+}
+
+fn imm_op(op: ImmOp, src: u32, imm: i32) -> u32 {
+    match op {
+        ImmOp::Addi => src.wrapping_add_signed(imm),
+        ImmOp::Slti => ((src as i32) < imm).into(),
+        ImmOp::Sltiu => (src < imm as u32).into(),
+        ImmOp::Xori => src ^ imm as u32,
+        ImmOp::Ori => src | imm as u32,
+        ImmOp::Andi => src & imm as u32,
+        ImmOp::Slli => src << (imm & 31),
+        ImmOp::Srli => src >> (imm & 31),
+        ImmOp::Srai => ((src as i32) >> (imm & 31)) as u32,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        backend::{riscv::RiscVBackend, Backend},
+        ir::Program,
+        opt::{OptLevel, PassManager},
+        parser,
+        testing::{self, Execution},
+        Arithmetic,
+    };
+    use std::fs;
+
+    fn simulate(program: &Program, registers: usize) -> Execution {
+        testing::simulate(
+            &RiscVBackend::default()
+                .with_registers(registers)
+                .process(program),
+        )
+    }
+
+    #[test]
+    fn operations() {
+        assert_eq!(reg_op(RegOp::Div, 7, 0), u32::MAX);
+        assert_eq!(
+            reg_op(RegOp::Div, i32::MIN as u32, -1i32 as u32),
+            i32::MIN as u32
+        );
+        assert_eq!(reg_op(RegOp::Rem, i32::MIN as u32, -1i32 as u32), 0);
+        assert_eq!(reg_op(RegOp::Rem, 7, 0), 7);
+        assert_eq!(reg_op(RegOp::Remu, -7i32 as u32, 10), 9);
+        assert_eq!(reg_op(RegOp::Mulh, -1i32 as u32, 1), u32::MAX);
+        assert_eq!(reg_op(RegOp::Mulhu, u32::MAX, u32::MAX), u32::MAX - 1);
+        assert_eq!(reg_op(RegOp::Sra, 0x8000_0000, 33), 0xc000_0000);
+        assert_eq!(imm_op(ImmOp::Sltiu, 5, -1), 1);
+        assert_eq!(imm_op(ImmOp::Addi, 0, -2048), -2048i32 as u32);
+    }
+
+    #[test]
+    fn examples() {
+        let directory = concat!(env!("CARGO_MANIFEST_DIR"), "/examples");
+        let mut sources = fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "ins"))
+            .collect::<Vec<_>>();
+        sources.sort();
+        assert!(!sources.is_empty());
+
+        for path in sources {
+            let source = fs::read_to_string(&path).unwrap();
+            let expected = fs::read(path.with_extension("output")).unwrap();
+            for arithmetic in [Arithmetic::Wrapping, Arithmetic::Checked] {
+                for level in [OptLevel::O0, OptLevel::O2] {
+                    let stmts = parser::parse(&source, arithmetic).unwrap();
+                    let mut program = Program::lower(&stmts, arithmetic).unwrap();
+                    PassManager::new(level, true)
+                        .run(&mut program, &mut io::sink())
+                        .unwrap();
+
+                    for registers in [1, Register::ALLOCATABLE.len()] {
+                        let execution = simulate(&program, registers);
+                        assert_eq!(execution.stdout, expected, "{}", path.display());
+                        assert_eq!((execution.stderr, execution.code), (Some(Vec::new()), 0));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let sources = [
+            "a = 5; a; a / (a - 5); 1",
+            "a = 2147483647; a - 1; a + 1",
+            "a = 0 - 2147483647 - 1; a / 3; a - 1; 2",
+            "a = 0 - 2147483647 - 1; a; a / (0 - 1); a * a",
+            "a = 65536; b = a - 1; a * b; a * a",
+            "0 - 2147483647 - 1; 1 / 0",
+        ];
+        testing::check(&sources, |program| Some(simulate(program, 2)));
+    }
+
+    #[test]
+    fn faults() {
+        let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+        let assembly = Assembly {
+            instructions: vec![Instruction::Store {
+                width: Width::Word,
+                src: Register::Zero,
+                base: Register::Sp,
+                offset: 0,
+            }],
+            data: Vec::new(),
+        };
+        assert!(matches!(
+            run(&assembly, &mut stdout, &mut stderr),
+            Err(Fault::MemoryAddress(STACK_TOP))
+        ));
+
+        let assembly = Assembly {
+            instructions: vec![Instruction::OpImm {
+                op: ImmOp::Addi,
+                dst: Register::A7,
+                src: Register::Zero,
+                imm: 1,
+            }],
+            data: Vec::new(),
+        };
+        assert!(matches!(
+            run(&assembly, &mut stdout, &mut stderr),
+            Err(Fault::InstructionAddress(address)) if address == TEXT + 4
+        ));
+
+        let assembly = Assembly {
+            instructions: vec![Instruction::Load {
+                width: Width::Word,
+                dst: Register::A0,
+                base: Register::Sp,
+                offset: -2049,
+            }],
+            data: Vec::new(),
+        };
+        assert!(matches!(
+            run(&assembly, &mut stdout, &mut stderr),
+            Err(Fault::Immediate(TEXT))
+        ));
+
+        // The label is 4 KiB after the branch, 2 bytes beyond its reach.
+        let mut instructions = vec![Instruction::Branch {
+            condition: Condition::Equal,
+            lhs: Register::Zero,
+            rhs: Register::Zero,
+            target: Label::Numbered(0),
+        }];
+        instructions.extend([Instruction::Ecall; 1023]);
+        instructions.push(Instruction::Label(Label::Numbered(0)));
+        instructions.push(Instruction::Ecall);
+        let assembly = Assembly {
+            instructions,
+            data: Vec::new(),
+        };
+        assert!(matches!(
+            run(&assembly, &mut stdout, &mut stderr),
+            Err(Fault::Immediate(TEXT))
+        ));
+    }
+}
@@ -0,0 +1,101 @@
+//! Helpers shared by the tests executing compiled programs, with the [Interpreter] as the oracle.
+
+use crate::{
+    backend::riscv::Assembly,
+    interpreter::Interpreter,
+    ir::Program,
+    opt::{Pass, PassManager},
+    parser, simulator, Arithmetic,
+};
+use std::{fmt::Display, io, process::Command};
+
+/// Parses and lowers the source.
+pub fn program(source: &str, arithmetic: Arithmetic) -> Program {
+    let stmts = parser::parse(source, arithmetic).unwrap();
+    Program::lower(&stmts, arithmetic).unwrap()
+}
+
+/// Parses, lowers and simplifies the source. Simplification keeps the variable operands,
+/// while folding would compute the program, and introduces the machine operations.
+pub fn simplified(source: &str, arithmetic: Arithmetic) -> Program {
+    let mut program = program(source, arithmetic);
+    let passes = PassManager {
+        passes: vec![Pass::Simplify],
+        native: true,
+        print_after: None,
+        time_passes: false,
+    };
+    passes.run(&mut program, &mut io::sink()).unwrap();
+    program
+}
+
+/// Observable behavior of an executed program.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Execution {
+    pub stdout: Vec<u8>,
+    /// Message of the runtime error ending the program, followed by a newline.
+    /// [None] if the target reports the errors in its own words.
+    pub stderr: Option<Vec<u8>>,
+    /// 0, or 1 after a runtime error.
+    pub code: i32,
+}
+
+impl Execution {
+    /// Returns the behavior of a program run in this process, from its output and its result.
+    pub fn finished<T, E: Display>(stdout: Vec<u8>, result: &Result<T, E>) -> Self {
+        Self {
+            stdout,
+            stderr: Some(match result {
+                Ok(_) => Vec::new(),
+                Err(e) => format!("{}\n", e).into_bytes(),
+            }),
+            code: i32::from(result.is_err()),
+        }
+    }
+
+    /// Runs the command, returning [None] if it cannot be started.
+    pub fn of(command: &mut Command) -> Option<Self> {
+        let output = command.output().ok()?;
+        Some(Self {
+            stdout: output.stdout,
+            stderr: Some(output.stderr),
+            code: output.status.code().unwrap(),
+        })
+    }
+}
+
+/// Returns the behavior of the source run by the interpreter.
+pub fn interpret(source: &str, arithmetic: Arithmetic) -> Execution {
+    let stmts = parser::parse(source, arithmetic).unwrap();
+    let mut stdout = Vec::new();
+    let result = Interpreter::new(arithmetic).run(&stmts, &mut stdout);
+    Execution::finished(stdout, &result)
+}
+
+/// Runs the RISC-V assembly with the simulator, which must not fault.
+pub fn simulate(assembly: &Assembly) -> Execution {
+    let (mut stdout, mut stderr) = (Vec::new(), Vec::new());
+    let code = simulator::run(assembly, &mut stdout, &mut stderr).unwrap();
+    Execution {
+        stdout,
+        stderr: Some(stderr),
+        code,
+    }
+}
+
+/// Checks that the target runs the simplified sources like the interpreter, in both bounded modes.
+/// `run` returns [None] if a tool needed by the target is not available, which ends the check.
+pub fn check(sources: &[&str], mut run: impl FnMut(&Program) -> Option<Execution>) {
+    for source in sources {
+        for arithmetic in [Arithmetic::Wrapping, Arithmetic::Checked] {
+            let mut expected = interpret(source, arithmetic);
+            let Some(execution) = run(&simplified(source, arithmetic)) else {
+                return;
+            };
+            if execution.stderr.is_none() {
+                expected.stderr = None;
+            }
+            assert_eq!(execution, expected, "{} in {} mode", source, arithmetic);
+        }
+    }
+}
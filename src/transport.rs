@@ -0,0 +1,119 @@
+//! Transport of JSON messages framed with `Content-Length` headers, shared by the Debug Adapter
+//! Protocol and the Language Server Protocol.
+
+use serde_json::Value;
+use std::{
+    fmt::{self, Display, Formatter},
+    io::{self, BufRead, Write},
+};
+
+/// Maximum length of the content of a message, checked before allocating it.
+pub const MAX_LENGTH: usize = 1 << 26;
+
+/// Failure to read a message.
+#[derive(Debug)]
+pub enum ReadError {
+    /// The input failed or the framing is broken, so no further messages can be read.
+    Framing(String),
+    /// The content is not valid JSON. The message was consumed, so the next one can be read.
+    Parse(String),
+}
+
+impl Display for ReadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Framing(e) | Self::Parse(e) => f.write_str(e),
+        }
+    }
+}
+
+/// Reads the next message. Returns [None] at the end of the input.
+pub fn read_message(input: &mut dyn BufRead) -> Result<Option<Value>, ReadError> {
+    let failed = |e: io::Error| ReadError::Framing(format!("failed to read the message: {}", e));
+    let mut length = None;
+    let mut header = String::new();
+    loop {
+        header.clear();
+        let read = input.read_line(&mut header).map_err(failed)?;
+        match (read, header.trim_end()) {
+            (0, _) => return Ok(None),
+            (_, "") if length.is_some() => break,
+            (_, "") => {}
+            (_, header) => {
+                if let Some(value) = header.strip_prefix("Content-Length:") {
+                    let value = value
+                        .trim()
+                        .parse()
+                        .map_err(|_| ReadError::Framing(format!("invalid header {}", header)))?;
+                    length = Some(value);
+                }
+            }
+        }
+    }
+
+    let length = length.expect("the headers end after Content-Length");
+    if length > MAX_LENGTH {
+        return Err(ReadError::Framing(format!(
+            "message of {} bytes exceeds the limit of {} bytes",
+            length, MAX_LENGTH
+        )));
+    }
+    let mut content = vec![0; length];
+    input.read_exact(&mut content).map_err(failed)?;
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(|e| ReadError::Parse(format!("invalid message: {}", e)))
+}
+
+/// Writes the message with its header and flushes `output`.
+pub fn write_message(output: &mut dyn Write, message: &Value) -> Result<(), String> {
+    let content = message.to_string();
+    write!(
+        output,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )
+    .and_then(|_| output.flush())
+    .map_err(|e| format!("failed to write the message: {}", e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn framing() {
+        let mut output = Vec::new();
+        write_message(&mut output, &json!({"seq": 1})).unwrap();
+        write_message(&mut output, &json!([1, "ż"])).unwrap();
+        assert!(output.starts_with(b"Content-Length: 9\r\n\r\n{\"seq\":1}"));
+
+        let mut input = &output[..];
+        assert_eq!(read_message(&mut input).unwrap(), Some(json!({"seq": 1})));
+        assert_eq!(read_message(&mut input).unwrap(), Some(json!([1, "ż"])));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+
+        let mut input = &b"Content-Type: x\r\nContent-Length: 2\r\n\r\n{}"[..];
+        assert_eq!(read_message(&mut input).unwrap(), Some(json!({})));
+        let mut input = &b"Content-Length: 5\r\n\r\n{}"[..];
+        assert!(matches!(
+            read_message(&mut input),
+            Err(ReadError::Framing(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_messages() {
+        let mut input = &b"Content-Length: 5\r\n\r\n{seq}Content-Length: 2\r\n\r\n[]"[..];
+        assert!(matches!(read_message(&mut input), Err(ReadError::Parse(_))));
+        assert_eq!(read_message(&mut input).unwrap(), Some(json!([])));
+
+        let header = format!("Content-Length: {}\r\n\r\n", MAX_LENGTH + 1);
+        assert!(matches!(
+            read_message(&mut header.as_bytes()),
+            Err(ReadError::Framing(_))
+        ));
+    }
+}
@@ -0,0 +1,215 @@
+//! Stack virtual machine executing the [Bytecode].
+
+use crate::{
+    ast::Op,
+    backend::bytecode::{Bytecode, Instruction},
+    interpreter::{self, RuntimeError},
+    ir::MachineOp,
+    Arithmetic,
+};
+use num_bigint::BigInt;
+use std::{fmt::Display, io::Write};
+
+/// Value on the stack of the virtual machine.
+trait Value: Clone + Default + Display {
+    fn constant(constant: &BigInt) -> Self;
+
+    fn bin(
+        arithmetic: Arithmetic,
+        op: Op,
+        lhs: Self,
+        rhs: Self,
+    ) -> Result<Self, RuntimeError<'static>>;
+
+    fn machine(op: MachineOp, lhs: Self, rhs: Self) -> Self;
+}
+
+impl Value for i32 {
+    fn constant(constant: &BigInt) -> Self {
+        interpreter::small(constant)
+    }
+
+    fn bin(
+        arithmetic: Arithmetic,
+        op: Op,
+        lhs: Self,
+        rhs: Self,
+    ) -> Result<Self, RuntimeError<'static>> {
+        interpreter::evaluate_bounded(arithmetic, op, lhs, rhs)
+    }
+
+    fn machine(op: MachineOp, lhs: Self, rhs: Self) -> Self {
+        op.apply(lhs, rhs)
+    }
+}
+
+impl Value for BigInt {
+    fn constant(constant: &BigInt) -> Self {
+        constant.clone()
+    }
+
+    fn bin(
+        arithmetic: Arithmetic,
+        op: Op,
+        lhs: Self,
+        rhs: Self,
+    ) -> Result<Self, RuntimeError<'static>> {
+        interpreter::evaluate(arithmetic, op, &lhs, &rhs)
+    }
+
+    fn machine(_: MachineOp, _: Self, _: Self) -> Self {
+        unreachable!("verified bytecode has no machine operations in unbounded arithmetic")
+    }
+}
+
+/// Executes the bytecode, writing the printed values to `output`.
+/// Values are 32-bit integers in bounded arithmetic modes, so the execution never allocates
+/// after the start.
+pub fn run(bytecode: &Bytecode, output: &mut dyn Write) -> Result<(), RuntimeError<'static>> {
+    match bytecode.arithmetic {
+        Arithmetic::Wrapping | Arithmetic::Checked => execute::<i32>(bytecode, output),
+        Arithmetic::Unbounded => execute::<BigInt>(bytecode, output),
+    }
+}
+
+fn execute<V: Value>(
+    bytecode: &Bytecode,
+    output: &mut dyn Write,
+) -> Result<(), RuntimeError<'static>> {
+    let constants = bytecode
+        .constants
+        .iter()
+        .map(V::constant)
+        .collect::<Vec<_>>();
+    let mut locals = vec![V::default(); bytecode.locals];
+    let mut stack = Vec::with_capacity(bytecode.stack_limit);
+    let pop = |stack: &mut Vec<V>| {
+        stack
+            .pop()
+            .expect("verified bytecode never pops from an empty stack")
+    };
+
+    for instruction in &bytecode.instructions {
+        match *instruction {
+            Instruction::Const(idx) => stack.push(constants[idx].clone()),
+            Instruction::Load(slot) => stack.push(locals[slot].clone()),
+            Instruction::Store(slot) => locals[slot] = pop(&mut stack),
+            Instruction::Bin(op) => {
+                let rhs = pop(&mut stack);
+                let lhs = pop(&mut stack);
+                stack.push(V::bin(bytecode.arithmetic, op, lhs, rhs)?);
+            }
+            Instruction::Machine(op) => {
+                let rhs = pop(&mut stack);
+                let lhs = pop(&mut stack);
+                stack.push(V::machine(op, lhs, rhs));
+            }
+            Instruction::Dup => {
+                let top = pop(&mut stack);
+                stack.push(top.clone());
+                stack.push(top);
+            }
+            Instruction::Swap => {
+                let len = stack.len();
+                stack.swap(len - 1, len - 2);
+            }
+            Instruction::Pop => {
+                pop(&mut stack);
+            }
+            Instruction::Print => {
+                writeln!(output, "{}", pop(&mut stack)).map_err(RuntimeError::Output)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        backend::{bytecode::BytecodeBackend, Backend},
+        interpreter::Interpreter,
+        ir::Program,
+        parser,
+    };
+
+    /// Returns the output and the error of both the virtual machine and the interpreter.
+    fn outputs(source: &str, arithmetic: Arithmetic) -> [(String, Option<String>); 2] {
+        let stmts = parser::parse(source, arithmetic).unwrap();
+        let program = Program::lower(&stmts, arithmetic).unwrap();
+        let mut output = Vec::new();
+        let bytecode = Bytecode::decode(&BytecodeBackend.process(&program).encode()).unwrap();
+        let vm = run(&bytecode, &mut output).err();
+        let vm = (
+            String::from_utf8(output).unwrap(),
+            vm.map(|e| format!("{:?}", e)),
+        );
+
+        let mut output = Vec::new();
+        let interpreter = Interpreter::new(arithmetic).run(&stmts, &mut output).err();
+        let interpreter = (
+            String::from_utf8(output).unwrap(),
+            interpreter.map(|e| format!("{:?}", e)),
+        );
+
+        [vm, interpreter]
+    }
+
+    #[test]
+    fn execution() {
+        let source = "a = 2147483647 + 1; a; b = a / (0 - 1); 7 - b * b / 3; b + 1; 1 / (a - a); 8";
+        for arithmetic in [
+            Arithmetic::Wrapping,
+            Arithmetic::Checked,
+            Arithmetic::Unbounded,
+        ] {
+            let [vm, interpreter] = outputs(source, arithmetic);
+            assert_eq!(vm, interpreter);
+        }
+
+        let [vm, _] = outputs(source, Arithmetic::Wrapping);
+        assert_eq!(
+            vm,
+            (
+                "-2147483648\n7\n-2147483647\n".into(),
+                Some("DivisionByZero".into())
+            )
+        );
+    }
+
+    #[test]
+    fn machine_operations() {
+        // Literals parsed in unbounded mode wrap around to negative values when lowered,
+        // so that the divisors stay constant.
+        let source = (-20..20)
+            .filter(|&divisor| divisor != 0)
+            .map(|divisor| {
+                let divisor = divisor as u32;
+                format!(
+                    "2147483648 / {0}; 123456789 / {0}; {1} * {0}",
+                    divisor, -77i32 as u32
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("; ");
+        let stmts = parser::parse(&source, Arithmetic::Unbounded).unwrap();
+
+        let mut program = Program::lower(&stmts, Arithmetic::Wrapping).unwrap();
+        crate::opt::simplify::simplify(&mut program, true);
+        let bytecode = BytecodeBackend.process(&program);
+        assert!(bytecode
+            .instructions
+            .iter()
+            .any(|instruction| matches!(instruction, Instruction::Machine(MachineOp::MulHigh))));
+
+        let mut output = Vec::new();
+        run(&bytecode, &mut output).unwrap();
+        let mut expected = Vec::new();
+        Interpreter::new(Arithmetic::Wrapping)
+            .run(&stmts, &mut expected)
+            .unwrap();
+        assert_eq!(output, expected);
+    }
+}
@@ -2,20 +2,56 @@
 
 set -e
 
-for f in examples/*.ins
+for options in "" "--bigint" "--precompute" "--bigint --precompute"
 do
-    dir=$(dirname $f)
-    file=$(basename $f .ins)
+    for f in examples/*.ins
+    do
+        dir=$(dirname $f)
+        file=$(basename $f .ins)
 
-    echo Starting $file
+        echo Starting $file $options
 
-    ./insc_jvm $f
-    java -cp "$dir" "$file" > "$dir/$file.result.jvm"
-    diff "$dir/$file.output" "$dir/$file.result.jvm"
+        ./instant run $f $options > "$dir/$file.result.run"
+        diff "$dir/$file.output" "$dir/$file.result.run"
 
-    ./insc_llvm $f
-    lli "$dir/$file.bc" > "$dir/$file.result.llvm"
-    diff "$dir/$file.output" "$dir/$file.result.llvm"
+        ./instant $options --emit bytecode < $f > "$dir/$file.insb"
+        ./instant run "$dir/$file.insb" > "$dir/$file.result.vm"
+        diff "$dir/$file.output" "$dir/$file.result.vm"
 
-    echo $file OK
+        ./insc_jvm $f $options
+        java -cp "$dir" "$file" > "$dir/$file.result.jvm"
+        diff "$dir/$file.output" "$dir/$file.result.jvm"
+
+        ./insc_llvm $f $options
+        lli "$dir/$file.bc" > "$dir/$file.result.llvm"
+        diff "$dir/$file.output" "$dir/$file.result.llvm"
+
+        # The x86-64, WebAssembly, C and RISC-V backends and the JIT support only 32-bit arithmetic.
+        if [[ $options != *--bigint* ]]; then
+            ./insc_x86 $f $options
+            "$dir/$file" > "$dir/$file.result.x86"
+            diff "$dir/$file.output" "$dir/$file.result.x86"
+
+            ./instant $options --emit exe < $f > "$dir/$file.exe"
+            chmod +x "$dir/$file.exe"
+            "$dir/$file.exe" > "$dir/$file.result.exe"
+            diff "$dir/$file.output" "$dir/$file.result.exe"
+
+            ./insc_wasm $f $options
+            node run_wasm.js "$dir/$file.wasm" > "$dir/$file.result.wasm"
+            diff "$dir/$file.output" "$dir/$file.result.wasm"
+
+            ./insc_c $f $options
+            "$dir/$file" > "$dir/$file.result.c"
+            diff "$dir/$file.output" "$dir/$file.result.c"
+
+            ./instant $options run --riscv $f > "$dir/$file.result.riscv"
+            diff "$dir/$file.output" "$dir/$file.result.riscv"
+
+            ./instant $options run --jit $f > "$dir/$file.result.jit"
+            diff "$dir/$file.output" "$dir/$file.result.jit"
+        fi
+
+        echo $file OK
+    done
 done